@@ -1,12 +1,24 @@
-use std::collections::HashMap;
+use crate::span::Span;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
 
 #[derive(Debug, Clone)]
 pub struct Program {
+    /// The `#![effects(...)]` bound on the whole program, if declared.
+    pub effects: Option<EffectDecl>,
     pub statements: Vec<Stmt>,
 }
 
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
     ToolDecl {
         name: String,
         input: Vec<(String, Type)>,
@@ -14,26 +26,200 @@ pub enum Stmt {
         cap: String,
     },
     Let {
+        name: String,
+        /// `let mut`; only mutable bindings may be assigned to.
+        mutable: bool,
+        /// Optional annotation; required for empty collection literals.
+        ty: Option<Type>,
+        expr: Expr,
+    },
+    /// `name = expr;`
+    Assign {
         name: String,
         expr: Expr,
     },
     Print {
         expr: Expr,
     },
-    ToolCall {
-        tool: String,
-        input: Vec<(String, Expr)>,
-        cap: String,
-        timeout_ms: u64,
+    /// `call Tool { ... } using cap timeout N;`; a failed call aborts the run.
+    ToolCall(ToolCall),
+    /// `let name = narrow parent to [Tool, ...];` derives a capability that
+    /// may only call the listed tools.
+    Narrow {
+        name: String,
+        parent: String,
+        tools: Vec<String>,
+    },
+    If {
+        cond: Expr,
+        then_block: Block,
+        /// `else if` chains are an else block holding a single `If`.
+        else_block: Option<Block>,
+    },
+    While {
+        cond: Expr,
+        body: Block,
+    },
+    /// `for var in iter { body }`; maps iterate over their keys.
+    For {
+        var: String,
+        iter: Expr,
+        body: Block,
+    },
+    FnDecl(FnDecl),
+    /// `struct Name { field: Type, ... }`
+    StructDecl {
+        name: String,
+        fields: Vec<(String, Type)>,
+    },
+    /// `enum Name { Variant, Variant(Type, ...), ... }`
+    EnumDecl {
+        name: String,
+        variants: Vec<(String, Vec<Type>)>,
+    },
+    /// `match` in statement position; arms are blocks.
+    Match {
+        scrutinee: Expr,
+        arms: Vec<MatchArm<Block>>,
+    },
+    Return {
+        expr: Option<Expr>,
+    },
+    /// A function call evaluated for its effects.
+    Expr {
+        expr: Expr,
     },
 }
 
+/// `fn name(a: T, ...) -> R !effect[caps] { body }`
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct FnDecl {
+    pub name: String,
+    pub params: Vec<(String, Type)>,
+    /// `Unit` when no return type is written.
+    pub ret: Type,
+    pub effect: EffectDecl,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// Declared upper bound on a function's effects, e.g. `!fs !net`; defaults
+/// to `!pure`.
+#[derive(Debug, Clone)]
+pub struct EffectDecl {
+    pub effect: Effect,
+    /// Capabilities named in `!tool[...]`; empty means any capability.
+    pub caps: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub tool: String,
+    pub input: Vec<(String, Expr)>,
+    pub cap: String,
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm<B> {
+    pub pattern: Pattern,
+    pub body: B,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// `_`
+    Wildcard,
+    Binding(String),
     Int(i64),
     Bool(bool),
     String(String),
+    /// `Enum::Variant` or `Enum::Variant(p, ...)`; `Some(p)`, `None`, `Ok(p)`
+    /// and `Err(p)` use the built-in enum names `Option` and `Result`.
+    Variant {
+        enum_name: String,
+        variant: String,
+        args: Vec<Pattern>,
+    },
+}
+
+/// A braced statement list; introduces a lexical scope.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    /// `()`
+    Unit,
+    /// `"text {expr} text"`; evaluates to a String.
+    Template(Vec<TemplatePart>),
     Var(String),
+    /// `[a, b]`
+    Array(Vec<Expr>),
+    /// `Map { k: v }`
+    Map(Vec<(Expr, Expr)>),
+    /// `Set { a, b }`
+    Set(Vec<Expr>),
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `Name { field: expr, ... }`
+    Struct {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+    /// `Enum::Variant` or `Enum::Variant(args)`; also `Some(x)`, `None`,
+    /// `Ok(x)` and `Err(e)`, see [`PatternKind::Variant`].
+    Variant {
+        enum_name: String,
+        variant: String,
+        args: Vec<Expr>,
+    },
+    Field {
+        expr: Box<Expr>,
+        field: String,
+    },
+    /// `match` in expression position; arms are expressions.
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm<Expr>>,
+    },
+    /// `call Tool { ... } using cap timeout N`; the tool's output as a record.
+    ToolCall(ToolCall),
+    /// `try call ...`; a `Result` of the output record or a `ToolError`.
+    TryCall(ToolCall),
+    /// `expr?`: unwraps `Some`/`Ok`, or returns `None`/`Err` from the function.
+    Propagate(Box<Expr>),
+    Call {
+        callee: String,
+        args: Vec<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
     Binary {
         lhs: Box<Expr>,
         op: BinOp,
@@ -41,27 +227,296 @@ pub enum Expr {
     },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    Lit(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+
+    pub fn is_arithmetic(self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div)
+    }
+
+    /// Applies an arithmetic operator to Ints; `None` on overflow, division
+    /// by zero, or a non-arithmetic operator.
+    pub fn checked_int(self, l: i64, r: i64) -> Option<i64> {
+        match self {
+            BinOp::Add => l.checked_add(r),
+            BinOp::Sub => l.checked_sub(r),
+            BinOp::Mul => l.checked_mul(r),
+            BinOp::Div => l.checked_div(r),
+            _ => None,
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     Int,
+    Float,
     Bool,
     String,
+    Bytes,
+    Unit,
+    Array(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Set(Box<Type>),
+    /// `Option[T]`.
+    Option(Box<Type>),
+    /// `Result[T, E]`.
+    Result(Box<Type>, Box<Type>),
+    /// `{ field: Type, ... }`, the output of a tool call; fields sorted by name.
+    Record(Vec<(String, Type)>),
+    /// A user-declared struct or enum.
+    Named(String),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Effect {
-    Pure,
+/// Enums the language provides; their variants are written without a prefix.
+pub const BUILTIN_ENUMS: [&str; 2] = ["Option", "Result"];
+
+/// `ToolError { tool: String, message: String }`, the error type of `try call`.
+pub const TOOL_ERROR: &str = "ToolError";
+
+impl Type {
+    /// Types usable as map keys and set elements; they have a total order.
+    pub fn is_key(&self) -> bool {
+        matches!(self, Type::Int | Type::Bool | Type::String | Type::Bytes)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Bool => write!(f, "Bool"),
+            Type::String => write!(f, "String"),
+            Type::Bytes => write!(f, "Bytes"),
+            Type::Unit => write!(f, "Unit"),
+            Type::Array(elem) => write!(f, "Array[{elem}]"),
+            Type::Map(key, value) => write!(f, "Map[{key}, {value}]"),
+            Type::Set(elem) => write!(f, "Set[{elem}]"),
+            Type::Option(inner) => write!(f, "Option[{inner}]"),
+            Type::Result(ok, err) => write!(f, "Result[{ok}, {err}]"),
+            Type::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{name}: {ty}"))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Type::Named(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// One kind of observable effect. `pure` is the absence of all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EffectKind {
+    Time,
+    Random,
+    Fs,
+    Net,
     Tool,
 }
 
+impl EffectKind {
+    pub const ALL: [EffectKind; 5] = [
+        EffectKind::Time,
+        EffectKind::Random,
+        EffectKind::Fs,
+        EffectKind::Net,
+        EffectKind::Tool,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectKind::Time => "time",
+            EffectKind::Random => "random",
+            EffectKind::Fs => "fs",
+            EffectKind::Net => "net",
+            EffectKind::Tool => "tool",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// The kind of capability a program must be granted to perform this effect.
+    pub fn capability(self) -> CapabilityKind {
+        match self {
+            EffectKind::Time => CapabilityKind::Time,
+            EffectKind::Random => CapabilityKind::Random,
+            EffectKind::Fs => CapabilityKind::Fs,
+            EffectKind::Net => CapabilityKind::Net,
+            EffectKind::Tool => CapabilityKind::Tool,
+        }
+    }
+}
+
+/// A set of effect kinds, ordered by inclusion: `pure < {time, random, fs,
+/// net, tool}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Effect(u8);
+
+impl Effect {
+    pub const PURE: Effect = Effect(0);
+    pub const TIME: Effect = Effect::of(EffectKind::Time);
+    pub const RANDOM: Effect = Effect::of(EffectKind::Random);
+    pub const FS: Effect = Effect::of(EffectKind::Fs);
+    pub const NET: Effect = Effect::of(EffectKind::Net);
+    pub const TOOL: Effect = Effect::of(EffectKind::Tool);
+
+    pub const fn of(kind: EffectKind) -> Effect {
+        Effect(1 << kind as u8)
+    }
+
+    pub fn is_pure(self) -> bool {
+        self == Effect::PURE
+    }
+
+    pub fn contains(self, kind: EffectKind) -> bool {
+        self.0 & Effect::of(kind).0 != 0
+    }
+
+    pub fn kinds(self) -> impl Iterator<Item = EffectKind> {
+        EffectKind::ALL
+            .into_iter()
+            .filter(move |kind| self.contains(*kind))
+    }
+
+    /// Whether `self` is within the upper bound `bound`.
+    pub fn within(self, bound: Effect) -> bool {
+        self.join(bound) == bound
+    }
+
+    /// Least upper bound in the effect lattice.
+    pub fn join(self, other: Effect) -> Effect {
+        Effect(self.0 | other.0)
+    }
+
+    /// The kinds in `self` that are not in `other`.
+    pub fn without(self, other: Effect) -> Effect {
+        Effect(self.0 & !other.0)
+    }
+
+    /// Annotation syntax: `!pure`, or `!fs !net` for several kinds.
+    pub fn annotation(self) -> String {
+        if self.is_pure() {
+            return "!pure".to_string();
+        }
+        self.kinds()
+            .map(|kind| format!("!{}", kind.name()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_pure() {
+            return write!(f, "pure");
+        }
+        let kinds = self.kinds().map(EffectKind::name).collect::<Vec<_>>();
+        write!(f, "{}", kinds.join(", "))
+    }
+}
+
+/// What a runtime capability grants. Every effect kind needs a capability of
+/// the matching kind; `Memory` guards agent memory stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CapabilityKind {
+    Tool,
+    Net,
+    Fs,
+    Time,
+    Random,
+    Memory,
+}
+
+impl CapabilityKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CapabilityKind::Tool => "tool",
+            CapabilityKind::Net => "net",
+            CapabilityKind::Fs => "fs",
+            CapabilityKind::Time => "time",
+            CapabilityKind::Random => "random",
+            CapabilityKind::Memory => "memory",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            CapabilityKind::Tool,
+            CapabilityKind::Net,
+            CapabilityKind::Fs,
+            CapabilityKind::Time,
+            CapabilityKind::Random,
+            CapabilityKind::Memory,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ToolSignature {
     pub input: HashMap<String, Type>,
@@ -71,5 +526,8 @@ pub struct ToolSignature {
 
 #[derive(Debug, Clone)]
 pub struct CapabilityManifest {
+    /// Capabilities named by tool calls.
     pub required_caps: Vec<String>,
+    /// Kinds of capability the program's effects need, in `EffectKind` order.
+    pub required_kinds: Vec<CapabilityKind>,
 }
@@ -0,0 +1,231 @@
+use crate::span::{SourceMap, Span};
+use std::fmt::{Display, Formatter};
+
+/// Stable diagnostic codes. `AX01xx` are syntax errors, `AX02xx` type errors,
+/// `AX03xx` effect errors, `AX04xx` warnings.
+pub mod codes {
+    pub const UNEXPECTED_CHARACTER: &str = "AX0101";
+    pub const UNEXPECTED_TOKEN: &str = "AX0102";
+    pub const UNTERMINATED_LITERAL: &str = "AX0103";
+    pub const INVALID_LITERAL: &str = "AX0104";
+
+    pub const UNKNOWN_VARIABLE: &str = "AX0201";
+    pub const TYPE_MISMATCH: &str = "AX0202";
+    pub const UNKNOWN_TOOL: &str = "AX0203";
+    pub const TOOL_CAP_MISMATCH: &str = "AX0204";
+    pub const MISSING_TOOL_FIELD: &str = "AX0205";
+    pub const UNKNOWN_FUNCTION: &str = "AX0206";
+    pub const ARITY_MISMATCH: &str = "AX0207";
+    pub const INVALID_RETURN: &str = "AX0208";
+    pub const MISSING_RETURN: &str = "AX0209";
+    pub const DUPLICATE_DECLARATION: &str = "AX0210";
+    pub const CANNOT_INFER: &str = "AX0211";
+    pub const INVALID_KEY_TYPE: &str = "AX0212";
+    pub const UNKNOWN_FIELD: &str = "AX0213";
+    pub const MISSING_FIELD: &str = "AX0214";
+    pub const UNKNOWN_VARIANT: &str = "AX0215";
+    pub const NON_EXHAUSTIVE_MATCH: &str = "AX0216";
+    pub const IMMUTABLE_ASSIGNMENT: &str = "AX0217";
+    pub const USE_BEFORE_DEFINE: &str = "AX0218";
+    pub const DIVISION_BY_ZERO: &str = "AX0219";
+    pub const CONSTANT_OVERFLOW: &str = "AX0220";
+    pub const UNKNOWN_TYPE: &str = "AX0221";
+
+    pub const EFFECT_NOT_ALLOWED: &str = "AX0301";
+    pub const CAP_NOT_DECLARED: &str = "AX0302";
+    pub const CAP_OUT_OF_SCOPE: &str = "AX0303";
+
+    pub const UNUSED_TOOL: &str = "AX0401";
+    pub const UNUSED_VARIABLE: &str = "AX0402";
+    pub const UNUSED_MUT: &str = "AX0403";
+    pub const SHADOWED_VARIABLE: &str = "AX0404";
+    pub const ZERO_TIMEOUT: &str = "AX0405";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    /// Renders a rustc-style report with source snippets and caret underlines.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = format!("{self}\n");
+        let mut labels = self.labels.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|l| (l.span.file.0, l.span.start, !l.primary));
+        let gutter = labels
+            .iter()
+            .map(|l| sources.location(l.span).line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        if let Some(span) = self.primary_span().or(labels.first().map(|l| l.span)) {
+            out.push_str(&format!("{pad}--> {}\n", sources.location(span)));
+            out.push_str(&format!("{pad} |\n"));
+        }
+        let mut last_line = None;
+        for label in &labels {
+            let loc = sources.location(label.span);
+            let text = line_text(sources.source(label.span.file), label.span.start);
+            if last_line != Some((label.span.file, loc.line)) {
+                out.push_str(&format!("{:>gutter$} | {text}\n", loc.line));
+                last_line = Some((label.span.file, loc.line));
+            }
+            let (_, end_col) = sources.line_col(label.span.file, label.span.end);
+            let line_len = text.chars().count() + 1;
+            let width = if label.span.end > label.span.start {
+                end_col.min(line_len).saturating_sub(loc.col).max(1)
+            } else {
+                1
+            };
+            let marker = if label.primary { "^" } else { "-" };
+            let underline = format!("{}{}", " ".repeat(loc.col - 1), marker.repeat(width));
+            if label.message.is_empty() {
+                out.push_str(&format!("{pad} | {underline}\n"));
+            } else {
+                out.push_str(&format!("{pad} | {underline} {}\n", label.message));
+            }
+        }
+        if !self.notes.is_empty() || self.help.is_some() {
+            out.push_str(&format!("{pad} |\n"));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{pad} = note: {note}\n"));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{pad} = help: {help}\n"));
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+fn line_text(src: &str, offset: usize) -> &str {
+    let offset = offset.min(src.len());
+    let start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = src[offset..].find('\n').map_or(src.len(), |i| offset + i);
+    src[start..end].trim_end_matches('\r')
+}
+
+/// Accumulates diagnostics so a pass can recover and keep reporting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.items.extend(other.items);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.items.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<Diagnostic> {
+        self.items
+    }
+}
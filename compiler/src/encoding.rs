@@ -0,0 +1,68 @@
+//! Hex and base64 codecs for `Bytes` literals and their JSON encoding.
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err("hex literal must have an even number of digits".to_string());
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digits = String::from_utf8_lossy(pair);
+            if !pair.iter().all(u8::is_ascii_hexdigit) {
+                return Err(format!("invalid hex digits `{digits}`"));
+            }
+            u8::from_str_radix(&digits, 16).map_err(|_| format!("invalid hex digits `{digits}`"))
+        })
+        .collect()
+}
+
+/// Standard alphabet with `=` padding.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn decode_base64(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(4) {
+        return Err("base64 literal length must be a multiple of 4".to_string());
+    }
+    let data = s.trim_end_matches('=');
+    if s.len() - data.len() > 2 {
+        return Err("base64 literal has too much padding".to_string());
+    }
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in data.chars() {
+        let value = BASE64
+            .iter()
+            .position(|b| *b as char == c)
+            .ok_or_else(|| format!("invalid base64 character `{c}`"))?;
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
@@ -1,16 +1,58 @@
+use crate::{
+    diagnostic::{Diagnostic, Diagnostics},
+    span::{SourceMap, Span},
+};
 use std::fmt::{Display, Formatter};
 
+/// A failed compilation: every diagnostic collected before giving up, errors and warnings alike.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum CompileError {
-    Parse(String),
-    Type(String),
+pub struct CompileError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompileError {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    /// Primary span of the first error.
+    pub fn span(&self) -> Option<Span> {
+        self.errors().find_map(Diagnostic::primary_span)
+    }
+
+    pub fn render(&self, sources: &SourceMap) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.render(sources))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl From<Diagnostic> for CompileError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self {
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+impl From<Diagnostics> for CompileError {
+    fn from(diagnostics: Diagnostics) -> Self {
+        Self {
+            diagnostics: diagnostics.into_vec(),
+        }
+    }
 }
 
 impl Display for CompileError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CompileError::Parse(msg) => write!(f, "parse error: {msg}"),
-            CompileError::Type(msg) => write!(f, "type error: {msg}"),
+        for (idx, d) in self.diagnostics.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{d}")?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,157 @@
+//! Match exhaustiveness via pattern-matrix usefulness: a match is exhaustive
+//! when no value of the scrutinee type escapes every arm.
+
+use crate::{
+    ast::{Pattern, PatternKind, Type, BUILTIN_ENUMS},
+    span::Span,
+};
+use std::collections::HashMap;
+
+/// Variants of each declared enum, with their payload types.
+pub(crate) type Enums = HashMap<String, Vec<(String, Vec<Type>)>>;
+
+/// Returns an example pattern that none of `arms` matches, if there is one.
+pub(crate) fn missing_pattern(arms: &[&Pattern], ty: &Type, enums: &Enums) -> Option<String> {
+    let wildcard = Pattern {
+        kind: PatternKind::Wildcard,
+        span: Span::default(),
+    };
+    let rows = arms.iter().map(|p| vec![*p]).collect();
+    let mut witness = missing(rows, std::slice::from_ref(ty), enums, &wildcard)?;
+    Some(witness.remove(0))
+}
+
+/// A constructor of a finitely-enumerable type: a Bool literal (with an empty
+/// enum name) or an enum variant.
+struct Ctor {
+    enum_name: String,
+    variant: String,
+    fields: Vec<Type>,
+}
+
+impl Ctor {
+    fn new(enum_name: &str, variant: &str, fields: Vec<Type>) -> Self {
+        Ctor {
+            enum_name: enum_name.to_string(),
+            variant: variant.to_string(),
+            fields,
+        }
+    }
+
+    fn matches(&self, pattern: &PatternKind) -> bool {
+        match pattern {
+            PatternKind::Bool(b) => self.enum_name.is_empty() && self.variant == b.to_string(),
+            PatternKind::Variant {
+                enum_name, variant, ..
+            } => self.enum_name == *enum_name && self.variant == *variant,
+            _ => false,
+        }
+    }
+
+    fn render(&self, args: Vec<String>) -> String {
+        let name = if self.enum_name.is_empty() || BUILTIN_ENUMS.contains(&&*self.enum_name) {
+            self.variant.clone()
+        } else {
+            format!("{}::{}", self.enum_name, self.variant)
+        };
+        if args.is_empty() {
+            name
+        } else {
+            format!("{name}({})", args.join(", "))
+        }
+    }
+}
+
+fn constructors(ty: &Type, enums: &Enums) -> Option<Vec<Ctor>> {
+    match ty {
+        Type::Bool => Some(vec![
+            Ctor::new("", "true", Vec::new()),
+            Ctor::new("", "false", Vec::new()),
+        ]),
+        Type::Option(inner) => Some(vec![
+            Ctor::new("Option", "Some", vec![(**inner).clone()]),
+            Ctor::new("Option", "None", Vec::new()),
+        ]),
+        Type::Result(ok, err) => Some(vec![
+            Ctor::new("Result", "Ok", vec![(**ok).clone()]),
+            Ctor::new("Result", "Err", vec![(**err).clone()]),
+        ]),
+        Type::Named(name) => enums.get(name).map(|variants| {
+            variants
+                .iter()
+                .map(|(variant, fields)| Ctor::new(name, variant, fields.clone()))
+                .collect()
+        }),
+        _ => None,
+    }
+}
+
+fn is_wildcard(pattern: &Pattern) -> bool {
+    matches!(
+        pattern.kind,
+        PatternKind::Wildcard | PatternKind::Binding(_)
+    )
+}
+
+/// Finds a row of values, one per entry in `tys`, matched by no row of patterns.
+fn missing<'p>(
+    rows: Vec<Vec<&'p Pattern>>,
+    tys: &[Type],
+    enums: &Enums,
+    wildcard: &'p Pattern,
+) -> Option<Vec<String>> {
+    let Some((ty, rest_tys)) = tys.split_first() else {
+        return rows.is_empty().then(Vec::new);
+    };
+    let ctors = constructors(ty, enums).unwrap_or_default();
+    let (used, unused): (Vec<Ctor>, Vec<Ctor>) = ctors
+        .into_iter()
+        .partition(|ctor| rows.iter().any(|row| ctor.matches(&row[0].kind)));
+    if used.is_empty() || !unused.is_empty() {
+        // Some constructor (or, for infinite and opaque types, every value)
+        // is only matched by catch-all patterns, so only the rows starting
+        // with one matter. Specialising on unused constructors instead would
+        // never end for recursive enums.
+        let rows = rows
+            .into_iter()
+            .filter(|row| is_wildcard(row[0]))
+            .map(|row| row[1..].to_vec())
+            .collect();
+        let mut witness = missing(rows, rest_tys, enums, wildcard)?;
+        let head = match unused.first() {
+            Some(ctor) if !used.is_empty() => ctor.render(vec!["_".to_string(); ctor.fields.len()]),
+            _ => "_".to_string(),
+        };
+        witness.insert(0, head);
+        return Some(witness);
+    }
+    for ctor in used {
+        let specialized = rows
+            .iter()
+            .filter_map(|row| {
+                let args = match &row[0].kind {
+                    _ if is_wildcard(row[0]) => vec![wildcard; ctor.fields.len()],
+                    kind if ctor.matches(kind) => match kind {
+                        PatternKind::Variant { args, .. } => args.iter().collect(),
+                        _ => Vec::new(),
+                    },
+                    _ => return None,
+                };
+                Some(args.into_iter().chain(row[1..].iter().copied()).collect())
+            })
+            .collect();
+        let tys = ctor
+            .fields
+            .iter()
+            .chain(rest_tys)
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(mut witness) = missing(specialized, &tys, enums, wildcard) {
+            let rest = witness.split_off(ctor.fields.len());
+            let mut out = vec![ctor.render(witness)];
+            out.extend(rest);
+            return Some(out);
+        }
+    }
+    None
+}
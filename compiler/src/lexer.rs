@@ -0,0 +1,547 @@
+use crate::{
+    diagnostic::{codes, Diagnostic, Diagnostics},
+    encoding::{decode_base64, decode_hex},
+    error::CompileError,
+    span::{FileId, Span},
+};
+
+/// The largest integer literal the lexer accepts: the magnitude of `i64::MIN`.
+const MAX_INT_MAGNITUDE: u64 = i64::MAX as u64 + 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    /// An unsigned integer literal; the parser range-checks it after folding
+    /// in any leading `-`, so that `-9223372036854775808` is accepted.
+    Int(u64),
+    Float(f64),
+    Str(String),
+    /// `hex"..."` or `base64"..."`.
+    Bytes(Vec<u8>),
+    /// A string literal containing `{expr}` interpolations.
+    Template(Vec<TemplateToken>),
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Colon,
+    Comma,
+    Semi,
+    Eq,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Bang,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    AndAnd,
+    OrOr,
+    Arrow,
+    /// `=>` in match arms.
+    FatArrow,
+    ColonColon,
+    Dot,
+    Question,
+    /// `#`, which starts a `#![...]` attribute.
+    Hash,
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateToken {
+    Lit(String),
+    /// Tokens of an embedded expression, terminated by `Eof`.
+    Expr(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl TokenKind {
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::Ident(s) => format!("`{s}`"),
+            TokenKind::Int(v) => format!("integer `{v}`"),
+            TokenKind::Float(v) => format!("float `{v:?}`"),
+            TokenKind::Str(_) | TokenKind::Template(_) => "string literal".to_string(),
+            TokenKind::Bytes(_) => "bytes literal".to_string(),
+            TokenKind::LBrace => "`{`".to_string(),
+            TokenKind::RBrace => "`}`".to_string(),
+            TokenKind::LParen => "`(`".to_string(),
+            TokenKind::RParen => "`)`".to_string(),
+            TokenKind::LBracket => "`[`".to_string(),
+            TokenKind::RBracket => "`]`".to_string(),
+            TokenKind::Colon => "`:`".to_string(),
+            TokenKind::Comma => "`,`".to_string(),
+            TokenKind::Semi => "`;`".to_string(),
+            TokenKind::Eq => "`=`".to_string(),
+            TokenKind::Plus => "`+`".to_string(),
+            TokenKind::Minus => "`-`".to_string(),
+            TokenKind::Star => "`*`".to_string(),
+            TokenKind::Slash => "`/`".to_string(),
+            TokenKind::Bang => "`!`".to_string(),
+            TokenKind::EqEq => "`==`".to_string(),
+            TokenKind::NotEq => "`!=`".to_string(),
+            TokenKind::Lt => "`<`".to_string(),
+            TokenKind::Le => "`<=`".to_string(),
+            TokenKind::Gt => "`>`".to_string(),
+            TokenKind::Ge => "`>=`".to_string(),
+            TokenKind::AndAnd => "`&&`".to_string(),
+            TokenKind::OrOr => "`||`".to_string(),
+            TokenKind::Arrow => "`->`".to_string(),
+            TokenKind::FatArrow => "`=>`".to_string(),
+            TokenKind::ColonColon => "`::`".to_string(),
+            TokenKind::Dot => "`.`".to_string(),
+            TokenKind::Question => "`?`".to_string(),
+            TokenKind::Hash => "`#`".to_string(),
+            TokenKind::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+pub fn lex(file: FileId, src: &str) -> Result<Vec<Token>, CompileError> {
+    let (tokens, diags) = lex_recovering(file, src);
+    if diags.has_errors() {
+        Err(diags.into())
+    } else {
+        Ok(tokens)
+    }
+}
+
+/// Lexes the whole file, reporting malformed input and skipping past it.
+pub fn lex_recovering(file: FileId, src: &str) -> (Vec<Token>, Diagnostics) {
+    let mut lexer = Lexer {
+        file,
+        src,
+        pos: 0,
+        end: src.len(),
+        line: 1,
+        col: 1,
+        diags: Diagnostics::default(),
+    };
+    let tokens = lexer.tokens();
+    (tokens, lexer.diags)
+}
+
+/// Lexes `src[pos..end]`; `end` is narrower than the file for interpolated expressions.
+struct Lexer<'a> {
+    file: FileId,
+    src: &'a str,
+    pos: usize,
+    end: usize,
+    line: usize,
+    col: usize,
+    diags: Diagnostics,
+}
+
+impl Lexer<'_> {
+    fn tokens(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        loop {
+            self.skip_trivia();
+            let Some(token) = self.next_token() else {
+                continue;
+            };
+            let done = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if done {
+                return tokens;
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..self.end].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.src[self.pos..self.end].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn report(&mut self, start: usize, code: &'static str, msg: &str, label: &str) {
+        let span = Span::new(self.file, start, self.pos.max(start + 1));
+        self.diags
+            .push(Diagnostic::error(code, msg).with_primary(span, label));
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while let Some(c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.pos;
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match (self.peek(), self.peek_second()) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                self.bump();
+                                break;
+                            }
+                            (Some(_), _) => {
+                                self.bump();
+                            }
+                            (None, _) => {
+                                self.report(
+                                    start,
+                                    codes::UNTERMINATED_LITERAL,
+                                    "unterminated block comment",
+                                    "comment starts here",
+                                );
+                                return;
+                            }
+                        }
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let (start, line, col) = (self.pos, self.line, self.col);
+        let Some(c) = self.peek() else {
+            return Some(Token {
+                kind: TokenKind::Eof,
+                span: Span::new(self.file, start, start),
+                line,
+                col,
+            });
+        };
+        let kind = if c == 'r' && self.peek_second() == Some('"') {
+            self.bump();
+            self.lex_raw_string(start)
+        } else if c == '_' || c.is_alphabetic() {
+            while matches!(self.peek(), Some(c) if c == '_' || c.is_alphanumeric()) {
+                self.bump();
+            }
+            let ident = &self.src[start..self.pos];
+            if matches!(ident, "hex" | "base64") && self.peek() == Some('"') {
+                self.lex_bytes(start, ident == "hex")
+            } else {
+                TokenKind::Ident(ident.to_string())
+            }
+        } else if c.is_ascii_digit() {
+            self.skip_digits();
+            if self.peek() == Some('.')
+                && matches!(self.peek_second(), Some(c) if c.is_ascii_digit())
+            {
+                return Some(self.lex_float(start, line, col));
+            }
+            let digits = self.src[start..self.pos].replace('_', "");
+            let value = digits
+                .parse::<u64>()
+                .ok()
+                .filter(|v| *v <= MAX_INT_MAGNITUDE)
+                .unwrap_or_else(|| {
+                    self.report(
+                        start,
+                        codes::INVALID_LITERAL,
+                        "integer literal out of range",
+                        "does not fit in a 64-bit signed integer",
+                    );
+                    0
+                });
+            TokenKind::Int(value)
+        } else if c == '"' {
+            self.lex_string(start)
+        } else {
+            self.bump();
+            if let Some(kind) = self.two_char_op(c) {
+                kind
+            } else {
+                match c {
+                    '{' => TokenKind::LBrace,
+                    '}' => TokenKind::RBrace,
+                    '(' => TokenKind::LParen,
+                    ')' => TokenKind::RParen,
+                    '[' => TokenKind::LBracket,
+                    ']' => TokenKind::RBracket,
+                    ':' => TokenKind::Colon,
+                    '.' => TokenKind::Dot,
+                    '?' => TokenKind::Question,
+                    '#' => TokenKind::Hash,
+                    ',' => TokenKind::Comma,
+                    ';' => TokenKind::Semi,
+                    '=' => TokenKind::Eq,
+                    '+' => TokenKind::Plus,
+                    '-' => TokenKind::Minus,
+                    '*' => TokenKind::Star,
+                    '/' => TokenKind::Slash,
+                    '!' => TokenKind::Bang,
+                    '<' => TokenKind::Lt,
+                    '>' => TokenKind::Gt,
+                    other => {
+                        self.report(
+                            start,
+                            codes::UNEXPECTED_CHARACTER,
+                            &format!("unexpected character `{other}`"),
+                            "not valid in Axiom source",
+                        );
+                        return None;
+                    }
+                }
+            }
+        };
+        Some(Token {
+            kind,
+            span: Span::new(self.file, start, self.pos),
+            line,
+            col,
+        })
+    }
+
+    fn two_char_op(&mut self, first: char) -> Option<TokenKind> {
+        let kind = match (first, self.peek()?) {
+            ('=', '=') => TokenKind::EqEq,
+            ('!', '=') => TokenKind::NotEq,
+            ('<', '=') => TokenKind::Le,
+            ('>', '=') => TokenKind::Ge,
+            ('&', '&') => TokenKind::AndAnd,
+            ('|', '|') => TokenKind::OrOr,
+            ('-', '>') => TokenKind::Arrow,
+            ('=', '>') => TokenKind::FatArrow,
+            (':', ':') => TokenKind::ColonColon,
+            _ => return None,
+        };
+        self.bump();
+        Some(kind)
+    }
+
+    fn lex_string(&mut self, start: usize) -> TokenKind {
+        self.bump();
+        let mut parts = Vec::new();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => {
+                    let esc_start = self.pos - 1;
+                    match self.bump() {
+                        Some('n') => out.push('\n'),
+                        Some('t') => out.push('\t'),
+                        Some('r') => out.push('\r'),
+                        Some('0') => out.push('\0'),
+                        Some(c @ ('\\' | '"' | '{' | '}')) => out.push(c),
+                        Some(other) => self.report(
+                            esc_start,
+                            codes::INVALID_LITERAL,
+                            &format!("unknown escape sequence `\\{other}`"),
+                            "unknown escape",
+                        ),
+                        None => return self.unterminated_string(start),
+                    }
+                }
+                Some('{') => {
+                    let open = self.pos - 1;
+                    let Some(close) = self.interpolation_end() else {
+                        self.report(
+                            open,
+                            codes::UNTERMINATED_LITERAL,
+                            "unterminated interpolation",
+                            "`{` is never closed; write `\\{` for a literal brace",
+                        );
+                        return self.unterminated_string(start);
+                    };
+                    let mut inner = Lexer {
+                        file: self.file,
+                        src: self.src,
+                        pos: self.pos,
+                        end: close,
+                        line: self.line,
+                        col: self.col,
+                        diags: Diagnostics::default(),
+                    };
+                    let tokens = inner.tokens();
+                    self.diags.extend(inner.diags);
+                    while self.pos < close {
+                        self.bump();
+                    }
+                    self.bump();
+                    if tokens.len() == 1 {
+                        self.report(
+                            open,
+                            codes::INVALID_LITERAL,
+                            "empty interpolation",
+                            "expected an expression inside `{}`",
+                        );
+                        continue;
+                    }
+                    if !out.is_empty() {
+                        parts.push(TemplateToken::Lit(std::mem::take(&mut out)));
+                    }
+                    parts.push(TemplateToken::Expr(tokens));
+                }
+                Some(c) => out.push(c),
+                None => return self.unterminated_string(start),
+            }
+        }
+        if parts.is_empty() {
+            return TokenKind::Str(out);
+        }
+        if !out.is_empty() {
+            parts.push(TemplateToken::Lit(out));
+        }
+        TokenKind::Template(parts)
+    }
+
+    /// Byte offset of the `}` closing an interpolation that starts at the
+    /// current position, skipping nested braces and string literals.
+    fn interpolation_end(&self) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        for (idx, c) in self.src[self.pos..self.end].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '\n' if !in_string => return None,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string && depth == 0 => return Some(self.pos + idx),
+                '}' if !in_string => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn unterminated_string(&mut self, start: usize) -> TokenKind {
+        self.report(
+            start,
+            codes::UNTERMINATED_LITERAL,
+            "unterminated string literal",
+            "string starts here",
+        );
+        TokenKind::Str(String::new())
+    }
+
+    fn skip_digits(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '_') {
+            self.bump();
+        }
+    }
+
+    /// Lexes the fraction and optional exponent after the integer digits.
+    fn lex_float(&mut self, start: usize, line: usize, col: usize) -> Token {
+        self.bump();
+        self.skip_digits();
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let mut exponent = self.src[self.pos + 1..self.end].chars();
+            let next = match exponent.next() {
+                Some('+' | '-') => exponent.next(),
+                other => other,
+            };
+            if matches!(next, Some(c) if c.is_ascii_digit()) {
+                self.bump();
+                if matches!(self.peek(), Some('+' | '-')) {
+                    self.bump();
+                }
+                self.skip_digits();
+            }
+        }
+        let digits = self.src[start..self.pos].replace('_', "");
+        let value = match digits.parse::<f64>() {
+            Ok(v) if v.is_finite() => v,
+            _ => {
+                self.report(
+                    start,
+                    codes::INVALID_LITERAL,
+                    "float literal out of range",
+                    "does not fit in a 64-bit float",
+                );
+                0.0
+            }
+        };
+        Token {
+            kind: TokenKind::Float(value),
+            span: Span::new(self.file, start, self.pos),
+            line,
+            col,
+        }
+    }
+
+    /// `hex"..."` / `base64"..."`; the prefix has already been consumed.
+    fn lex_bytes(&mut self, token_start: usize, hex: bool) -> TokenKind {
+        self.bump();
+        let start = self.pos;
+        while !matches!(self.peek(), Some('"') | None) {
+            self.bump();
+        }
+        if self.peek().is_none() {
+            self.unterminated_string(token_start);
+            return TokenKind::Bytes(Vec::new());
+        }
+        let text = &self.src[start..self.pos];
+        self.bump();
+        let decoded = if hex {
+            decode_hex(text)
+        } else {
+            decode_base64(text)
+        };
+        TokenKind::Bytes(decoded.unwrap_or_else(|msg| {
+            self.report(
+                token_start,
+                codes::INVALID_LITERAL,
+                &msg,
+                "invalid bytes literal",
+            );
+            Vec::new()
+        }))
+    }
+
+    fn lex_raw_string(&mut self, token_start: usize) -> TokenKind {
+        self.bump();
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    let value = self.src[start..self.pos].to_string();
+                    self.bump();
+                    return TokenKind::Str(value);
+                }
+                Some(_) => {
+                    self.bump();
+                }
+                None => return self.unterminated_string(token_start),
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
 pub mod ast;
+pub mod diagnostic;
+pub mod encoding;
 pub mod error;
+mod exhaustiveness;
+pub mod lexer;
 pub mod manifest;
 pub mod parser;
+pub mod span;
 pub mod typecheck;
 
 pub use ast::*;
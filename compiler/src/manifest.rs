@@ -3,6 +3,18 @@ use crate::typecheck::TypedProgram;
 pub fn render_manifest(typed: &TypedProgram) -> String {
     let mut out = String::new();
     out.push_str("capability_manifest_v1\n");
+    out.push_str(&format!(
+        "effects={}\n",
+        typed
+            .effect
+            .kinds()
+            .map(|k| k.name())
+            .collect::<Vec<_>>()
+            .join(",")
+    ));
+    for kind in &typed.manifest.required_kinds {
+        out.push_str(&format!("requires_kind={}\n", kind.name()));
+    }
     for cap in &typed.manifest.required_caps {
         out.push_str(&format!("requires={cap}\n"));
     }
@@ -1,172 +1,1159 @@
-use crate::{ast::*, error::CompileError};
+use crate::{
+    ast::*,
+    diagnostic::{codes, Diagnostic, Diagnostics},
+    error::CompileError,
+    lexer::{lex_recovering, TemplateToken, Token, TokenKind},
+    span::{FileId, Span},
+};
+
+const STMT_KEYWORDS: &[&str] = &[
+    "tool", "fn", "struct", "enum", "let", "print", "call", "if", "while", "for", "match", "return",
+];
+const RESERVED: &[&str] = &["else", "in", "mut", "try"];
 
 pub fn parse_program(src: &str) -> Result<Program, CompileError> {
-    let mut statements = Vec::new();
-    for (line_no, raw) in src.lines().enumerate() {
-        let line = raw.trim();
-        if line.is_empty() || line.starts_with("//") {
-            continue;
-        }
-        if let Some(rest) = line.strip_prefix("tool ") {
-            // tool Name input {a: Int} output {b: String} cap toolCap;
-            let (name, tail) = rest.split_once(" input ").ok_or_else(|| {
-                CompileError::Parse(format!("line {}: invalid tool decl", line_no + 1))
-            })?;
-            let (input_raw, tail) = parse_braced_section(tail, line_no, "input")?;
-            let tail = tail.strip_prefix("output ").ok_or_else(|| {
-                CompileError::Parse(format!("line {}: expected output", line_no + 1))
-            })?;
-            let (output_raw, tail) = parse_braced_section(tail, line_no, "output")?;
-            let tail = tail
-                .trim()
-                .strip_prefix("cap ")
-                .ok_or_else(|| CompileError::Parse(format!("line {}: expected cap", line_no + 1)))?
-                .trim_end_matches(';')
-                .trim();
-            statements.push(Stmt::ToolDecl {
-                name: name.trim().to_string(),
-                input: parse_typed_fields(input_raw, line_no)?,
-                output: parse_typed_fields(output_raw, line_no)?,
-                cap: tail.to_string(),
-            });
-            continue;
-        }
-        if let Some(rest) = line.strip_prefix("let ") {
-            let (name, expr_part) = rest
-                .split_once('=')
-                .ok_or_else(|| CompileError::Parse(format!("line {}: invalid let", line_no + 1)))?;
-            let expr = parse_expr(expr_part.trim().trim_end_matches(';'))?;
-            statements.push(Stmt::Let {
-                name: name.trim().to_string(),
+    parse_file(FileId::default(), src)
+}
+
+pub fn parse_file(file: FileId, src: &str) -> Result<Program, CompileError> {
+    let (tokens, diags) = lex_recovering(file, src);
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        block_depth: 0,
+        no_struct_literals: false,
+        diags,
+    };
+    let effects = parser.parse_attributes();
+    let statements = parser.parse_stmt_list();
+    if parser.diags.has_errors() {
+        let mut diags = parser.diags.into_vec();
+        diags.sort_by_key(|d| d.primary_span().map(|s| s.start));
+        return Err(CompileError { diagnostics: diags });
+    }
+    Ok(Program {
+        effects,
+        statements,
+    })
+}
+
+type PResult<T> = Result<T, Diagnostic>;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    block_depth: usize,
+    /// Set while parsing `if`/`while`/`for`/`match` heads, where `Name {`
+    /// starts the block rather than a struct literal.
+    no_struct_literals: bool,
+    diags: Diagnostics,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn at(&self, kind: &TokenKind) -> bool {
+        &self.peek().kind == kind
+    }
+
+    fn at_keyword(&self, kw: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(s) if s == kw)
+    }
+
+    /// Whether the token after the current one is `kind`.
+    fn next_is(&self, kind: &TokenKind) -> bool {
+        self.tokens.get(self.pos + 1).map(|t| &t.kind) == Some(kind)
+    }
+
+    /// Span from `start` to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        let end = self.tokens[self.pos.saturating_sub(1)].span;
+        start.to(end)
+    }
+
+    fn bump(&mut self) -> Token {
+        let tok = self.tokens[self.pos].clone();
+        if tok.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    /// Skips to the end of the broken statement so parsing can resume after it.
+    fn synchronize(&mut self, stmt_start: usize) {
+        let mut depth = 0usize;
+        while !self.at(&TokenKind::Eof) {
+            let at_boundary =
+                self.at(&TokenKind::RBrace) || STMT_KEYWORDS.iter().any(|kw| self.at_keyword(kw));
+            if self.pos > stmt_start && depth == 0 && at_boundary {
+                return;
+            }
+            match self.bump().kind {
+                TokenKind::Semi if depth == 0 => return,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth <= 1 => return,
+                TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Statements up to the end of the file or the `}` closing the current block.
+    fn parse_stmt_list(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.at_list_end() {
+            let stmt_start = self.pos;
+            match self.parse_stmt() {
+                Ok(stmt) => statements.push(stmt),
+                Err(diag) => {
+                    self.diags.push(diag);
+                    self.synchronize(stmt_start);
+                }
+            }
+        }
+        statements
+    }
+
+    fn at_list_end(&self) -> bool {
+        self.at(&TokenKind::Eof) || (self.block_depth > 0 && self.at(&TokenKind::RBrace))
+    }
+
+    fn parse_block(&mut self) -> PResult<Block> {
+        let open = self.expect(TokenKind::LBrace)?;
+        self.block_depth += 1;
+        let stmts = self.parse_stmt_list();
+        self.block_depth -= 1;
+        self.expect(TokenKind::RBrace)?;
+        Ok(Block {
+            stmts,
+            span: self.span_from(open.span),
+        })
+    }
+
+    fn error_at(&self, tok: &Token, msg: &str) -> Diagnostic {
+        Diagnostic::error(codes::UNEXPECTED_TOKEN, msg).with_primary(tok.span, "")
+    }
+
+    fn expected(&self, what: &str) -> Diagnostic {
+        let tok = self.peek();
+        Diagnostic::error(
+            codes::UNEXPECTED_TOKEN,
+            format!("expected {what}, found {}", tok.kind.describe()),
+        )
+        .with_primary(tok.span, format!("expected {what}"))
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> PResult<Token> {
+        if self.at(&kind) {
+            Ok(self.bump())
+        } else {
+            Err(self.expected(&kind.describe()))
+        }
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> PResult<Token> {
+        if self.at_keyword(kw) {
+            Ok(self.bump())
+        } else {
+            Err(self.expected(&format!("`{kw}`")))
+        }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> PResult<String> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) => {
+                let name = name.clone();
+                self.bump();
+                Ok(name)
+            }
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn parse_stmt(&mut self) -> PResult<Stmt> {
+        let start = self.peek().span;
+        let kind = self.parse_stmt_kind()?;
+        Ok(Stmt {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_stmt_kind(&mut self) -> PResult<StmtKind> {
+        if self.at(&TokenKind::Hash) {
+            return Err(self.error_at(
+                self.peek(),
+                "attributes must appear at the top of the file, before any statement",
+            ));
+        }
+        if self.at_keyword("tool") {
+            if self.block_depth > 0 {
+                return Err(self.error_at(
+                    self.peek(),
+                    "tool declarations must appear at the top level",
+                ));
+            }
+            return self.parse_tool_decl();
+        }
+        if self.at_keyword("fn") {
+            if self.block_depth > 0 {
+                return Err(self.error_at(
+                    self.peek(),
+                    "function declarations must appear at the top level",
+                ));
+            }
+            return Ok(StmtKind::FnDecl(self.parse_fn_decl()?));
+        }
+        if self.at_keyword("struct") || self.at_keyword("enum") {
+            if self.block_depth > 0 {
+                return Err(self.error_at(
+                    self.peek(),
+                    "type declarations must appear at the top level",
+                ));
+            }
+            return self.parse_type_decl();
+        }
+        if self.at_keyword("match") {
+            self.bump();
+            let scrutinee = self.parse_head_expr()?;
+            let arms = self.parse_match_arms(Self::parse_block)?;
+            return Ok(StmtKind::Match { scrutinee, arms });
+        }
+        if self.at_keyword("return") {
+            self.bump();
+            let expr = if self.at(&TokenKind::Semi) {
+                None
+            } else {
+                Some(self.parse_expr()?)
+            };
+            self.expect(TokenKind::Semi)?;
+            return Ok(StmtKind::Return { expr });
+        }
+        if self.at_keyword("if") {
+            return self.parse_if();
+        }
+        if self.at_keyword("while") {
+            self.bump();
+            let cond = self.parse_head_expr()?;
+            let body = self.parse_block()?;
+            return Ok(StmtKind::While { cond, body });
+        }
+        if self.at_keyword("for") {
+            self.bump();
+            let var = self.expect_ident("loop variable")?;
+            self.expect_keyword("in")?;
+            let iter = self.parse_head_expr()?;
+            let body = self.parse_block()?;
+            return Ok(StmtKind::For { var, iter, body });
+        }
+        if self.at_keyword("let") {
+            self.bump();
+            let mutable = self.at_keyword("mut");
+            if mutable {
+                self.bump();
+            }
+            let name = self.expect_ident("variable name")?;
+            let ty = if self.at(&TokenKind::Colon) {
+                self.bump();
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            self.expect(TokenKind::Eq)?;
+            // `narrow` followed by a name; two names in a row are never an expression.
+            if self.at_keyword("narrow")
+                && matches!(self.tokens[self.pos + 1].kind, TokenKind::Ident(_))
+            {
+                return self.parse_narrow(name, mutable || ty.is_some());
+            }
+            let expr = self.parse_expr()?;
+            self.expect(TokenKind::Semi)?;
+            return Ok(StmtKind::Let {
+                name,
+                mutable,
+                ty,
                 expr,
             });
-            continue;
         }
-        if let Some(rest) = line.strip_prefix("print ") {
-            statements.push(Stmt::Print {
-                expr: parse_expr(rest.trim().trim_end_matches(';'))?,
-            });
-            continue;
-        }
-        if let Some(rest) = line.strip_prefix("call ") {
-            let (tool, tail) = rest.split_once('{').ok_or_else(|| {
-                CompileError::Parse(format!("line {}: missing '{{'", line_no + 1))
-            })?;
-            let (input_raw, tail) = tail.split_once('}').ok_or_else(|| {
-                CompileError::Parse(format!("line {}: missing '}}'", line_no + 1))
-            })?;
-            let mut input = Vec::new();
-            for part in input_raw
-                .split(',')
-                .map(str::trim)
-                .filter(|s| !s.is_empty())
-            {
-                let (k, v) = part.split_once(':').ok_or_else(|| {
-                    CompileError::Parse(format!("line {}: invalid tool input", line_no + 1))
-                })?;
-                input.push((k.trim().to_string(), parse_expr(v.trim())?));
-            }
-            let tail = tail.trim().trim_end_matches(';');
-            let tail = tail.strip_prefix("using ").ok_or_else(|| {
-                CompileError::Parse(format!("line {}: expected using", line_no + 1))
-            })?;
-            let (cap, timeout_part) = tail.split_once(" timeout ").ok_or_else(|| {
-                CompileError::Parse(format!("line {}: expected timeout", line_no + 1))
-            })?;
-            let timeout_ms = timeout_part
-                .trim()
-                .parse::<u64>()
-                .map_err(|e| CompileError::Parse(e.to_string()))?;
-            statements.push(Stmt::ToolCall {
-                tool: tool.trim().to_string(),
-                input,
-                cap: cap.trim().to_string(),
-                timeout_ms,
+        if self.at_keyword("print") {
+            self.bump();
+            let expr = self.parse_expr()?;
+            self.expect(TokenKind::Semi)?;
+            return Ok(StmtKind::Print { expr });
+        }
+        if self.at_keyword("call") {
+            let call = self.parse_tool_call()?;
+            self.expect(TokenKind::Semi)?;
+            return Ok(StmtKind::ToolCall(call));
+        }
+        let expr = self.parse_expr()?;
+        if let (ExprKind::Var(name), true) = (&expr.kind, self.at(&TokenKind::Eq)) {
+            self.bump();
+            let name = name.clone();
+            let expr = self.parse_expr()?;
+            self.expect(TokenKind::Semi)?;
+            return Ok(StmtKind::Assign { name, expr });
+        }
+        if !matches!(expr.kind, ExprKind::Call { .. }) {
+            return Err(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                "only function calls can be used as statements",
+            )
+            .with_primary(expr.span, "this value is not used"));
+        }
+        self.expect(TokenKind::Semi)?;
+        Ok(StmtKind::Expr { expr })
+    }
+
+    // fn name(a: Int, b: String) -> Int !tool[toolCap] { ... }
+    fn parse_fn_decl(&mut self) -> PResult<FnDecl> {
+        let start = self.expect_keyword("fn")?.span;
+        let name = self.expect_ident("function name")?;
+        self.expect(TokenKind::LParen)?;
+        let mut params = Vec::new();
+        while !self.at(&TokenKind::RParen) {
+            let param = self.expect_ident("parameter name")?;
+            self.expect(TokenKind::Colon)?;
+            params.push((param, self.parse_type()?));
+            if !self.at(&TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RParen)?;
+        let ret = if self.at(&TokenKind::Arrow) {
+            self.bump();
+            self.parse_type()?
+        } else {
+            Type::Unit
+        };
+        let effect = self.parse_effect_decl()?;
+        let body = self.parse_block()?;
+        Ok(FnDecl {
+            name,
+            params,
+            ret,
+            effect,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `!pure`, or one or more of `!time`, `!random`, `!fs`, `!net` and
+    /// `!tool`/`!tool[capA, capB]`; absent means `!pure`.
+    fn parse_effect_decl(&mut self) -> PResult<EffectDecl> {
+        let start = self.peek().span;
+        if !self.at(&TokenKind::Bang) {
+            return Ok(EffectDecl {
+                effect: Effect::PURE,
+                caps: Vec::new(),
+                span: start,
             });
-            continue;
         }
-        return Err(CompileError::Parse(format!(
-            "line {}: unrecognized statement",
-            line_no + 1
-        )));
+        let mut decl = EffectDecl {
+            effect: Effect::PURE,
+            caps: Vec::new(),
+            span: start,
+        };
+        let mut pure = None;
+        while self.at(&TokenKind::Bang) {
+            self.bump();
+            pure = pure.or(self.parse_effect_item(&mut decl)?);
+        }
+        if let (Some(span), false) = (pure, decl.effect.is_pure()) {
+            return Err(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                "`!pure` cannot be combined with other effects",
+            )
+            .with_primary(span, ""));
+        }
+        decl.span = self.span_from(start);
+        Ok(decl)
     }
-    Ok(Program { statements })
-}
 
-fn parse_braced_section<'a>(
-    tail: &'a str,
-    line_no: usize,
-    _name: &str,
-) -> Result<(&'a str, &'a str), CompileError> {
-    let tail = tail.trim();
-    let tail = tail
-        .strip_prefix('{')
-        .ok_or_else(|| CompileError::Parse(format!("line {}: expected '{{'", line_no + 1)))?;
-    let (inside, rest) = tail
-        .split_once('}')
-        .ok_or_else(|| CompileError::Parse(format!("line {}: missing '}}'", line_no + 1)))?;
-    Ok((inside, rest.trim()))
-}
+    /// Parses one effect name, with `tool`'s optional `[caps]`, into `decl`.
+    /// Returns the span of `pure`, which adds nothing.
+    fn parse_effect_item(&mut self, decl: &mut EffectDecl) -> PResult<Option<Span>> {
+        let tok = self.peek().clone();
+        let name = self.expect_ident("effect name")?;
+        if name == "pure" {
+            return Ok(Some(tok.span));
+        }
+        let Some(kind) = EffectKind::from_name(&name) else {
+            return Err(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                format!("unknown effect: {name}"),
+            )
+            .with_primary(tok.span, "not a known effect")
+            .with_help("expected `pure`, `time`, `random`, `fs`, `net` or `tool`"));
+        };
+        decl.effect = decl.effect.join(Effect::of(kind));
+        if kind == EffectKind::Tool && self.at(&TokenKind::LBracket) {
+            self.bump();
+            while !self.at(&TokenKind::RBracket) {
+                decl.caps.push(self.expect_ident("capability name")?);
+                if !self.at(&TokenKind::RBracket) {
+                    self.expect(TokenKind::Comma)?;
+                }
+            }
+            self.expect(TokenKind::RBracket)?;
+        }
+        Ok(None)
+    }
+
+    /// Parses the `#![...]` attributes at the top of a file. `effects` is the
+    /// only attribute and may appear once.
+    fn parse_attributes(&mut self) -> Option<EffectDecl> {
+        let mut effects: Option<EffectDecl> = None;
+        while self.at(&TokenKind::Hash) {
+            match self.parse_effects_attribute() {
+                Ok(decl) => {
+                    if let Some(first) = &effects {
+                        self.diags.push(
+                            Diagnostic::error(
+                                codes::UNEXPECTED_TOKEN,
+                                "duplicate `#![effects(...)]` attribute",
+                            )
+                            .with_primary(decl.span, "")
+                            .with_secondary(first.span, "first declared here"),
+                        );
+                    } else {
+                        effects = Some(decl);
+                    }
+                }
+                Err(diag) => {
+                    self.diags.push(diag);
+                    while !matches!(self.peek().kind, TokenKind::RBracket | TokenKind::Eof) {
+                        self.bump();
+                    }
+                    self.bump();
+                }
+            }
+        }
+        effects
+    }
 
-fn parse_typed_fields(raw: &str, line_no: usize) -> Result<Vec<(String, Type)>, CompileError> {
-    let mut out = Vec::new();
-    for part in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
-        let (k, t) = part.split_once(':').ok_or_else(|| {
-            CompileError::Parse(format!("line {}: invalid typed field", line_no + 1))
+    // #![effects(fs, net)] or #![effects(tool[toolCap])]
+    fn parse_effects_attribute(&mut self) -> PResult<EffectDecl> {
+        let start = self.expect(TokenKind::Hash)?.span;
+        self.expect(TokenKind::Bang)?;
+        self.expect(TokenKind::LBracket)?;
+        let tok = self.peek().clone();
+        let name = self.expect_ident("attribute name")?;
+        if name != "effects" {
+            return Err(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                format!("unknown attribute: {name}"),
+            )
+            .with_primary(tok.span, "")
+            .with_help("the only attribute is `#![effects(...)]`"));
+        }
+        self.expect(TokenKind::LParen)?;
+        let mut decl = EffectDecl {
+            effect: Effect::PURE,
+            caps: Vec::new(),
+            span: start,
+        };
+        let mut pure = None;
+        while !self.at(&TokenKind::RParen) {
+            pure = pure.or(self.parse_effect_item(&mut decl)?);
+            if !self.at(&TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RParen)?;
+        self.expect(TokenKind::RBracket)?;
+        if let (Some(span), false) = (pure, decl.effect.is_pure()) {
+            return Err(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                "`pure` cannot be combined with other effects",
+            )
+            .with_primary(span, ""));
+        }
+        decl.span = self.span_from(start);
+        Ok(decl)
+    }
+
+    // let name = narrow parent to [Tool, ...];
+    fn parse_narrow(&mut self, name: String, decorated: bool) -> PResult<StmtKind> {
+        let tok = self.expect_keyword("narrow")?;
+        if self.block_depth > 0 {
+            return Err(self.error_at(&tok, "capability narrowing must appear at the top level"));
+        }
+        if decorated {
+            return Err(self.error_at(
+                &tok,
+                "derived capabilities cannot be `mut` or have a type annotation",
+            ));
+        }
+        let parent = self.expect_ident("capability name")?;
+        self.expect_keyword("to")?;
+        self.expect(TokenKind::LBracket)?;
+        let mut tools = Vec::new();
+        while !self.at(&TokenKind::RBracket) {
+            tools.push(self.expect_ident("tool name")?);
+            if !self.at(&TokenKind::RBracket) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RBracket)?;
+        self.expect(TokenKind::Semi)?;
+        Ok(StmtKind::Narrow {
+            name,
+            parent,
+            tools,
+        })
+    }
+
+    // if cond { ... } else if cond { ... } else { ... }
+    fn parse_if(&mut self) -> PResult<StmtKind> {
+        self.expect_keyword("if")?;
+        let cond = self.parse_head_expr()?;
+        let then_block = self.parse_block()?;
+        let else_block = if self.at_keyword("else") {
+            self.bump();
+            if self.at_keyword("if") {
+                let start = self.peek().span;
+                let kind = self.parse_if()?;
+                let span = self.span_from(start);
+                Some(Block {
+                    stmts: vec![Stmt { kind, span }],
+                    span,
+                })
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+        Ok(StmtKind::If {
+            cond,
+            then_block,
+            else_block,
+        })
+    }
+
+    // tool Name input {a: Int} output {b: String} cap toolCap;
+    fn parse_tool_decl(&mut self) -> PResult<StmtKind> {
+        self.expect_keyword("tool")?;
+        let name = self.expect_ident("tool name")?;
+        self.expect_keyword("input")?;
+        let input = self.parse_typed_fields()?;
+        self.expect_keyword("output")?;
+        let output = self.parse_typed_fields()?;
+        self.expect_keyword("cap")?;
+        let cap = self.expect_ident("capability name")?;
+        self.expect(TokenKind::Semi)?;
+        Ok(StmtKind::ToolDecl {
+            name,
+            input,
+            output,
+            cap,
+        })
+    }
+
+    // struct Point { x: Int, y: Int }
+    // enum State { Idle, Done(Int, String) }
+    fn parse_type_decl(&mut self) -> PResult<StmtKind> {
+        if self.at_keyword("struct") {
+            self.bump();
+            let name = self.expect_ident("struct name")?;
+            let fields = self.parse_typed_fields()?;
+            return Ok(StmtKind::StructDecl { name, fields });
+        }
+        self.expect_keyword("enum")?;
+        let name = self.expect_ident("enum name")?;
+        self.expect(TokenKind::LBrace)?;
+        let variants = self.parse_list(TokenKind::RBrace, |p| {
+            let variant = p.expect_ident("variant name")?;
+            let payload = if p.at(&TokenKind::LParen) {
+                p.bump();
+                p.parse_list(TokenKind::RParen, Self::parse_type)?
+            } else {
+                Vec::new()
+            };
+            Ok((variant, payload))
         })?;
-        out.push((k.trim().to_string(), parse_type(t.trim())?));
+        Ok(StmtKind::EnumDecl { name, variants })
     }
-    Ok(out)
-}
 
-fn parse_type(raw: &str) -> Result<Type, CompileError> {
-    match raw {
-        "Int" => Ok(Type::Int),
-        "Bool" => Ok(Type::Bool),
-        "String" => Ok(Type::String),
-        _ => Err(CompileError::Parse(format!("unknown type: {raw}"))),
+    /// `{ pattern => body, ... }`; commas after block bodies are optional.
+    fn parse_match_arms<B>(
+        &mut self,
+        mut body: impl FnMut(&mut Self) -> PResult<B>,
+    ) -> PResult<Vec<MatchArm<B>>> {
+        self.expect(TokenKind::LBrace)?;
+        let mut arms = Vec::new();
+        while !self.at(&TokenKind::RBrace) {
+            let pattern = self.parse_pattern()?;
+            self.expect(TokenKind::FatArrow)?;
+            let block_body = self.at(&TokenKind::LBrace);
+            arms.push(MatchArm {
+                pattern,
+                body: body(self)?,
+            });
+            if self.at(&TokenKind::Comma) || (!block_body && !self.at(&TokenKind::RBrace)) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+        Ok(arms)
+    }
+
+    fn parse_pattern(&mut self) -> PResult<Pattern> {
+        let tok = self.peek().clone();
+        let kind = match &tok.kind {
+            TokenKind::Ident(s) if s == "_" => PatternKind::Wildcard,
+            TokenKind::Ident(s) if s == "true" => PatternKind::Bool(true),
+            TokenKind::Ident(s) if s == "false" => PatternKind::Bool(false),
+            TokenKind::Ident(s)
+                if self.next_is(&TokenKind::ColonColon) || builtin_enum(s).is_some() =>
+            {
+                let (enum_name, variant) = match builtin_enum(s) {
+                    Some(enum_name) => {
+                        self.bump();
+                        (enum_name.to_string(), s.clone())
+                    }
+                    None => {
+                        self.bump();
+                        self.bump();
+                        (s.clone(), self.expect_ident("variant name")?)
+                    }
+                };
+                let args = if self.at(&TokenKind::LParen) {
+                    self.bump();
+                    self.parse_list(TokenKind::RParen, Self::parse_pattern)?
+                } else {
+                    Vec::new()
+                };
+                return Ok(Pattern {
+                    kind: PatternKind::Variant {
+                        enum_name,
+                        variant,
+                        args,
+                    },
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s) if !is_keyword(s) => PatternKind::Binding(s.clone()),
+            TokenKind::Int(v) => PatternKind::Int(int_literal(&tok, *v)?),
+            TokenKind::Minus => {
+                self.bump();
+                let TokenKind::Int(v) = self.peek().kind else {
+                    return Err(self.expected("integer"));
+                };
+                PatternKind::Int(negated_literal(v))
+            }
+            TokenKind::Str(s) => PatternKind::String(s.clone()),
+            _ => return Err(self.expected("pattern")),
+        };
+        self.bump();
+        Ok(Pattern {
+            kind,
+            span: self.span_from(tok.span),
+        })
+    }
+
+    // call Name { a: expr } using toolCap timeout 1000
+    fn parse_tool_call(&mut self) -> PResult<ToolCall> {
+        self.expect_keyword("call")?;
+        let tool = self.expect_ident("tool name")?;
+        self.expect(TokenKind::LBrace)?;
+        let mut input = Vec::new();
+        while !self.at(&TokenKind::RBrace) {
+            let field = self.expect_ident("field name")?;
+            self.expect(TokenKind::Colon)?;
+            input.push((field, self.parse_expr()?));
+            if !self.at(&TokenKind::RBrace) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+        self.expect_keyword("using")?;
+        let cap = self.expect_ident("capability name")?;
+        self.expect_keyword("timeout")?;
+        let timeout_tok = self.bump();
+        let timeout_ms = match timeout_tok.kind {
+            TokenKind::Int(v) => v,
+            _ => {
+                return Err(self.error_at(
+                    &timeout_tok,
+                    &format!(
+                        "expected timeout in milliseconds, found {}",
+                        timeout_tok.kind.describe()
+                    ),
+                ))
+            }
+        };
+        Ok(ToolCall {
+            tool,
+            input,
+            cap,
+            timeout_ms,
+        })
+    }
+
+    fn parse_typed_fields(&mut self) -> PResult<Vec<(String, Type)>> {
+        self.expect(TokenKind::LBrace)?;
+        let mut out = Vec::new();
+        while !self.at(&TokenKind::RBrace) {
+            let field = self.expect_ident("field name")?;
+            self.expect(TokenKind::Colon)?;
+            out.push((field, self.parse_type()?));
+            if !self.at(&TokenKind::RBrace) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+        Ok(out)
+    }
+
+    fn parse_type(&mut self) -> PResult<Type> {
+        if self.at(&TokenKind::LBrace) {
+            let mut fields = self.parse_typed_fields()?;
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            return Ok(Type::Record(fields));
+        }
+        let name = self.expect_ident("type")?;
+        match name.as_str() {
+            "Array" | "Set" => {
+                self.expect(TokenKind::LBracket)?;
+                let elem = Box::new(if name == "Set" {
+                    self.parse_key_type()?
+                } else {
+                    self.parse_type()?
+                });
+                self.expect(TokenKind::RBracket)?;
+                Ok(if name == "Array" {
+                    Type::Array(elem)
+                } else {
+                    Type::Set(elem)
+                })
+            }
+            "Map" => {
+                self.expect(TokenKind::LBracket)?;
+                let key = Box::new(self.parse_key_type()?);
+                self.expect(TokenKind::Comma)?;
+                let value = Box::new(self.parse_type()?);
+                self.expect(TokenKind::RBracket)?;
+                Ok(Type::Map(key, value))
+            }
+            "Option" => {
+                self.expect(TokenKind::LBracket)?;
+                let inner = self.parse_type()?;
+                self.expect(TokenKind::RBracket)?;
+                Ok(Type::Option(Box::new(inner)))
+            }
+            "Result" => {
+                self.expect(TokenKind::LBracket)?;
+                let ok = self.parse_type()?;
+                self.expect(TokenKind::Comma)?;
+                let err = self.parse_type()?;
+                self.expect(TokenKind::RBracket)?;
+                Ok(Type::Result(Box::new(ok), Box::new(err)))
+            }
+            "Int" => Ok(Type::Int),
+            "Float" => Ok(Type::Float),
+            "Bool" => Ok(Type::Bool),
+            "String" => Ok(Type::String),
+            "Bytes" => Ok(Type::Bytes),
+            "Unit" => Ok(Type::Unit),
+            // Resolved against struct and enum declarations by the typechecker.
+            _ => Ok(Type::Named(name)),
+        }
+    }
+
+    fn parse_key_type(&mut self) -> PResult<Type> {
+        let start = self.peek().span;
+        let ty = self.parse_type()?;
+        if ty.is_key() {
+            return Ok(ty);
+        }
+        Err(Diagnostic::error(
+            codes::INVALID_KEY_TYPE,
+            format!("{ty} cannot be used as a map key or set element"),
+        )
+        .with_primary(self.span_from(start), "")
+        .with_note("keys must be Int, Bool, String or Bytes"))
+    }
+
+    fn parse_expr(&mut self) -> PResult<Expr> {
+        self.parse_binary(0)
+    }
+
+    /// An expression directly followed by a block, e.g. an `if` condition.
+    fn parse_head_expr(&mut self) -> PResult<Expr> {
+        self.with_struct_literals(false, Self::parse_expr)
+    }
+
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        f: impl FnOnce(&mut Self) -> PResult<T>,
+    ) -> PResult<T> {
+        let outer = std::mem::replace(&mut self.no_struct_literals, !allowed);
+        let result = f(self);
+        self.no_struct_literals = outer;
+        result
+    }
+
+    /// Precedence climbing: parses operators binding tighter than `min_prec`.
+    fn parse_binary(&mut self, min_prec: u8) -> PResult<Expr> {
+        let mut lhs = self.parse_unary()?;
+        let mut last_comparison: Option<Token> = None;
+        while let Some((op, prec)) = binary_op(&self.peek().kind) {
+            if prec < min_prec {
+                break;
+            }
+            let op_tok = self.bump();
+            if op.is_comparison() {
+                if let Some(prev) = &last_comparison {
+                    return Err(Diagnostic::error(
+                        codes::UNEXPECTED_TOKEN,
+                        "comparison operators cannot be chained",
+                    )
+                    .with_primary(op_tok.span, "")
+                    .with_secondary(prev.span, "previous comparison")
+                    .with_help("split the comparison with `&&`"));
+                }
+                last_comparison = Some(op_tok.clone());
+            }
+            let rhs = self.parse_binary(prec + 1)?;
+            let span = lhs.span.to(rhs.span);
+            lhs = Expr {
+                kind: ExprKind::Binary {
+                    lhs: Box::new(lhs),
+                    op,
+                    rhs: Box::new(rhs),
+                },
+                span,
+            };
+        }
+        Ok(lhs)
     }
-}
 
-fn parse_expr(raw: &str) -> Result<Expr, CompileError> {
-    let raw = raw.trim();
-    if raw.starts_with('"') && raw.ends_with('"') {
-        return Ok(Expr::String(raw.trim_matches('"').to_string()));
-    }
-    if raw == "true" {
-        return Ok(Expr::Bool(true));
-    }
-    if raw == "false" {
-        return Ok(Expr::Bool(false));
-    }
-    if let Ok(v) = raw.parse::<i64>() {
-        return Ok(Expr::Int(v));
-    }
-    for (c, op) in [
-        ('+', BinOp::Add),
-        ('-', BinOp::Sub),
-        ('*', BinOp::Mul),
-        ('/', BinOp::Div),
-    ] {
-        if let Some((lhs, rhs)) = split_once_top_level(raw, c) {
-            return Ok(Expr::Binary {
-                lhs: Box::new(parse_expr(lhs)?),
+    fn parse_unary(&mut self) -> PResult<Expr> {
+        if let Some(lit) = self.negative_literal() {
+            return Ok(lit);
+        }
+        let op = match self.peek().kind {
+            TokenKind::Minus => UnaryOp::Neg,
+            TokenKind::Bang => UnaryOp::Not,
+            _ => return self.parse_postfix(),
+        };
+        let op_tok = self.bump();
+        let expr = self.parse_unary()?;
+        Ok(Expr {
+            span: op_tok.span.to(expr.span),
+            kind: ExprKind::Unary {
                 op,
-                rhs: Box::new(parse_expr(rhs)?),
-            });
+                expr: Box::new(expr),
+            },
+        })
+    }
+
+    /// Folds `-` followed by an integer literal into a negative literal, so
+    /// that `-9223372036854775808` is range-checked as a whole. A literal
+    /// with a postfix suffix, as in `-1.x`, is left to `parse_unary`.
+    fn negative_literal(&mut self) -> Option<Expr> {
+        let kinds = self
+            .tokens
+            .get(self.pos..self.pos + 3)?
+            .iter()
+            .map(|t| &t.kind)
+            .collect::<Vec<_>>();
+        let [TokenKind::Minus, TokenKind::Int(v), next] = kinds[..] else {
+            return None;
+        };
+        if matches!(
+            next,
+            TokenKind::LBracket | TokenKind::Dot | TokenKind::Question
+        ) {
+            return None;
         }
+        let value = negated_literal(*v);
+        let minus = self.bump();
+        let lit = self.bump();
+        Some(Expr {
+            kind: ExprKind::Int(value),
+            span: minus.span.to(lit.span),
+        })
+    }
+
+    /// A primary expression followed by any number of `[index]`, `.field` and
+    /// `?` suffixes.
+    fn parse_postfix(&mut self) -> PResult<Expr> {
+        let start = self.peek().span;
+        let mut expr = self.parse_primary()?;
+        loop {
+            let kind = if self.at(&TokenKind::LBracket) {
+                self.bump();
+                let index = self.with_struct_literals(true, Self::parse_expr)?;
+                self.expect(TokenKind::RBracket)?;
+                ExprKind::Index {
+                    expr: Box::new(expr),
+                    index: Box::new(index),
+                }
+            } else if self.at(&TokenKind::Question) {
+                self.bump();
+                ExprKind::Propagate(Box::new(expr))
+            } else if self.at(&TokenKind::Dot) {
+                self.bump();
+                ExprKind::Field {
+                    field: self.expect_ident("field name")?,
+                    expr: Box::new(expr),
+                }
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr {
+                kind,
+                span: self.span_from(start),
+            };
+        }
+    }
+
+    /// Parses comma-separated items up to and including `close`; a trailing
+    /// comma is allowed.
+    fn parse_list<T>(
+        &mut self,
+        close: TokenKind,
+        mut item: impl FnMut(&mut Self) -> PResult<T>,
+    ) -> PResult<Vec<T>> {
+        self.with_struct_literals(true, |p| {
+            let mut items = Vec::new();
+            while !p.at(&close) {
+                items.push(item(p)?);
+                if !p.at(&close) {
+                    p.expect(TokenKind::Comma)?;
+                }
+            }
+            p.expect(close)?;
+            Ok(items)
+        })
+    }
+
+    fn parse_primary(&mut self) -> PResult<Expr> {
+        let tok = self.peek().clone();
+        let kind = match &tok.kind {
+            TokenKind::Int(v) => ExprKind::Int(int_literal(&tok, *v)?),
+            TokenKind::Float(v) => ExprKind::Float(*v),
+            TokenKind::Str(s) => ExprKind::String(s.clone()),
+            TokenKind::Bytes(b) => ExprKind::Bytes(b.clone()),
+            TokenKind::Template(parts) => ExprKind::Template(self.parse_template(parts)?),
+            TokenKind::Ident(s) if s == "true" => ExprKind::Bool(true),
+            TokenKind::Ident(s) if s == "false" => ExprKind::Bool(false),
+            TokenKind::Ident(s)
+                if (s == "Map" || s == "Set") && self.next_is(&TokenKind::LBrace) =>
+            {
+                self.bump();
+                self.bump();
+                let kind = if s == "Map" {
+                    ExprKind::Map(self.parse_list(TokenKind::RBrace, |p| {
+                        let key = p.parse_expr()?;
+                        p.expect(TokenKind::Colon)?;
+                        Ok((key, p.parse_expr()?))
+                    })?)
+                } else {
+                    ExprKind::Set(self.parse_list(TokenKind::RBrace, Self::parse_expr)?)
+                };
+                return Ok(Expr {
+                    kind,
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s) if s == "match" => {
+                self.bump();
+                let scrutinee = self.parse_head_expr()?;
+                let arms = self.parse_match_arms(Self::parse_expr)?;
+                return Ok(Expr {
+                    kind: ExprKind::Match {
+                        scrutinee: Box::new(scrutinee),
+                        arms,
+                    },
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s) if s == "call" => {
+                let call = self.parse_tool_call()?;
+                return Ok(Expr {
+                    kind: ExprKind::ToolCall(call),
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s) if s == "try" => {
+                self.bump();
+                let call = self.parse_tool_call()?;
+                return Ok(Expr {
+                    kind: ExprKind::TryCall(call),
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s)
+                if self.next_is(&TokenKind::ColonColon) || builtin_enum(s).is_some() =>
+            {
+                let (enum_name, variant) = match builtin_enum(s) {
+                    Some(enum_name) => {
+                        self.bump();
+                        (enum_name.to_string(), s.clone())
+                    }
+                    None => {
+                        self.bump();
+                        self.bump();
+                        (s.clone(), self.expect_ident("variant name")?)
+                    }
+                };
+                let args = if self.at(&TokenKind::LParen) {
+                    self.bump();
+                    self.parse_list(TokenKind::RParen, Self::parse_expr)?
+                } else {
+                    Vec::new()
+                };
+                return Ok(Expr {
+                    kind: ExprKind::Variant {
+                        enum_name,
+                        variant,
+                        args,
+                    },
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s)
+                if !is_keyword(s)
+                    && !self.no_struct_literals
+                    && self.next_is(&TokenKind::LBrace) =>
+            {
+                let name = s.clone();
+                self.bump();
+                self.bump();
+                let fields = self.parse_list(TokenKind::RBrace, |p| {
+                    let field = p.expect_ident("field name")?;
+                    p.expect(TokenKind::Colon)?;
+                    Ok((field, p.parse_expr()?))
+                })?;
+                return Ok(Expr {
+                    kind: ExprKind::Struct { name, fields },
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::Ident(s) if !is_keyword(s) => {
+                if self.next_is(&TokenKind::LParen) {
+                    return self.parse_call(s.clone(), tok.span);
+                }
+                ExprKind::Var(s.clone())
+            }
+            TokenKind::LBracket => {
+                self.bump();
+                let items = self.parse_list(TokenKind::RBracket, Self::parse_expr)?;
+                return Ok(Expr {
+                    kind: ExprKind::Array(items),
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::LParen if self.next_is(&TokenKind::RParen) => {
+                self.bump();
+                self.bump();
+                return Ok(Expr {
+                    kind: ExprKind::Unit,
+                    span: self.span_from(tok.span),
+                });
+            }
+            TokenKind::LParen => {
+                self.bump();
+                let inner = self.with_struct_literals(true, Self::parse_expr)?;
+                self.expect(TokenKind::RParen)?;
+                return Ok(Expr {
+                    kind: inner.kind,
+                    span: self.span_from(tok.span),
+                });
+            }
+            _ => return Err(self.expected("expression")),
+        };
+        self.bump();
+        Ok(Expr {
+            kind,
+            span: tok.span,
+        })
     }
-    Ok(Expr::Var(raw.to_string()))
-}
 
-fn split_once_top_level(s: &str, needle: char) -> Option<(&str, &str)> {
-    let mut in_string = false;
-    for (idx, ch) in s.char_indices() {
-        match ch {
-            '"' => in_string = !in_string,
-            _ if !in_string && ch == needle => return Some((&s[..idx], &s[idx + 1..])),
-            _ => {}
+    fn parse_call(&mut self, callee: String, start: Span) -> PResult<Expr> {
+        self.bump();
+        self.expect(TokenKind::LParen)?;
+        let args = self.parse_list(TokenKind::RParen, Self::parse_expr)?;
+        Ok(Expr {
+            kind: ExprKind::Call { callee, args },
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_template(&self, parts: &[TemplateToken]) -> PResult<Vec<TemplatePart>> {
+        let mut out = Vec::new();
+        for part in parts {
+            match part {
+                TemplateToken::Lit(s) => out.push(TemplatePart::Lit(s.clone())),
+                TemplateToken::Expr(tokens) => {
+                    let mut inner = Parser {
+                        tokens: tokens.clone(),
+                        pos: 0,
+                        block_depth: 0,
+                        no_struct_literals: false,
+                        diags: Diagnostics::default(),
+                    };
+                    let expr = inner.parse_expr()?;
+                    inner.expect(TokenKind::Eof)?;
+                    out.push(TemplatePart::Expr(expr));
+                }
+            }
         }
+        Ok(out)
     }
-    None
+}
+
+/// The built-in enum a bare `Some`, `None`, `Ok` or `Err` belongs to.
+fn builtin_enum(variant: &str) -> Option<&'static str> {
+    match variant {
+        "Some" | "None" => Some("Option"),
+        "Ok" | "Err" => Some("Result"),
+        _ => None,
+    }
+}
+
+fn is_keyword(s: &str) -> bool {
+    STMT_KEYWORDS.contains(&s) || RESERVED.contains(&s)
+}
+
+const COMPARISON_PREC: u8 = 3;
+
+/// Binary operators and their precedence; all are left-associative except
+/// comparisons, which may not be chained.
+fn binary_op(kind: &TokenKind) -> Option<(BinOp, u8)> {
+    Some(match kind {
+        TokenKind::OrOr => (BinOp::Or, 1),
+        TokenKind::AndAnd => (BinOp::And, 2),
+        TokenKind::EqEq => (BinOp::Eq, COMPARISON_PREC),
+        TokenKind::NotEq => (BinOp::Ne, COMPARISON_PREC),
+        TokenKind::Lt => (BinOp::Lt, COMPARISON_PREC),
+        TokenKind::Le => (BinOp::Le, COMPARISON_PREC),
+        TokenKind::Gt => (BinOp::Gt, COMPARISON_PREC),
+        TokenKind::Ge => (BinOp::Ge, COMPARISON_PREC),
+        TokenKind::Plus => (BinOp::Add, 4),
+        TokenKind::Minus => (BinOp::Sub, 4),
+        TokenKind::Star => (BinOp::Mul, 5),
+        TokenKind::Slash => (BinOp::Div, 5),
+        _ => return None,
+    })
+}
+
+/// Converts an integer literal without a leading `-`.
+fn int_literal(tok: &Token, value: u64) -> PResult<i64> {
+    i64::try_from(value).map_err(|_| {
+        Diagnostic::error(codes::INVALID_LITERAL, "integer literal out of range")
+            .with_primary(tok.span, "does not fit in a 64-bit signed integer")
+    })
+}
+
+/// The value of `-value`; the lexer caps literals at the magnitude of `i64::MIN`.
+fn negated_literal(value: u64) -> i64 {
+    0i64.wrapping_sub_unsigned(value)
 }
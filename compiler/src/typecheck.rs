@@ -1,5 +1,14 @@
-use crate::{ast::*, error::CompileError};
-use std::collections::{HashMap, HashSet};
+use crate::{
+    ast::*,
+    diagnostic::{codes, Diagnostic, Diagnostics},
+    error::CompileError,
+    exhaustiveness::{missing_pattern, Enums},
+    span::Span,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+};
 
 #[derive(Debug, Clone)]
 pub struct TypedProgram {
@@ -7,104 +16,1916 @@ pub struct TypedProgram {
     pub effect: Effect,
     pub tools: HashMap<String, ToolSignature>,
     pub manifest: CapabilityManifest,
+    pub warnings: Vec<Diagnostic>,
 }
 
 pub fn typecheck(program: Program) -> Result<TypedProgram, CompileError> {
-    let mut env = HashMap::<String, Type>::new();
-    let mut effect = Effect::Pure;
-    let mut tools = HashMap::<String, ToolSignature>::new();
-    let mut caps = HashSet::<String>::new();
-
-    for stmt in &program.statements {
-        if let Stmt::ToolDecl {
-            name,
+    let mut checker = Checker {
+        scopes: vec![HashMap::new()],
+        structs: HashMap::from([(
+            TOOL_ERROR.to_string(),
+            vec![
+                ("tool".to_string(), Type::String),
+                ("message".to_string(), Type::String),
+            ],
+        )]),
+        program_effects: program.effects.clone(),
+        ..Checker::default()
+    };
+    checker.collect_types(&program);
+    checker.collect_tools(&program);
+    checker.collect_fns(&program);
+    checker.collect_derived_caps(&program);
+    let effect = checker.check_stmts(&program.statements);
+    checker.pop_scope();
+    checker.check_calls_before_narrow();
+    checker.check_unused_tools();
+    if checker.diags.has_errors() {
+        return Err(checker.diags.into());
+    }
+    for lint in std::mem::take(&mut checker.lints) {
+        checker.diags.push(lint);
+    }
+
+    let mut required_caps = checker.caps.into_iter().collect::<Vec<_>>();
+    required_caps.sort();
+    let required_kinds = effect.kinds().map(EffectKind::capability).collect();
+
+    Ok(TypedProgram {
+        program,
+        effect,
+        tools: checker.tools,
+        manifest: CapabilityManifest {
+            required_caps,
+            required_kinds,
+        },
+        warnings: checker.diags.into_vec(),
+    })
+}
+
+#[derive(Default)]
+struct Checker {
+    scopes: Vec<HashMap<String, Binding>>,
+    /// `let`s of the statement lists being checked, innermost last; a name
+    /// found here but not in scope is used before its definition.
+    pending_lets: Vec<Vec<(String, Span)>>,
+    tools: HashMap<String, ToolSignature>,
+    /// Declared tools in source order, with their declaration spans.
+    tool_decls: Vec<(String, Span)>,
+    called_tools: HashSet<String>,
+    /// Root capabilities the program needs from the runtime.
+    caps: HashSet<String>,
+    /// Capabilities derived with `narrow`, by name.
+    derived_caps: HashMap<String, DerivedCap>,
+    /// Derived capabilities whose `narrow` has been checked; top-level code
+    /// may only use these.
+    narrowed: HashSet<String>,
+    /// Derived capabilities each function uses directly, by function.
+    fn_derived_uses: HashMap<String, BTreeSet<String>>,
+    /// User-defined functions each function calls, by caller.
+    fn_callees: HashMap<String, BTreeSet<String>>,
+    /// Top-level calls to user-defined functions, with the derived
+    /// capabilities narrowed when each call is made.
+    top_level_calls: Vec<(String, Span, HashSet<String>)>,
+    structs: HashMap<String, Vec<(String, Type)>>,
+    enums: Enums,
+    fns: HashMap<String, FnSig>,
+    /// The function whose body is being checked, if any.
+    current_fn: Option<(String, FnSig)>,
+    /// The program's `#![effects(...)]` bound on top-level code.
+    program_effects: Option<EffectDecl>,
+    /// Effects of calls and `match` arms in the current statement's expressions.
+    call_effect: Effect,
+    diags: Diagnostics,
+    /// Unused and shadowed variable warnings, reported only when the program
+    /// has no errors, since errors often leave bindings unused.
+    lints: Vec<Diagnostic>,
+}
+
+/// A variable in scope. `ty` is `None` when the binding's type could not be
+/// inferred; uses of such bindings are not reported again.
+#[derive(Debug)]
+struct Binding {
+    ty: Option<Type>,
+    span: Span,
+    mutable: bool,
+    used: bool,
+    assigned: bool,
+}
+
+impl Binding {
+    fn new(ty: Option<Type>, span: Span, mutable: bool) -> Self {
+        Binding {
+            ty,
+            span,
+            mutable,
+            used: false,
+            assigned: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DerivedCap {
+    parent: String,
+    tools: Vec<String>,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+struct FnSig {
+    params: Vec<Type>,
+    ret: Type,
+    effect: EffectDecl,
+}
+
+/// Whether every path through `block` ends in a `return`.
+fn always_returns(block: &Block) -> bool {
+    block.stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return { .. } => true,
+        StmtKind::If {
+            then_block,
+            else_block: Some(else_block),
+            ..
+        } => always_returns(then_block) && always_returns(else_block),
+        // Exhaustiveness is checked separately.
+        StmtKind::Match { arms, .. } => {
+            !arms.is_empty() && arms.iter().all(|arm| always_returns(&arm.body))
+        }
+        _ => false,
+    })
+}
+
+/// `bound` written as the attribute that declares it, e.g. `#![effects(fs, net)]`.
+fn effects_attribute(bound: &EffectDecl) -> String {
+    let kinds = bound
+        .effect
+        .kinds()
+        .map(|kind| match kind {
+            EffectKind::Tool if !bound.caps.is_empty() => {
+                format!("tool[{}]", bound.caps.join(", "))
+            }
+            _ => kind.name().to_string(),
+        })
+        .collect::<Vec<_>>();
+    if kinds.is_empty() {
+        "#![effects(pure)]".to_string()
+    } else {
+        format!("#![effects({})]", kinds.join(", "))
+    }
+}
+
+/// Built-in functions other than the polymorphic `len`, each with the
+/// effect it performs.
+fn builtin_sig(name: &str, span: Span) -> Option<FnSig> {
+    let (params, ret, effect) = match name {
+        "now" => (vec![], Type::Int, Effect::TIME),
+        "random" => (vec![], Type::Int, Effect::RANDOM),
+        "read_file" => (vec![Type::String], Type::String, Effect::FS),
+        "http_get" => (vec![Type::String], Type::String, Effect::NET),
+        _ => return None,
+    };
+    Some(FnSig {
+        params,
+        ret,
+        effect: EffectDecl {
+            effect,
+            caps: Vec::new(),
+            span,
+        },
+    })
+}
+
+/// The value of an Int expression built from literals, if it has one.
+fn const_int(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::Int(v) => Some(*v),
+        ExprKind::Unary {
+            op: UnaryOp::Neg,
+            expr,
+        } => const_int(expr)?.checked_neg(),
+        ExprKind::Binary { lhs, op, rhs } => op.checked_int(const_int(lhs)?, const_int(rhs)?),
+        _ => None,
+    }
+}
+
+/// Payload types of `Some`/`None`/`Ok`/`Err` within `ty`, if `ty` is the
+/// built-in type the variant belongs to.
+fn builtin_payload(variant: &str, ty: &Type) -> Option<Vec<Type>> {
+    match (variant, ty) {
+        ("Some", Type::Option(inner))
+        | ("Ok", Type::Result(inner, _))
+        | ("Err", Type::Result(_, inner)) => Some(vec![(**inner).clone()]),
+        ("None", Type::Option(_)) => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+fn builtin_arity(variant: &str) -> usize {
+    usize::from(variant != "None")
+}
+
+impl Checker {
+    fn collect_types(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            let (name, kind) = match &stmt.kind {
+                StmtKind::StructDecl { name, .. } => (name, "struct"),
+                StmtKind::EnumDecl { name, .. } => (name, "enum"),
+                _ => continue,
+            };
+            if name == TOOL_ERROR || BUILTIN_ENUMS.contains(&name.as_str()) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("type {name} is built in"),
+                    )
+                    .with_primary(stmt.span, format!("{kind} cannot be named {name}")),
+                );
+                continue;
+            }
+            if self.structs.contains_key(name) || self.enums.contains_key(name) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("type {name} is declared more than once"),
+                    )
+                    .with_primary(stmt.span, format!("{kind} redeclared here")),
+                );
+                continue;
+            }
+            match &stmt.kind {
+                StmtKind::StructDecl { fields, .. } => {
+                    self.check_unique(fields.iter().map(|(f, _)| f), "field", stmt.span);
+                    self.structs.insert(name.clone(), fields.clone());
+                }
+                StmtKind::EnumDecl { variants, .. } => {
+                    self.check_unique(variants.iter().map(|(v, _)| v), "variant", stmt.span);
+                    self.enums.insert(name.clone(), variants.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_unique<'n>(
+        &mut self,
+        names: impl Iterator<Item = &'n String>,
+        what: &str,
+        span: Span,
+    ) {
+        let mut seen = HashSet::new();
+        for name in names {
+            if !seen.insert(name) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("{what} {name} is declared more than once"),
+                    )
+                    .with_primary(span, ""),
+                );
+            }
+        }
+    }
+
+    /// Reports named types in `ty` that are neither structs nor enums.
+    fn check_type(&mut self, ty: &Type, span: Span) {
+        match ty {
+            Type::Array(elem) | Type::Set(elem) => self.check_type(elem, span),
+            Type::Option(inner) => self.check_type(inner, span),
+            Type::Record(fields) => {
+                for (_, ty) in fields {
+                    self.check_type(ty, span);
+                }
+            }
+            Type::Map(key, value) | Type::Result(key, value) => {
+                self.check_type(key, span);
+                self.check_type(value, span);
+            }
+            Type::Named(name)
+                if !self.structs.contains_key(name) && !self.enums.contains_key(name) =>
+            {
+                self.diags.push(
+                    Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown type: {name}"))
+                        .with_primary(span, format!("`{name}` is used here"))
+                        .with_help("declare it with `struct` or `enum`, or use a built-in type"),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn collect_tools(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            if let StmtKind::ToolDecl {
+                name,
+                input,
+                output,
+                cap,
+            } = &stmt.kind
+            {
+                if self.tools.contains_key(name) {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::DUPLICATE_DECLARATION,
+                            format!("tool {name} is declared more than once"),
+                        )
+                        .with_primary(stmt.span, "redeclared here"),
+                    );
+                    continue;
+                }
+                self.check_unique(input.iter().map(|(f, _)| f), "input field", stmt.span);
+                self.check_unique(output.iter().map(|(f, _)| f), "output field", stmt.span);
+                // Reversed so that the first of any duplicated fields wins.
+                let input_map = input.iter().rev().cloned().collect::<HashMap<_, _>>();
+                let output_map = output.iter().rev().cloned().collect::<HashMap<_, _>>();
+                self.tools.insert(
+                    name.clone(),
+                    ToolSignature {
+                        input: input_map,
+                        output: output_map,
+                        cap: cap.clone(),
+                    },
+                );
+                self.tool_decls.push((name.clone(), stmt.span));
+            }
+        }
+    }
+
+    fn collect_derived_caps(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            let StmtKind::Narrow {
+                name,
+                parent,
+                tools,
+            } = &stmt.kind
+            else {
+                continue;
+            };
+            if let Some(first) = self.derived_caps.get(name) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("capability {name} is derived more than once"),
+                    )
+                    .with_primary(stmt.span, "derived again here")
+                    .with_secondary(first.span, "first derived here"),
+                );
+                continue;
+            }
+            self.derived_caps.insert(
+                name.clone(),
+                DerivedCap {
+                    parent: parent.clone(),
+                    tools: tools.clone(),
+                    span: stmt.span,
+                },
+            );
+        }
+    }
+
+    /// `cap` followed by the capabilities it was derived from, ending with
+    /// the root capability the runtime supplies.
+    fn lineage(&self, cap: &str) -> Vec<String> {
+        let mut lineage = vec![cap.to_string()];
+        while let Some(derived) = self.derived_caps.get(lineage.last().expect("non-empty")) {
+            // A cycle can only come from a use before definition, which is
+            // reported separately.
+            if lineage.contains(&derived.parent) {
+                break;
+            }
+            lineage.push(derived.parent.clone());
+        }
+        lineage
+    }
+
+    fn root_cap(&self, cap: &str) -> String {
+        self.lineage(cap).pop().expect("non-empty")
+    }
+
+    /// Reports a derived capability used by top-level code before its `narrow`.
+    fn check_cap_defined(&mut self, cap: &str, span: Span) {
+        if self.current_fn.is_some() || self.narrowed.contains(cap) {
+            return;
+        }
+        if let Some(derived) = self.derived_caps.get(cap) {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::USE_BEFORE_DEFINE,
+                    format!("{cap} is used before its definition"),
+                )
+                .with_primary(span, "used here")
+                .with_secondary(derived.span, "derived here"),
+            );
+        }
+    }
+
+    /// Reports top-level calls to functions that reach a derived capability
+    /// whose `narrow` comes after the call. Checked once every body is known,
+    /// since functions may be called before they are declared.
+    fn check_calls_before_narrow(&mut self) {
+        for (callee, span, narrowed) in std::mem::take(&mut self.top_level_calls) {
+            let reached = self.derived_caps_reached(&callee);
+            let Some(cap) = reached.iter().find(|cap| !narrowed.contains(*cap)) else {
+                continue;
+            };
+            let derived = &self.derived_caps[cap];
+            self.diags.push(
+                Diagnostic::error(
+                    codes::USE_BEFORE_DEFINE,
+                    format!("call to {callee} uses {cap} before its definition"),
+                )
+                .with_primary(span, format!("{callee} uses {cap}"))
+                .with_secondary(derived.span, "derived here")
+                .with_help(format!("move the call after the `narrow` of {cap}")),
+            );
+        }
+    }
+
+    /// Derived capabilities `name` uses, directly or through the functions it calls.
+    fn derived_caps_reached(&self, name: &str) -> BTreeSet<String> {
+        let mut reached = BTreeSet::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![name.to_string()];
+        while let Some(f) = stack.pop() {
+            if !seen.insert(f.clone()) {
+                continue;
+            }
+            reached.extend(self.fn_derived_uses.get(&f).into_iter().flatten().cloned());
+            stack.extend(self.fn_callees.get(&f).into_iter().flatten().cloned());
+        }
+        reached
+    }
+
+    fn check_narrow(&mut self, stmt: &Stmt, name: &str, parent: &str, tools: &[String]) {
+        self.check_cap_defined(parent, stmt.span);
+        if self.tools.values().any(|sig| sig.cap == name) {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::DUPLICATE_DECLARATION,
+                    format!("capability {name} is already declared by a tool"),
+                )
+                .with_primary(stmt.span, "")
+                .with_help("give the derived capability a new name"),
+            );
+        }
+        let root = self.root_cap(parent);
+        for tool in tools {
+            if let Some(derived) = self.derived_caps.get(parent) {
+                if !derived.tools.contains(tool) {
+                    let diag = Diagnostic::error(
+                        codes::CAP_OUT_OF_SCOPE,
+                        format!(
+                            "cannot narrow {parent} to {tool}: {parent} only grants {}",
+                            derived.tools.join(", ")
+                        ),
+                    )
+                    .with_primary(stmt.span, "narrowing cannot widen a capability")
+                    .with_secondary(derived.span, format!("{parent} is derived here"));
+                    self.diags.push(diag);
+                    continue;
+                }
+            }
+            match self.tools.get(tool) {
+                None => self.diags.push(
+                    Diagnostic::error(
+                        codes::UNKNOWN_TOOL,
+                        format!("unknown tool declaration: {tool}"),
+                    )
+                    .with_primary(stmt.span, ""),
+                ),
+                Some(sig) if sig.cap != root => {
+                    let message = format!(
+                        "tool {tool} requires cap {}, but {name} derives from {root}",
+                        sig.cap
+                    );
+                    self.diags.push(
+                        Diagnostic::error(codes::TOOL_CAP_MISMATCH, message)
+                            .with_primary(stmt.span, ""),
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        self.caps.insert(root);
+        self.narrowed.insert(name.to_string());
+    }
+
+    /// Warns about tools that are declared but never called.
+    fn check_unused_tools(&mut self) {
+        for (name, span) in std::mem::take(&mut self.tool_decls) {
+            if !self.called_tools.contains(&name) {
+                self.diags.push(
+                    Diagnostic::warning(codes::UNUSED_TOOL, format!("tool {name} is never called"))
+                        .with_primary(span, "declared here"),
+                );
+            }
+        }
+    }
+
+    fn collect_fns(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            let StmtKind::FnDecl(decl) = &stmt.kind else {
+                continue;
+            };
+            if self.fns.contains_key(&decl.name) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("function {} is declared more than once", decl.name),
+                    )
+                    .with_primary(decl.span, "redeclared here"),
+                );
+                continue;
+            }
+            self.fns.insert(
+                decl.name.clone(),
+                FnSig {
+                    params: decl.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                    ret: decl.ret.clone(),
+                    effect: decl.effect.clone(),
+                },
+            );
+        }
+    }
+
+    /// The type of variable `name`, marking it used; `None` if it is not in
+    /// scope.
+    fn lookup(&mut self, name: &str) -> Option<Option<Type>> {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))?;
+        binding.used = true;
+        Some(binding.ty.clone())
+    }
+
+    /// Brings `name` into the innermost scope, warning if it hides another
+    /// variable.
+    fn define(&mut self, name: &str, ty: Option<Type>, span: Span, mutable: bool) {
+        let previous = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|b| b.span);
+        if let (Some(previous), false) = (previous, name.starts_with('_')) {
+            self.lints.push(
+                Diagnostic::warning(
+                    codes::SHADOWED_VARIABLE,
+                    format!("{name} shadows an earlier variable"),
+                )
+                .with_primary(span, format!("`{name}` is redefined here"))
+                .with_secondary(previous, "previously defined here")
+                .with_help(format!(
+                    "choose a different name, or declare `let mut {name}` and assign to it"
+                )),
+            );
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(replaced) = scope.insert(name.to_string(), Binding::new(ty, span, mutable))
+            {
+                self.lint_unused(name, &replaced);
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let mut bindings = scope.into_iter().collect::<Vec<_>>();
+        bindings.sort_by_key(|(_, b)| b.span.start);
+        for (name, binding) in bindings {
+            self.lint_unused(&name, &binding);
+        }
+    }
+
+    fn lint_unused(&mut self, name: &str, binding: &Binding) {
+        if name.starts_with('_') {
+            return;
+        }
+        if !binding.used {
+            self.lints.push(
+                Diagnostic::warning(codes::UNUSED_VARIABLE, format!("unused variable: {name}"))
+                    .with_primary(binding.span, "never read")
+                    .with_help(format!("prefix it with an underscore: `_{name}`")),
+            );
+        } else if binding.mutable && !binding.assigned {
+            self.lints.push(
+                Diagnostic::warning(
+                    codes::UNUSED_MUT,
+                    format!("variable {name} does not need to be mutable"),
+                )
+                .with_primary(binding.span, "never assigned to")
+                .with_help("remove `mut`"),
+            );
+        }
+    }
+
+    /// Reports a variable that is not in scope.
+    fn unknown_variable(&mut self, name: &str, span: Span) {
+        let later = self
+            .pending_lets
+            .iter()
+            .rev()
+            .flatten()
+            .find(|(n, _)| n == name)
+            .map(|(_, span)| *span);
+        self.diags.push(match later {
+            Some(def) => Diagnostic::error(
+                codes::USE_BEFORE_DEFINE,
+                format!("{name} is used before its definition"),
+            )
+            .with_primary(span, "used here")
+            .with_secondary(def, format!("`{name}` is defined here")),
+            None => Diagnostic::error(codes::UNKNOWN_VARIABLE, format!("unknown variable: {name}"))
+                .with_primary(span, "not found in this scope"),
+        });
+    }
+
+    fn check_stmts(&mut self, stmts: &[Stmt]) -> Effect {
+        let lets = stmts
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Let { name, .. } => Some((name.clone(), stmt.span)),
+                _ => None,
+            })
+            .collect();
+        self.pending_lets.push(lets);
+        let effect = stmts.iter().fold(Effect::PURE, |effect, stmt| {
+            effect.join(self.check_stmt(stmt))
+        });
+        self.pending_lets.pop();
+        effect
+    }
+
+    fn check_block(&mut self, block: &Block) -> Effect {
+        self.push_scope();
+        let effect = self.check_stmts(&block.stmts);
+        self.pop_scope();
+        effect
+    }
+
+    fn check_assign(&mut self, stmt: &Stmt, name: &str, expr: &Expr) {
+        let target = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .map(|b| {
+                b.assigned = true;
+                (b.ty.clone(), b.mutable, b.span)
+            });
+        let Some((ty, mutable, def)) = target else {
+            self.infer_expr(expr);
+            self.unknown_variable(name, stmt.span);
+            return;
+        };
+        if !mutable {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::IMMUTABLE_ASSIGNMENT,
+                    format!("cannot assign to immutable variable {name}"),
+                )
+                .with_primary(stmt.span, "cannot assign twice")
+                .with_secondary(def, format!("`{name}` is defined here"))
+                .with_help(format!("declare it with `let mut {name}`")),
+            );
+        }
+        let actual = self.infer_hinted(expr, ty.as_ref());
+        if let (Some(ty), Some(actual)) = (ty, actual) {
+            if ty != actual {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("cannot assign {actual} to {name} of type {ty}"),
+                    )
+                    .with_primary(expr.span, format!("this is {actual}")),
+                );
+            }
+        }
+    }
+
+    /// Reports Int arithmetic on constant operands that would fail at run
+    /// time. Operands that fail to fold were reported already.
+    fn check_constant_arithmetic(&mut self, expr: &Expr, lhs: &Expr, op: BinOp, rhs: &Expr) {
+        let divisor = const_int(rhs);
+        if op == BinOp::Div && divisor == Some(0) {
+            self.diags.push(
+                Diagnostic::error(codes::DIVISION_BY_ZERO, "division by zero")
+                    .with_primary(rhs.span, "this divisor is always zero"),
+            );
+            return;
+        }
+        if let (Some(l), Some(r)) = (const_int(lhs), divisor) {
+            if op.checked_int(l, r).is_none() {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::CONSTANT_OVERFLOW,
+                        format!("integer overflow: {l} {} {r}", op.symbol()),
+                    )
+                    .with_primary(expr.span, "this does not fit in an Int"),
+                );
+            }
+        }
+    }
+
+    fn check_condition(&mut self, cond: &Expr) {
+        if let Some(ty) = self.infer_expr(cond) {
+            if ty != Type::Bool {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("condition must be Bool, got {ty}"),
+                    )
+                    .with_primary(cond.span, format!("this is {ty}")),
+                );
+            }
+        }
+    }
+
+    /// Checks one statement and returns the effect it may perform, including
+    /// the effects of any functions it calls.
+    fn check_stmt(&mut self, stmt: &Stmt) -> Effect {
+        let outer = std::mem::replace(&mut self.call_effect, Effect::PURE);
+        let effect = self.check_stmt_kind(stmt);
+        let calls = std::mem::replace(&mut self.call_effect, outer);
+        effect.join(calls)
+    }
+
+    fn check_fn(&mut self, decl: &FnDecl) {
+        for ty in decl.params.iter().map(|(_, ty)| ty).chain([&decl.ret]) {
+            self.check_type(ty, decl.span);
+        }
+        let Some(sig) = self.fns.get(&decl.name).cloned() else {
+            return;
+        };
+        // Function bodies only see their parameters.
+        let scopes = std::mem::take(&mut self.scopes);
+        let pending_lets = std::mem::take(&mut self.pending_lets);
+        self.push_scope();
+        for (name, ty) in &decl.params {
+            self.define(name, Some(ty.clone()), decl.span, false);
+        }
+        let outer = self.current_fn.replace((decl.name.clone(), sig));
+        self.check_stmts(&decl.body.stmts);
+        self.current_fn = outer;
+        self.pop_scope();
+        self.scopes = scopes;
+        self.pending_lets = pending_lets;
+
+        if decl.ret != Type::Unit && !always_returns(&decl.body) {
+            let end = decl.body.span.end;
+            self.diags.push(
+                Diagnostic::error(
+                    codes::MISSING_RETURN,
+                    format!(
+                        "function {} may finish without returning a value",
+                        decl.name
+                    ),
+                )
+                .with_primary(
+                    Span::new(decl.body.span.file, end.saturating_sub(1), end),
+                    format!("expected a {} to be returned before here", decl.ret),
+                ),
+            );
+        }
+    }
+
+    fn check_return(&mut self, stmt: &Stmt, expr: Option<&Expr>) {
+        let hint = self.current_fn.as_ref().map(|(_, sig)| sig.ret.clone());
+        // A bare `return;` returns Unit.
+        let actual = expr.map_or(Some(Type::Unit), |e| self.infer_hinted(e, hint.as_ref()));
+        let Some((name, sig)) = &self.current_fn else {
+            self.diags.push(
+                Diagnostic::error(codes::INVALID_RETURN, "return outside of a function")
+                    .with_primary(stmt.span, ""),
+            );
+            return;
+        };
+        match actual {
+            Some(actual) if actual != sig.ret => {
+                let message = format!("function {name} returns {}, got {actual}", sig.ret);
+                self.diags.push(
+                    Diagnostic::error(codes::INVALID_RETURN, message).with_primary(stmt.span, ""),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks that `effect`, performed at `span` using `caps`, stays within
+    /// the declared bound of the enclosing function, or of the program for
+    /// top-level code.
+    fn check_effect_bound(&mut self, span: Span, what: &str, effect: Effect, caps: &[String]) {
+        let (owner, bound, context, declared) = match (&self.current_fn, &self.program_effects) {
+            (Some((name, sig)), _) => {
+                let annotation = sig.effect.effect.annotation();
+                (
+                    format!("function {name}"),
+                    &sig.effect,
+                    format!("`{annotation}` function {name}"),
+                    format!("{name} is declared `{annotation}`"),
+                )
+            }
+            (None, Some(bound)) => {
+                let attribute = effects_attribute(bound);
+                (
+                    "the program".to_string(),
+                    bound,
+                    format!("a `{attribute}` program"),
+                    format!("the program is declared `{attribute}`"),
+                )
+            }
+            (None, None) => return,
+        };
+        if !effect.within(bound.effect) {
+            let excess = effect.without(bound.effect);
+            let diag = Diagnostic::error(
+                codes::EFFECT_NOT_ALLOWED,
+                format!("{what} has effect `{excess}`, which is not allowed in {context}"),
+            )
+            .with_primary(span, format!("performs `{excess}`"))
+            .with_secondary(bound.span, declared);
+            self.diags.push(diag);
+            return;
+        }
+        if !effect.contains(EffectKind::Tool) || bound.caps.is_empty() {
+            return;
+        }
+        let message = if caps.is_empty() {
+            format!(
+                "{what} may use any capability, but {owner} only declares {}",
+                bound.caps.join(", ")
+            )
+        } else if let Some(cap) = caps
+            .iter()
+            .find(|cap| !self.lineage(cap).iter().any(|c| bound.caps.contains(c)))
+        {
+            format!("{what} uses capability {cap}, which {owner} does not declare")
+        } else {
+            return;
+        };
+        self.diags.push(
+            Diagnostic::error(codes::CAP_NOT_DECLARED, message)
+                .with_primary(span, "")
+                .with_secondary(bound.span, "declared capabilities"),
+        );
+    }
+
+    /// Checks a call to a user-defined function and returns its result type.
+    fn infer_call(&mut self, expr: &Expr, callee: &str, args: &[Expr]) -> Option<Type> {
+        let Some(sig) = self
+            .fns
+            .get(callee)
+            .cloned()
+            .or_else(|| builtin_sig(callee, expr.span))
+        else {
+            if callee == "len" {
+                return self.infer_len(expr, args);
+            }
+            for arg in args {
+                self.infer_expr(arg);
+            }
+            self.diags.push(
+                Diagnostic::error(
+                    codes::UNKNOWN_FUNCTION,
+                    format!("unknown function: {callee}"),
+                )
+                .with_primary(expr.span, "no `fn` declaration with this name"),
+            );
+            return None;
+        };
+        if self.fns.contains_key(callee) {
+            match &self.current_fn {
+                Some((caller, _)) => {
+                    self.fn_callees
+                        .entry(caller.clone())
+                        .or_default()
+                        .insert(callee.to_string());
+                }
+                None => self.top_level_calls.push((
+                    callee.to_string(),
+                    expr.span,
+                    self.narrowed.clone(),
+                )),
+            }
+        }
+        let arg_types = args
+            .iter()
+            .enumerate()
+            .map(|(i, a)| self.infer_hinted(a, sig.params.get(i)))
+            .collect::<Vec<_>>();
+        self.call_effect = self.call_effect.join(sig.effect.effect);
+        self.check_effect_bound(
+            expr.span,
+            &format!("call to {callee}"),
+            sig.effect.effect,
+            &sig.effect.caps,
+        );
+        if args.len() != sig.params.len() {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::ARITY_MISMATCH,
+                    format!(
+                        "function {callee} takes {} argument(s), got {}",
+                        sig.params.len(),
+                        args.len()
+                    ),
+                )
+                .with_primary(expr.span, ""),
+            );
+        } else {
+            for ((arg, actual), expected) in args.iter().zip(arg_types).zip(&sig.params) {
+                match actual {
+                    Some(actual) if actual != *expected => self.diags.push(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("argument to {callee} expected {expected}, got {actual}"),
+                        )
+                        .with_primary(arg.span, format!("this is {actual}")),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+        Some(sig.ret)
+    }
+
+    /// `len(x)` counts array elements, map entries, set members, string
+    /// characters or bytes.
+    fn infer_len(&mut self, expr: &Expr, args: &[Expr]) -> Option<Type> {
+        let [arg] = args else {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::ARITY_MISMATCH,
+                    format!("function len takes 1 argument(s), got {}", args.len()),
+                )
+                .with_primary(expr.span, ""),
+            );
+            return None;
+        };
+        let ty = self.infer_expr(arg)?;
+        match ty {
+            Type::Array(_) | Type::Map(..) | Type::Set(_) | Type::String | Type::Bytes => {
+                Some(Type::Int)
+            }
+            _ => {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("len cannot be applied to {ty}"),
+                    )
+                    .with_primary(arg.span, format!("this is {ty}")),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_stmt_kind(&mut self, stmt: &Stmt) -> Effect {
+        match &stmt.kind {
+            StmtKind::ToolDecl { input, output, .. } => {
+                for (_, ty) in input.iter().chain(output) {
+                    self.check_type(ty, stmt.span);
+                }
+                Effect::PURE
+            }
+            StmtKind::StructDecl { fields, .. } => {
+                for (_, ty) in fields {
+                    self.check_type(ty, stmt.span);
+                }
+                Effect::PURE
+            }
+            StmtKind::EnumDecl { variants, .. } => {
+                for ty in variants.iter().flat_map(|(_, payload)| payload) {
+                    self.check_type(ty, stmt.span);
+                }
+                Effect::PURE
+            }
+            StmtKind::Match { scrutinee, arms } => {
+                let effects = self.check_match(scrutinee, arms, stmt.span, |c, body| {
+                    c.check_stmts(&body.stmts)
+                });
+                effects.into_iter().fold(Effect::PURE, Effect::join)
+            }
+            StmtKind::FnDecl(decl) => {
+                self.check_fn(decl);
+                Effect::PURE
+            }
+            StmtKind::Return { expr } => {
+                self.check_return(stmt, expr.as_ref());
+                Effect::PURE
+            }
+            StmtKind::Expr { expr } => {
+                self.infer_expr(expr);
+                Effect::PURE
+            }
+            StmtKind::Let {
+                name,
+                mutable,
+                ty,
+                expr,
+            } => {
+                if let Some(ty) = ty {
+                    self.check_type(ty, stmt.span);
+                }
+                let actual = self.infer_hinted(expr, ty.as_ref());
+                if let (Some(declared), Some(actual)) = (ty, &actual) {
+                    if declared != actual {
+                        self.diags.push(
+                            Diagnostic::error(
+                                codes::TYPE_MISMATCH,
+                                format!("let {name} expected {declared}, got {actual}"),
+                            )
+                            .with_primary(expr.span, format!("this is {actual}")),
+                        );
+                    }
+                }
+                self.define(name, ty.clone().or(actual), stmt.span, *mutable);
+                Effect::PURE
+            }
+            StmtKind::Assign { name, expr } => {
+                self.check_assign(stmt, name, expr);
+                Effect::PURE
+            }
+            StmtKind::Print { expr } => {
+                self.infer_expr(expr);
+                Effect::PURE
+            }
+            StmtKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                self.check_condition(cond);
+                let then_effect = self.check_block(then_block);
+                let else_effect = else_block
+                    .as_ref()
+                    .map_or(Effect::PURE, |b| self.check_block(b));
+                then_effect.join(else_effect)
+            }
+            StmtKind::While { cond, body } => {
+                self.check_condition(cond);
+                self.check_block(body)
+            }
+            StmtKind::For { var, iter, body } => {
+                let elem = self.infer_expr(iter).and_then(|ty| match ty {
+                    Type::Array(elem) | Type::Set(elem) | Type::Map(elem, _) => Some(*elem),
+                    ty => {
+                        self.diags.push(
+                            Diagnostic::error(
+                                codes::TYPE_MISMATCH,
+                                format!("cannot iterate over {ty}"),
+                            )
+                            .with_primary(iter.span, format!("this is {ty}"))
+                            .with_note("`for` loops iterate over arrays, sets and map keys"),
+                        );
+                        None
+                    }
+                });
+                self.push_scope();
+                self.define(var, elem, stmt.span, false);
+                let effect = self.check_stmts(&body.stmts);
+                self.pop_scope();
+                effect
+            }
+            StmtKind::ToolCall(call) => {
+                self.check_tool_call(call, stmt.span);
+                Effect::TOOL
+            }
+            StmtKind::Narrow {
+                name,
+                parent,
+                tools,
+            } => {
+                self.check_narrow(stmt, name, parent, tools);
+                Effect::PURE
+            }
+        }
+    }
+
+    /// Returns the tool's output record type, if the tool is declared.
+    fn check_tool_call(&mut self, call: &ToolCall, span: Span) -> Option<Type> {
+        let ToolCall {
+            tool,
             input,
-            output,
             cap,
-        } = stmt
-        {
-            let input_map = input.iter().cloned().collect::<HashMap<_, _>>();
-            let output_map = output.iter().cloned().collect::<HashMap<_, _>>();
-            tools.insert(
-                name.clone(),
-                ToolSignature {
-                    input: input_map,
-                    output: output_map,
-                    cap: cap.clone(),
+            timeout_ms,
+        } = call;
+        self.check_cap_defined(cap, span);
+        if *timeout_ms == 0 {
+            self.lints.push(
+                Diagnostic::warning(codes::ZERO_TIMEOUT, "tool call with a zero timeout")
+                    .with_primary(span, "rejected at run time")
+                    .with_help("use a timeout greater than 0"),
+            );
+        }
+        if let (Some((f, _)), true) = (&self.current_fn, self.derived_caps.contains_key(cap)) {
+            self.fn_derived_uses
+                .entry(f.clone())
+                .or_default()
+                .insert(cap.clone());
+        }
+        if let Some(derived) = self.derived_caps.get(cap) {
+            if !derived.tools.contains(tool) {
+                let diag = Diagnostic::error(
+                    codes::CAP_OUT_OF_SCOPE,
+                    format!("capability {cap} does not grant tool {tool}"),
+                )
+                .with_primary(span, format!("called using `{cap}`"))
+                .with_secondary(
+                    derived.span,
+                    format!("{cap} is narrowed to {} here", derived.tools.join(", ")),
+                );
+                self.diags.push(diag);
+            }
+        }
+        let root = self.root_cap(cap);
+        self.caps.insert(root.clone());
+        self.called_tools.insert(tool.clone());
+        self.check_effect_bound(
+            span,
+            &format!("call to tool {tool}"),
+            Effect::TOOL,
+            std::slice::from_ref(cap),
+        );
+        let Some(sig) = self.tools.get(tool).cloned() else {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::UNKNOWN_TOOL,
+                    format!("unknown tool declaration: {tool}"),
+                )
+                .with_primary(span, "no `tool` declaration with this name")
+                .with_help(format!(
+                    "declare it with `tool {tool} input {{ ... }} output {{ ... }} cap {cap};`"
+                )),
+            );
+            for (_, expr) in input {
+                self.infer_expr(expr);
+            }
+            return None;
+        };
+        if sig.cap != root {
+            let mut diag = Diagnostic::error(
+                codes::TOOL_CAP_MISMATCH,
+                format!("tool {tool} requires cap {}, got {cap}", sig.cap),
+            )
+            .with_primary(span, format!("called using `{cap}`"))
+            .with_note(format!("`{tool}` is declared with `cap {}`", sig.cap));
+            if root != *cap {
+                diag = diag.with_note(format!("{cap} is derived from {root}"));
+            }
+            self.diags.push(diag);
+        }
+        let mut fields = sig.input.iter().collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        let mut seen = HashSet::new();
+        for (field, expr) in input {
+            if !seen.insert(field) {
+                self.infer_expr(expr);
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("field {field} is specified more than once"),
+                    )
+                    .with_primary(expr.span, ""),
+                );
+            } else if !sig.input.contains_key(field) {
+                self.infer_expr(expr);
+                let declared = fields.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>();
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::UNKNOWN_FIELD,
+                        format!("tool {tool} has no input field {field}"),
+                    )
+                    .with_primary(expr.span, "unknown field")
+                    .with_note(if declared.is_empty() {
+                        format!("`{tool}` declares no input fields")
+                    } else {
+                        format!("`{tool}` declares {}", declared.join(", "))
+                    }),
+                );
+            }
+        }
+        for (field, expected) in fields {
+            let Some((_, expr)) = input.iter().find(|(k, _)| k == field) else {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::MISSING_TOOL_FIELD,
+                        format!("missing required tool field: {field}"),
+                    )
+                    .with_primary(span, format!("missing `{field}`"))
+                    .with_note(format!("`{tool}` declares `{field}: {expected}`")),
+                );
+                continue;
+            };
+            if let Some(actual) = self.infer_hinted(expr, Some(expected)) {
+                if &actual != expected {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("tool field {field} expected {}, got {}", expected, actual),
+                        )
+                        .with_primary(expr.span, format!("this is {actual}")),
+                    );
+                }
+            }
+        }
+        let mut output = sig.output.into_iter().collect::<Vec<_>>();
+        output.sort_by(|a, b| a.0.cmp(&b.0));
+        Some(Type::Record(output))
+    }
+
+    /// Infers `expr`, taking the element types of empty collection literals
+    /// from `hint` when it is a collection of the same kind, and the types
+    /// `Some`, `None`, `Ok` and `Err` leave open from an `Option` or `Result` hint.
+    fn infer_hinted(&mut self, expr: &Expr, hint: Option<&Type>) -> Option<Type> {
+        match (&expr.kind, hint) {
+            (ExprKind::Array(items), Some(ty @ Type::Array(_)))
+            | (ExprKind::Set(items), Some(ty @ Type::Set(_)))
+                if items.is_empty() =>
+            {
+                Some(ty.clone())
+            }
+            (ExprKind::Map(entries), Some(ty @ Type::Map(..))) if entries.is_empty() => {
+                Some(ty.clone())
+            }
+            (ExprKind::Array(items), Some(Type::Array(elem))) => {
+                let elem = self.infer_elements("element", items, expr, Some(elem))?;
+                Some(Type::Array(Box::new(elem)))
+            }
+            (ExprKind::Map(entries), Some(Type::Map(_, value))) => {
+                self.infer_map(expr, entries, Some(value))
+            }
+            (
+                ExprKind::Variant {
+                    enum_name,
+                    variant,
+                    args,
                 },
+                hint,
+            ) if BUILTIN_ENUMS.contains(&enum_name.as_str()) => {
+                self.infer_builtin_variant(expr, variant, args, hint)
+            }
+            (ExprKind::Match { scrutinee, arms }, hint) => {
+                self.infer_match(expr, scrutinee, arms, hint)
+            }
+            _ => self.infer_expr(expr),
+        }
+    }
+
+    fn infer_map(
+        &mut self,
+        expr: &Expr,
+        entries: &[(Expr, Expr)],
+        value_hint: Option<&Type>,
+    ) -> Option<Type> {
+        let key = self.infer_elements("key", entries.iter().map(|(k, _)| k), expr, None);
+        // An empty map has already been reported through its keys.
+        let value = if entries.is_empty() {
+            None
+        } else {
+            self.infer_elements("value", entries.iter().map(|(_, v)| v), expr, value_hint)
+        };
+        let (key, value) = (key?, value?);
+        self.check_key_type(&key, expr.span)
+            .then(|| Type::Map(Box::new(key), Box::new(value)))
+    }
+
+    /// Infers the common type of collection elements; the first element
+    /// (inferred with `hint`) decides and the rest must match it.
+    fn infer_elements<'e>(
+        &mut self,
+        what: &str,
+        items: impl IntoIterator<Item = &'e Expr>,
+        container: &Expr,
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let mut items = items.into_iter();
+        let Some(first) = items.next() else {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::CANNOT_INFER,
+                    "cannot infer the type of an empty collection literal",
+                )
+                .with_primary(container.span, "")
+                .with_help("add a type annotation, e.g. `let xs: Array[Int] = [];`"),
+            );
+            return None;
+        };
+        let mut elem = self.infer_hinted(first, hint);
+        for item in items {
+            let actual = self.infer_hinted(item, elem.as_ref());
+            match (&elem, actual) {
+                (Some(expected), Some(actual)) if *expected != actual => {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("{what} types differ: expected {expected}, got {actual}"),
+                        )
+                        .with_primary(item.span, format!("this is {actual}"))
+                        .with_secondary(first.span, format!("this is {expected}")),
+                    );
+                    return None;
+                }
+                (None, actual) => elem = actual,
+                _ => {}
+            }
+        }
+        elem
+    }
+
+    fn check_key_type(&mut self, ty: &Type, span: Span) -> bool {
+        if ty.is_key() {
+            return true;
+        }
+        self.diags.push(
+            Diagnostic::error(
+                codes::INVALID_KEY_TYPE,
+                format!("{ty} cannot be used as a map key or set element"),
+            )
+            .with_primary(span, format!("this is {ty}"))
+            .with_note("keys must be Int, Bool, String or Bytes"),
+        );
+        false
+    }
+
+    fn infer_index(&mut self, target: &Expr, index: &Expr) -> Option<Type> {
+        let target_ty = self.infer_expr(target);
+        let (expected, result) = match target_ty? {
+            Type::Array(elem) => (Type::Int, *elem),
+            Type::Map(key, value) => (*key, *value),
+            ty => {
+                self.infer_expr(index);
+                self.diags.push(
+                    Diagnostic::error(codes::TYPE_MISMATCH, format!("cannot index into {ty}"))
+                        .with_primary(target.span, format!("this is {ty}"))
+                        .with_note("only arrays and maps can be indexed"),
+                );
+                return None;
+            }
+        };
+        let actual = self.infer_expr(index)?;
+        if actual != expected {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::TYPE_MISMATCH,
+                    format!("index expected {expected}, got {actual}"),
+                )
+                .with_primary(index.span, format!("this is {actual}")),
+            );
+        }
+        Some(result)
+    }
+
+    fn infer_struct(&mut self, expr: &Expr, name: &str, fields: &[(String, Expr)]) -> Option<Type> {
+        let Some(decl) = self.structs.get(name).cloned() else {
+            for (_, value) in fields {
+                self.infer_expr(value);
+            }
+            self.diags.push(
+                Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown struct: {name}"))
+                    .with_primary(expr.span, "no `struct` declaration with this name"),
+            );
+            return None;
+        };
+        let mut seen = HashSet::new();
+        for (field, value) in fields {
+            let expected = decl.iter().find(|(f, _)| f == field).map(|(_, ty)| ty);
+            let actual = self.infer_hinted(value, expected);
+            if !seen.insert(field) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE_DECLARATION,
+                        format!("field {field} is specified more than once"),
+                    )
+                    .with_primary(value.span, ""),
+                );
+                continue;
+            }
+            match (expected, actual) {
+                (None, _) => self.diags.push(
+                    Diagnostic::error(
+                        codes::UNKNOWN_FIELD,
+                        format!("struct {name} has no field {field}"),
+                    )
+                    .with_primary(value.span, "unknown field"),
+                ),
+                (Some(expected), Some(actual)) if *expected != actual => self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("field {field} expected {expected}, got {actual}"),
+                    )
+                    .with_primary(value.span, format!("this is {actual}")),
+                ),
+                _ => {}
+            }
+        }
+        for (field, ty) in &decl {
+            if !seen.contains(field) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::MISSING_FIELD,
+                        format!("missing field {field} in struct {name}"),
+                    )
+                    .with_primary(expr.span, format!("missing `{field}`"))
+                    .with_note(format!("`{name}` declares `{field}: {ty}`")),
+                );
+            }
+        }
+        Some(Type::Named(name.to_string()))
+    }
+
+    fn variant_payload(&mut self, enum_name: &str, variant: &str, span: Span) -> Option<Vec<Type>> {
+        let Some(variants) = self.enums.get(enum_name) else {
+            self.diags.push(
+                Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown enum: {enum_name}"))
+                    .with_primary(span, "no `enum` declaration with this name"),
+            );
+            return None;
+        };
+        let payload = variants
+            .iter()
+            .find(|(v, _)| v == variant)
+            .map(|(_, payload)| payload.clone());
+        if payload.is_none() {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::UNKNOWN_VARIANT,
+                    format!("enum {enum_name} has no variant {variant}"),
+                )
+                .with_primary(span, "unknown variant"),
             );
         }
+        payload
     }
 
-    for stmt in &program.statements {
-        match stmt {
-            Stmt::ToolDecl { .. } => {}
-            Stmt::Let { name, expr } => {
-                env.insert(name.clone(), infer_expr(expr, &env)?);
+    fn infer_variant(
+        &mut self,
+        expr: &Expr,
+        enum_name: &str,
+        variant: &str,
+        args: &[Expr],
+    ) -> Option<Type> {
+        let Some(payload) = self.variant_payload(enum_name, variant, expr.span) else {
+            for arg in args {
+                self.infer_expr(arg);
             }
-            Stmt::Print { expr } => {
-                let _ = infer_expr(expr, &env)?;
+            return None;
+        };
+        if payload.len() != args.len() {
+            self.diags.push(
+                Diagnostic::error(
+                    codes::ARITY_MISMATCH,
+                    format!(
+                        "variant {enum_name}::{variant} takes {} value(s), got {}",
+                        payload.len(),
+                        args.len()
+                    ),
+                )
+                .with_primary(expr.span, ""),
+            );
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let expected = payload.get(i);
+            match (expected, self.infer_hinted(arg, expected)) {
+                (Some(expected), Some(actual)) if *expected != actual => self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("{enum_name}::{variant} expected {expected}, got {actual}"),
+                    )
+                    .with_primary(arg.span, format!("this is {actual}")),
+                ),
+                _ => {}
             }
-            Stmt::ToolCall {
-                tool, input, cap, ..
+        }
+        Some(Type::Named(enum_name.to_string()))
+    }
+
+    /// `Some(x)` infers `Option[T]` from `x`; the other built-in variants
+    /// need an expected type.
+    fn infer_builtin_variant(
+        &mut self,
+        expr: &Expr,
+        variant: &str,
+        args: &[Expr],
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let arity = builtin_arity(variant);
+        if args.len() != arity {
+            for arg in args {
+                self.infer_expr(arg);
+            }
+            self.diags.push(
+                Diagnostic::error(
+                    codes::ARITY_MISMATCH,
+                    format!("{variant} takes {arity} value(s), got {}", args.len()),
+                )
+                .with_primary(expr.span, ""),
+            );
+            return None;
+        }
+        let expected = hint.and_then(|ty| Some((ty, builtin_payload(variant, ty)?)));
+        let Some((ty, payload)) = expected else {
+            if variant == "Some" {
+                return Some(Type::Option(Box::new(self.infer_expr(&args[0])?)));
+            }
+            for arg in args {
+                self.infer_expr(arg);
+            }
+            let diagnostic = match hint {
+                Some(hint) => Diagnostic::error(
+                    codes::TYPE_MISMATCH,
+                    format!("expected {hint}, found `{variant}`"),
+                )
+                .with_primary(expr.span, ""),
+                None => Diagnostic::error(
+                    codes::CANNOT_INFER,
+                    format!("cannot infer the type of `{variant}`"),
+                )
+                .with_primary(expr.span, "")
+                .with_help(if variant == "None" {
+                    "add a type annotation, e.g. `let x: Option[Int] = None;`"
+                } else {
+                    "add a type annotation, e.g. `let r: Result[Int, String] = Ok(1);`"
+                }),
+            };
+            self.diags.push(diagnostic);
+            return None;
+        };
+        for (arg, expected) in args.iter().zip(&payload) {
+            match self.infer_hinted(arg, Some(expected)) {
+                Some(actual) if actual != *expected => self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("{variant} expected {expected}, got {actual}"),
+                    )
+                    .with_primary(arg.span, format!("this is {actual}")),
+                ),
+                _ => {}
+            }
+        }
+        Some(ty.clone())
+    }
+
+    /// `expr?` unwraps an `Option` or `Result` inside a function returning
+    /// the same kind (and, for `Result`, the same error type).
+    fn infer_propagate(&mut self, expr: &Expr, inner: &Expr) -> Option<Type> {
+        let ty = self.infer_expr(inner)?;
+        let (value, kind) = match &ty {
+            Type::Option(value) => (value, "Option"),
+            Type::Result(value, _) => (value, "Result"),
+            _ => {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("`?` cannot be applied to {ty}"),
+                    )
+                    .with_primary(expr.span, format!("this is {ty}"))
+                    .with_note("`?` unwraps Option and Result values"),
+                );
+                return None;
+            }
+        };
+        let Some((name, sig)) = &self.current_fn else {
+            self.diags.push(
+                Diagnostic::error(codes::INVALID_RETURN, "`?` outside of a function")
+                    .with_primary(expr.span, "")
+                    .with_help(format!("use `match` to handle the {kind}")),
+            );
+            return Some((**value).clone());
+        };
+        let compatible = match (&ty, &sig.ret) {
+            (Type::Option(_), Type::Option(_)) => true,
+            (Type::Result(_, err), Type::Result(_, ret_err)) => err == ret_err,
+            _ => false,
+        };
+        if !compatible {
+            let needed = match &ty {
+                Type::Result(_, err) => format!("Result[_, {err}]"),
+                _ => "Option[_]".to_string(),
+            };
+            self.diags.push(
+                Diagnostic::error(
+                    codes::INVALID_RETURN,
+                    format!(
+                        "`?` on {ty} needs function {name} to return {needed}, but it returns {}",
+                        sig.ret
+                    ),
+                )
+                .with_primary(expr.span, format!("this is {ty}")),
+            );
+        }
+        Some((**value).clone())
+    }
+
+    /// Checks a pattern against the scrutinee type, collecting its bindings.
+    fn check_pattern(
+        &mut self,
+        pattern: &Pattern,
+        ty: Option<&Type>,
+        bindings: &mut HashMap<String, Binding>,
+    ) {
+        let literal = match &pattern.kind {
+            PatternKind::Wildcard => return,
+            PatternKind::Binding(name) => {
+                let binding = Binding::new(ty.cloned(), pattern.span, false);
+                if bindings.insert(name.clone(), binding).is_some() {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::DUPLICATE_DECLARATION,
+                            format!("{name} is bound more than once in the same pattern"),
+                        )
+                        .with_primary(pattern.span, ""),
+                    );
+                }
+                return;
+            }
+            PatternKind::Int(_) => Type::Int,
+            PatternKind::Bool(_) => Type::Bool,
+            PatternKind::String(_) => Type::String,
+            PatternKind::Variant {
+                enum_name,
+                variant,
+                args,
             } => {
-                let sig = tools.get(tool).ok_or_else(|| {
-                    CompileError::Type(format!("unknown tool declaration: {tool}"))
-                })?;
-                if sig.cap != *cap {
-                    return Err(CompileError::Type(format!(
-                        "tool {tool} requires cap {}, got {cap}",
-                        sig.cap
-                    )));
-                }
-                for (field, expected) in &sig.input {
-                    let (_, expr) = input.iter().find(|(k, _)| k == field).ok_or_else(|| {
-                        CompileError::Type(format!("missing required tool field: {field}"))
-                    })?;
-                    let actual = infer_expr(expr, &env)?;
-                    if &actual != expected {
-                        return Err(CompileError::Type(format!(
-                            "tool field {field} expected {:?}, got {:?}",
-                            expected, actual
-                        )));
+                let (payload, arity, name) = if BUILTIN_ENUMS.contains(&enum_name.as_str()) {
+                    let payload = ty.and_then(|ty| builtin_payload(variant, ty));
+                    if let (Some(ty), None) = (ty, &payload) {
+                        self.pattern_mismatch(pattern, ty, enum_name);
                     }
+                    (payload, Some(builtin_arity(variant)), variant.clone())
+                } else {
+                    let payload = self.variant_payload(enum_name, variant, pattern.span);
+                    if let (Some(ty), Some(_)) = (ty, &payload) {
+                        if *ty != Type::Named(enum_name.clone()) {
+                            self.pattern_mismatch(pattern, ty, enum_name);
+                        }
+                    }
+                    let arity = payload.as_ref().map(Vec::len);
+                    (payload, arity, format!("{enum_name}::{variant}"))
+                };
+                match arity {
+                    Some(arity) if arity != args.len() => self.diags.push(
+                        Diagnostic::error(
+                            codes::ARITY_MISMATCH,
+                            format!(
+                                "variant {name} has {arity} value(s), pattern has {}",
+                                args.len()
+                            ),
+                        )
+                        .with_primary(pattern.span, ""),
+                    ),
+                    _ => {}
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    let arg_ty = payload.as_ref().and_then(|p| p.get(i));
+                    self.check_pattern(arg, arg_ty, bindings);
                 }
-                effect = Effect::Tool;
-                caps.insert(cap.clone());
+                return;
+            }
+        };
+        if let Some(ty) = ty {
+            if *ty != literal {
+                self.pattern_mismatch(pattern, ty, literal);
             }
         }
     }
 
-    let mut required_caps = caps.into_iter().collect::<Vec<_>>();
-    required_caps.sort();
+    fn pattern_mismatch(&mut self, pattern: &Pattern, expected: &Type, found: impl Display) {
+        self.diags.push(
+            Diagnostic::error(
+                codes::TYPE_MISMATCH,
+                format!("pattern expected {expected}, found {found}"),
+            )
+            .with_primary(pattern.span, format!("this matches {found}")),
+        );
+    }
 
-    Ok(TypedProgram {
-        program,
-        effect,
-        tools,
-        manifest: CapabilityManifest { required_caps },
-    })
-}
+    /// Expression arms must agree on one type; `hint` is passed to each arm.
+    fn infer_match(
+        &mut self,
+        expr: &Expr,
+        scrutinee: &Expr,
+        arms: &[MatchArm<Expr>],
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let types = self.check_match(scrutinee, arms, expr.span, |c, body| {
+            c.infer_hinted(body, hint).map(|ty| (ty, body.span))
+        });
+        let mut types = types.into_iter().flatten();
+        let (first, first_span) = types.next()?;
+        for (ty, span) in types {
+            if ty != first {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("match arms have different types: expected {first}, got {ty}"),
+                    )
+                    .with_primary(span, format!("this is {ty}"))
+                    .with_secondary(first_span, format!("this is {first}")),
+                );
+                return None;
+            }
+        }
+        Some(first)
+    }
 
-fn infer_expr(expr: &Expr, env: &HashMap<String, Type>) -> Result<Type, CompileError> {
-    match expr {
-        Expr::Int(_) => Ok(Type::Int),
-        Expr::Bool(_) => Ok(Type::Bool),
-        Expr::String(_) => Ok(Type::String),
-        Expr::Var(v) => env
-            .get(v)
-            .copied()
-            .ok_or_else(|| CompileError::Type(format!("unknown variable: {v}"))),
-        Expr::Binary { lhs, rhs, .. } => {
-            let l = infer_expr(lhs, env)?;
-            let r = infer_expr(rhs, env)?;
-            if l == Type::Int && r == Type::Int {
-                Ok(Type::Int)
-            } else {
-                Err(CompileError::Type(
-                    "binary operations currently require Int operands".into(),
+    /// Checks each arm's pattern and body, then that the arms cover every
+    /// value of the scrutinee's type.
+    fn check_match<B, T>(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm<B>],
+        span: Span,
+        mut body: impl FnMut(&mut Self, &B) -> T,
+    ) -> Vec<T> {
+        let ty = self.infer_expr(scrutinee);
+        let mut patterns_ok = true;
+        let mut results = Vec::with_capacity(arms.len());
+        for arm in arms {
+            let mut bindings = HashMap::new();
+            let errors_before = self.diags.len();
+            self.check_pattern(&arm.pattern, ty.as_ref(), &mut bindings);
+            patterns_ok &= self.diags.len() == errors_before;
+            self.scopes.push(bindings);
+            results.push(body(self, &arm.body));
+            self.pop_scope();
+        }
+        if let (Some(ty), true) = (ty, patterns_ok) {
+            let patterns = arms.iter().map(|arm| &arm.pattern).collect::<Vec<_>>();
+            if let Some(missing) = missing_pattern(&patterns, &ty, &self.enums) {
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::NON_EXHAUSTIVE_MATCH,
+                        format!("non-exhaustive match: `{missing}` not covered"),
+                    )
+                    .with_primary(span, format!("`{missing}` is not matched"))
+                    .with_help(format!("add an arm for `{missing}` or a `_` arm")),
+                );
+            }
+        }
+        results
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> Option<Type> {
+        match &expr.kind {
+            ExprKind::Int(_) => Some(Type::Int),
+            ExprKind::Float(_) => Some(Type::Float),
+            ExprKind::Bool(_) => Some(Type::Bool),
+            ExprKind::String(_) => Some(Type::String),
+            ExprKind::Bytes(_) => Some(Type::Bytes),
+            ExprKind::Unit => Some(Type::Unit),
+            ExprKind::Template(parts) => {
+                for part in parts {
+                    if let TemplatePart::Expr(inner) = part {
+                        self.infer_expr(inner);
+                    }
+                }
+                Some(Type::String)
+            }
+            ExprKind::Var(v) => match self.lookup(v) {
+                Some(ty) => ty,
+                None => {
+                    self.unknown_variable(v, expr.span);
+                    None
+                }
+            },
+            ExprKind::Array(items) => {
+                let elem = self.infer_elements("element", items, expr, None)?;
+                Some(Type::Array(Box::new(elem)))
+            }
+            ExprKind::Set(items) => {
+                let elem = self.infer_elements("element", items, expr, None)?;
+                self.check_key_type(&elem, expr.span)
+                    .then(|| Type::Set(Box::new(elem)))
+            }
+            ExprKind::Map(entries) => self.infer_map(expr, entries, None),
+            ExprKind::Index {
+                expr: target,
+                index,
+            } => self.infer_index(target, index),
+            ExprKind::Struct { name, fields } => self.infer_struct(expr, name, fields),
+            ExprKind::Variant {
+                enum_name,
+                variant,
+                args,
+            } if BUILTIN_ENUMS.contains(&enum_name.as_str()) => {
+                self.infer_builtin_variant(expr, variant, args, None)
+            }
+            ExprKind::Variant {
+                enum_name,
+                variant,
+                args,
+            } => self.infer_variant(expr, enum_name, variant, args),
+            ExprKind::ToolCall(call) => {
+                self.call_effect = self.call_effect.join(Effect::TOOL);
+                self.check_tool_call(call, expr.span)
+            }
+            ExprKind::TryCall(call) => {
+                self.call_effect = self.call_effect.join(Effect::TOOL);
+                let output = self.check_tool_call(call, expr.span)?;
+                Some(Type::Result(
+                    Box::new(output),
+                    Box::new(Type::Named(TOOL_ERROR.to_string())),
                 ))
             }
+            ExprKind::Propagate(inner) => self.infer_propagate(expr, inner),
+            ExprKind::Field {
+                expr: target,
+                field,
+            } => {
+                let ty = self.infer_expr(target)?;
+                let fields = match &ty {
+                    Type::Named(name) => self.structs.get(name),
+                    Type::Record(fields) => Some(fields),
+                    _ => None,
+                };
+                let found = fields.and_then(|fields| {
+                    fields
+                        .iter()
+                        .find(|(f, _)| f == field)
+                        .map(|(_, t)| t.clone())
+                });
+                if found.is_none() {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::UNKNOWN_FIELD,
+                            format!("type {ty} has no field {field}"),
+                        )
+                        .with_primary(expr.span, "unknown field"),
+                    );
+                }
+                found
+            }
+            ExprKind::Match { scrutinee, arms } => self.infer_match(expr, scrutinee, arms, None),
+            ExprKind::Call { callee, args } => self.infer_call(expr, callee, args),
+            ExprKind::Unary { op, expr: inner } => {
+                let ty = self.infer_expr(inner)?;
+                let expected = match op {
+                    UnaryOp::Neg => "Int or Float",
+                    UnaryOp::Not => "Bool",
+                };
+                if matches!(
+                    (op, &ty),
+                    (UnaryOp::Neg, Type::Int | Type::Float) | (UnaryOp::Not, Type::Bool)
+                ) {
+                    return Some(ty);
+                }
+                self.diags.push(
+                    Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("operator `{}` cannot be applied to {ty}", op.symbol()),
+                    )
+                    .with_primary(inner.span, format!("this is {ty}"))
+                    .with_note(format!("`{}` expects {expected}", op.symbol())),
+                );
+                None
+            }
+            ExprKind::Binary { lhs, op, rhs } => {
+                let l = self.infer_expr(lhs);
+                let hint = l.as_ref().filter(|_| matches!(op, BinOp::Eq | BinOp::Ne));
+                let r = self.infer_hinted(rhs, hint);
+                let (l, r) = (l?, r?);
+                let result = match (op, &l, &r) {
+                    (op, Type::Int, Type::Int) if op.is_arithmetic() => {
+                        self.check_constant_arithmetic(expr, lhs, *op, rhs);
+                        Some(Type::Int)
+                    }
+                    (op, Type::Float, Type::Float) if op.is_arithmetic() => Some(Type::Float),
+                    (BinOp::Add, Type::String, Type::String) => Some(Type::String),
+                    (BinOp::Add, Type::Bytes, Type::Bytes) => Some(Type::Bytes),
+                    (BinOp::Eq | BinOp::Ne, l, r) if l == r => Some(Type::Bool),
+                    (op, Type::Int, Type::Int) | (op, Type::Float, Type::Float)
+                        if op.is_comparison() =>
+                    {
+                        Some(Type::Bool)
+                    }
+                    (BinOp::And | BinOp::Or, Type::Bool, Type::Bool) => Some(Type::Bool),
+                    _ => None,
+                };
+                if result.is_none() {
+                    self.diags.push(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!(
+                                "operator `{}` cannot be applied to {l} and {r}",
+                                op.symbol()
+                            ),
+                        )
+                        .with_primary(expr.span, "")
+                        .with_secondary(lhs.span, format!("{l}"))
+                        .with_secondary(rhs.span, format!("{r}")),
+                    );
+                }
+                result
+            }
         }
     }
 }
@@ -0,0 +1,137 @@
+mod common;
+
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck};
+use common::diagnostics;
+
+const SHAPES: &str =
+    "struct Point { x: Int, y: Int }\nenum Shape { Empty, Dot(Point), Rect(Point, Point) }\n";
+
+#[test]
+fn typechecks_constructors_fields_and_matches() {
+    let src = format!(
+        "{SHAPES}fn width(s: Shape) -> Int {{\n  match s {{\n    Shape::Rect(a, b) => {{ return b.x - a.x; }}\n    _ => {{ return 0; }}\n  }}\n}}\nlet p = Point {{ y: 2, x: 1 }};\nlet s = Shape::Rect(p, Point {{ x: 4, y: 6 }});\nlet name = match s {{ Shape::Empty => \"empty\", Shape::Dot(_) => \"dot\", Shape::Rect(_, _) => \"rect\" }};\nprint name + \"{{width(s)}}\";\nlet shapes: Array[Shape] = [Shape::Empty, Shape::Dot(p)];\nprint shapes[1] == Shape::Empty;\nif p.x == 1 {{ print p.y; }}\n"
+    );
+    typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+}
+
+#[test]
+fn reports_missing_variants_and_nested_patterns() {
+    let src = format!(
+        "{SHAPES}enum Flag {{ On(Bool), Off }}\nlet s = Shape::Empty;\nmatch s {{\n  Shape::Empty => {{ }}\n  Shape::Rect(_, _) => {{ }}\n}}\nlet f = Flag::Off;\nprint match f {{ Flag::On(true) => 1, Flag::Off => 0 }};\nprint match 3 {{ 0 => \"zero\", 1 => \"one\" }};\n"
+    );
+    let diags = diagnostics(&src);
+    let messages = diags.iter().map(|(_, m)| m.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "non-exhaustive match: `Shape::Dot(_)` not covered",
+            "non-exhaustive match: `Flag::On(false)` not covered",
+            "non-exhaustive match: `_` not covered",
+        ]
+    );
+    assert!(diags.iter().all(|(c, _)| *c == codes::NON_EXHAUSTIVE_MATCH));
+}
+
+#[test]
+fn rejects_ill_formed_constructors_and_patterns() {
+    let src = format!(
+        "{SHAPES}let a = Point {{ x: 1 }};\nlet b = Point {{ x: 1, y: 2, z: 3 }};\nlet c = Point {{ x: true, y: 2 }};\nlet d = Shape::Circle(1);\nlet e = Shape::Dot(1, 2);\nprint a.z;\nprint 1.x;\nlet q = Line {{ }};\nmatch e {{\n  Shape::Dot(x, x) => {{ }}\n  1 => {{ }}\n  _ => {{ }}\n}}\nprint match 1 {{ 1 => \"one\", _ => 0 }};\n"
+    );
+    let codes = diagnostics(&src)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![
+            codes::MISSING_FIELD,
+            codes::UNKNOWN_FIELD,
+            codes::TYPE_MISMATCH,
+            codes::UNKNOWN_VARIANT,
+            codes::ARITY_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::UNKNOWN_FIELD,
+            codes::UNKNOWN_FIELD,
+            codes::UNKNOWN_TYPE,
+            codes::ARITY_MISMATCH,
+            codes::DUPLICATE_DECLARATION,
+            codes::TYPE_MISMATCH,
+            codes::TYPE_MISMATCH,
+        ]
+    );
+}
+
+#[test]
+fn rejects_unknown_and_duplicate_types() {
+    let src = "struct A { n: Int, n: Int }\nenum A { X }\nfn f(g: Ghost) -> Int { return 1; }\nlet xs: Array[Phantom] = [];\n";
+    let codes = diagnostics(src)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![
+            codes::DUPLICATE_DECLARATION,
+            codes::DUPLICATE_DECLARATION,
+            codes::UNKNOWN_TYPE,
+            codes::UNKNOWN_TYPE,
+        ]
+    );
+    // Diagnostic codes are public; unknown types are a typechecker error.
+    assert_eq!(codes::UNKNOWN_TYPE, "AX0221");
+}
+
+#[test]
+fn rejects_type_declarations_inside_blocks_and_struct_literals_in_conditions() {
+    let err = parse_program("if true { struct P { x: Int } }").expect_err("nested struct");
+    assert!(err
+        .to_string()
+        .contains("type declarations must appear at the top level"));
+    // `P {` after `if` opens the block, so `x: 1` is not a statement.
+    assert!(parse_program("struct P { x: Int }\nif P { x: 1 }.x == 1 { }").is_err());
+    parse_program("struct P { x: Int }\nif (P { x: 1 }).x == 1 { }").expect("parenthesized");
+}
+
+#[test]
+fn functions_ending_in_exhaustive_returning_match_need_no_trailing_return() {
+    let src = "enum Sign { Neg, Zero, Pos }\nfn sign(n: Int) -> Sign {\n  if n < 0 { return Sign::Neg; }\n  match n == 0 {\n    true => { return Sign::Zero; }\n    false => { return Sign::Pos; }\n  }\n}\nprint sign(3);\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+}
+
+#[test]
+fn errors_in_arm_bodies_do_not_hide_missing_arms() {
+    let src = format!(
+        "{SHAPES}let s = Shape::Empty;\nmatch s {{\n  Shape::Empty => {{ print 1 + true; }}\n  Shape::Dot(_) => {{ }}\n}}\n"
+    );
+    let codes = diagnostics(&src)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![codes::TYPE_MISMATCH, codes::NON_EXHAUSTIVE_MATCH]
+    );
+}
+
+#[test]
+fn matches_on_recursive_enums_terminate() {
+    let list = "enum List { Nil, Cons(Int, List) }\nlet l = List::Cons(1, List::Nil);\n";
+    let src = format!(
+        "{list}print match l {{ List::Nil => 0, List::Cons(_, List::Nil) => 1, List::Cons(_, List::Cons(_, _)) => 2 }};\nprint match l {{ List::Cons(n, _) => n, _ => 0 }};\n"
+    );
+    typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    let src = format!(
+        "{list}print match l {{ List::Nil => 0, List::Cons(_, List::Nil) => 1 }};\nprint match l {{ List::Cons(_, _) => 1 }};\n"
+    );
+    let messages = diagnostics(&src)
+        .into_iter()
+        .map(|(_, m)| m)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "non-exhaustive match: `List::Cons(_, List::Cons(_, _))` not covered",
+            "non-exhaustive match: `List::Nil` not covered",
+        ]
+    );
+}
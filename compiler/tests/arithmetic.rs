@@ -0,0 +1,40 @@
+mod common;
+
+use axiom_compiler::diagnostic::codes;
+use common::diagnostics;
+
+#[test]
+fn constant_division_by_zero_is_a_compile_error() {
+    let src = "print 1 / 0;\nprint 10 / (2 - 2);\nprint (1 / 0) / 0;\nlet zero = 0;\nprint 1 / zero;\nprint 1.0 / 0.0;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (codes::DIVISION_BY_ZERO, "division by zero".to_string()),
+            (codes::DIVISION_BY_ZERO, "division by zero".to_string()),
+            (codes::DIVISION_BY_ZERO, "division by zero".to_string()),
+            (codes::DIVISION_BY_ZERO, "division by zero".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn constant_overflow_is_a_compile_error() {
+    let src = "print 9223372036854775807 + 1;\nprint -9223372036854775807 - 2;\nprint (4611686018427387904 * 2) + 1;\nprint -9223372036854775807 - 1;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::CONSTANT_OVERFLOW,
+                "integer overflow: 9223372036854775807 + 1".to_string()
+            ),
+            (
+                codes::CONSTANT_OVERFLOW,
+                "integer overflow: -9223372036854775807 - 2".to_string()
+            ),
+            (
+                codes::CONSTANT_OVERFLOW,
+                "integer overflow: 4611686018427387904 * 2".to_string()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck};
+use common::{diagnostics, warnings};
+
+#[test]
+fn only_mutable_bindings_can_be_assigned() {
+    let src = "let n = 1;\nn = 2;\nlet mut m = 1;\nm = \"two\";\nk = 3;\nfor i in [1] {\n  i = 2;\n}\nprint n + m;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::IMMUTABLE_ASSIGNMENT,
+                "cannot assign to immutable variable n".to_string()
+            ),
+            (
+                codes::TYPE_MISMATCH,
+                "cannot assign String to m of type Int".to_string()
+            ),
+            (codes::UNKNOWN_VARIABLE, "unknown variable: k".to_string()),
+            (
+                codes::IMMUTABLE_ASSIGNMENT,
+                "cannot assign to immutable variable i".to_string()
+            ),
+        ]
+    );
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("immutable");
+    assert_eq!(
+        err.diagnostics[0].help.as_deref(),
+        Some("declare it with `let mut n`")
+    );
+}
+
+#[test]
+fn mutable_bindings_accept_assignments_from_nested_blocks() {
+    let src = "let mut total = 0;\nlet mut i = 0;\nwhile i < 3 {\n  total = total + i;\n  i = i + 1;\n}\nlet mut seen: Array[Int] = [];\nseen = [total];\nprint seen;\n";
+    assert_eq!(warnings(src), vec![]);
+}
+
+#[test]
+fn variables_used_before_their_definition_are_reported() {
+    let src = "print later;\nlet x = x + 1;\nif true {\n  print inner;\n  let inner = 1;\n}\nlet later = 2;\nprint missing;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::USE_BEFORE_DEFINE,
+                "later is used before its definition".to_string()
+            ),
+            (
+                codes::USE_BEFORE_DEFINE,
+                "x is used before its definition".to_string()
+            ),
+            (
+                codes::USE_BEFORE_DEFINE,
+                "inner is used before its definition".to_string()
+            ),
+            (
+                codes::UNKNOWN_VARIABLE,
+                "unknown variable: missing".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn unused_shadowed_and_needlessly_mutable_bindings_are_warned_about() {
+    let src = "let a = 1;\nlet a = \"one\";\nlet mut b = 2;\nlet _ignored = 3;\nfn f(x: Int, _y: Int) -> Int {\n  let unused = x;\n  return 1;\n}\nif true {\n  let b = 4;\n  print b;\n}\nmatch Some(1) {\n  Some(v) => { print 0; }\n  None => { print a; }\n}\nprint f(b, 0);\n";
+    assert_eq!(
+        warnings(src),
+        vec![
+            (
+                codes::SHADOWED_VARIABLE,
+                "a shadows an earlier variable".to_string()
+            ),
+            (codes::UNUSED_VARIABLE, "unused variable: a".to_string()),
+            (
+                codes::UNUSED_VARIABLE,
+                "unused variable: unused".to_string()
+            ),
+            (
+                codes::SHADOWED_VARIABLE,
+                "b shadows an earlier variable".to_string()
+            ),
+            (codes::UNUSED_VARIABLE, "unused variable: v".to_string()),
+            (
+                codes::UNUSED_MUT,
+                "variable b does not need to be mutable".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn mut_is_reserved() {
+    let err = parse_program("let mut = 1;\n").expect_err("mut is not a name");
+    assert_eq!(err.diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+    let err = parse_program("let x = mut;\n").expect_err("mut is not a value");
+    assert_eq!(err.diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+}
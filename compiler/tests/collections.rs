@@ -0,0 +1,60 @@
+mod common;
+
+use axiom_compiler::{
+    diagnostic::codes, parser::parse_program, typecheck::typecheck, StmtKind, Type,
+};
+use common::error_codes;
+
+#[test]
+fn parses_nested_collection_types() {
+    let program = parse_program("let m: Map[String, Array[Set[Int]]] = Map {};").expect("parse");
+    let StmtKind::Let { ty: Some(ty), .. } = &program.statements[0].kind else {
+        panic!("expected annotated let");
+    };
+    assert_eq!(ty.to_string(), "Map[String, Array[Set[Int]]]");
+    assert_eq!(
+        *ty,
+        Type::Map(
+            Box::new(Type::String),
+            Box::new(Type::Array(Box::new(Type::Set(Box::new(Type::Int)))))
+        )
+    );
+}
+
+#[test]
+fn typechecks_literals_indexing_len_and_for() {
+    let src = "fn total(xs: Array[Int]) -> Int {\n  let sum: Array[Int] = [];\n  for x in xs { print x; }\n  return len(xs) + len(sum);\n}\nlet xs = [1, 2, 3];\nlet grid = [[1], []];\nlet ages = Map { \"ada\": 36, \"alan\": 41 };\nlet tags = Set { \"a\", \"b\" };\nprint xs[0] + ages[\"ada\"] + total(xs) + grid[1][0];\nfor name in ages { print name + \"!\"; }\nfor t in tags { print len(t); }\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+}
+
+#[test]
+fn rejects_ill_typed_collections() {
+    let src = "let a = [1, \"two\"];\nlet e = [];\nlet m = Map { 1.5: true };\nlet xs = [1];\nprint xs[\"0\"];\nprint 3[0];\nfor c in \"abc\" { }\nprint len(1);\nlet typed: Array[String] = [1];\n";
+    assert_eq!(
+        error_codes(src),
+        vec![
+            codes::TYPE_MISMATCH,
+            codes::CANNOT_INFER,
+            codes::INVALID_KEY_TYPE,
+            codes::TYPE_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::TYPE_MISMATCH,
+        ]
+    );
+}
+
+#[test]
+fn rejects_non_key_types_in_annotations() {
+    let err = parse_program("let s: Set[Float] = Set {};").expect_err("float set");
+    assert!(err
+        .to_string()
+        .contains("Float cannot be used as a map key or set element"));
+}
+
+#[test]
+fn loop_variables_are_scoped_to_the_body() {
+    let src = "for x in [1] { print x; }\nprint x;\n";
+    assert_eq!(error_codes(src), vec![codes::UNKNOWN_VARIABLE]);
+}
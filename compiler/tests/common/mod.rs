@@ -0,0 +1,30 @@
+//! Helpers shared by the typechecker tests; each test binary uses a subset.
+#![allow(dead_code)]
+
+use axiom_compiler::{parser::parse_program, typecheck::typecheck};
+
+/// Codes and messages of every diagnostic from a program that fails to typecheck.
+pub fn diagnostics(src: &str) -> Vec<(&'static str, String)> {
+    typecheck(parse_program(src).expect("parse"))
+        .expect_err("should not typecheck")
+        .diagnostics
+        .into_iter()
+        .map(|d| (d.code, d.message))
+        .collect()
+}
+
+/// Codes of the errors, ignoring warnings, from a program that fails to typecheck.
+pub fn error_codes(src: &str) -> Vec<&'static str> {
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("should not typecheck");
+    err.errors().map(|d| d.code).collect()
+}
+
+/// Codes and messages of the warnings from a program that typechecks.
+pub fn warnings(src: &str) -> Vec<(&'static str, String)> {
+    typecheck(parse_program(src).expect("parse"))
+        .expect("typecheck")
+        .warnings
+        .into_iter()
+        .map(|d| (d.code, d.message))
+        .collect()
+}
@@ -0,0 +1,66 @@
+use axiom_compiler::{parser::parse_program, typecheck::typecheck, Effect, StmtKind};
+
+const DECL: &str = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\n";
+
+#[test]
+fn parses_if_else_if_chains_and_while_loops() {
+    let src = "let x = 1;\nif x > 1 {\n  print \"big\";\n} else if x == 1 {\n  print \"one\";\n} else {\n  print \"small\";\n}\nwhile false { print x; }\n";
+    let program = parse_program(src).expect("parse");
+    assert_eq!(program.statements.len(), 3);
+    let StmtKind::If {
+        then_block,
+        else_block: Some(else_block),
+        ..
+    } = &program.statements[1].kind
+    else {
+        panic!("expected if/else");
+    };
+    assert_eq!(then_block.stmts.len(), 1);
+    assert!(matches!(
+        else_block.stmts[0].kind,
+        StmtKind::If {
+            else_block: Some(_),
+            ..
+        }
+    ));
+    assert!(matches!(program.statements[2].kind, StmtKind::While { .. }));
+}
+
+#[test]
+fn block_bindings_do_not_escape_their_scope() {
+    let src = "let x = 1;\nif true {\n  let y = x + 1;\n  let x = \"shadow\";\n  print x + \"!\";\n}\nprint x + 1;\nprint y;\n";
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("y out of scope");
+    assert_eq!(err.diagnostics.len(), 1);
+    assert!(err.to_string().contains("unknown variable: y"));
+}
+
+#[test]
+fn conditions_must_be_bool() {
+    let src = "if 1 { print 1; }\nwhile \"yes\" { print 2; }\n";
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("non-bool conditions");
+    assert_eq!(err.diagnostics.len(), 2);
+    assert!(err.to_string().contains("condition must be Bool, got Int"));
+}
+
+#[test]
+fn effects_join_across_branches() {
+    let src = format!(
+        "{DECL}let retry = true;\nif retry {{\n  print 1;\n}} else {{\n  call MockEcho {{ message: \"hi\" }} using toolCap timeout 10;\n}}\n"
+    );
+    let typed = typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, Effect::TOOL);
+    assert_eq!(typed.manifest.required_caps, vec!["toolCap".to_string()]);
+
+    let pure = "if true { print 1; } else { print 2; }\n";
+    let typed = typecheck(parse_program(pure).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, Effect::PURE);
+}
+
+#[test]
+fn tool_declarations_must_be_top_level() {
+    let src = format!("if true {{\n  {DECL}}}\n");
+    let err = parse_program(&src).expect_err("nested tool decl");
+    assert!(err
+        .to_string()
+        .contains("tool declarations must appear at the top level"));
+}
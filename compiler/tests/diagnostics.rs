@@ -0,0 +1,72 @@
+use axiom_compiler::{
+    diagnostic::{codes, Diagnostic},
+    parser::parse_file,
+    span::{SourceMap, Span},
+    typecheck::typecheck,
+};
+
+fn compile(sources: &mut SourceMap, src: &str) -> Result<(), Vec<Diagnostic>> {
+    let file = sources.add("main.ax", src);
+    parse_file(file, sources.source(file))
+        .and_then(typecheck)
+        .map(|_| ())
+        .map_err(|e| e.diagnostics)
+}
+
+#[test]
+fn parser_recovers_and_reports_every_syntax_error() {
+    let mut sources = SourceMap::default();
+    let src = "let a = ;\nlet b = 2;\nprint b\nlet c = 3 $ 4;\nprint c;\n";
+    let diags = compile(&mut sources, src).expect_err("expected syntax errors");
+    let found = diags.iter().map(|d| d.code).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            codes::UNEXPECTED_TOKEN,
+            codes::UNEXPECTED_TOKEN,
+            codes::UNEXPECTED_CHARACTER,
+            codes::UNEXPECTED_TOKEN,
+        ]
+    );
+}
+
+#[test]
+fn typechecker_reports_all_errors_without_cascading() {
+    let mut sources = SourceMap::default();
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet a = nope + 1;\nprint a + 1;\ncall MockEcho { message: 5 } using badCap timeout 10;\nprint other;\n";
+    let diags = compile(&mut sources, src).expect_err("expected type errors");
+    let found = diags.iter().map(|d| d.code).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            codes::UNKNOWN_VARIABLE,
+            codes::TOOL_CAP_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::UNKNOWN_VARIABLE,
+        ]
+    );
+}
+
+#[test]
+fn renders_rustc_style_snippet_with_labels_notes_and_help() {
+    let mut sources = SourceMap::default();
+    let file = sources.add("main.ax", "let total = 1;\nprint total + \"x\";\n");
+    let diag = Diagnostic::error(codes::TYPE_MISMATCH, "mismatched operand types")
+        .with_primary(Span::new(file, 29, 32), "this is String")
+        .with_secondary(Span::new(file, 4, 9), "`total` defined here")
+        .with_note("arithmetic is only defined on Int")
+        .with_help("convert the operand first");
+    let expected = "\
+error[AX0202]: mismatched operand types
+ --> main.ax:2:15
+  |
+1 | let total = 1;
+  |     ----- `total` defined here
+2 | print total + \"x\";
+  |               ^^^ this is String
+  |
+  = note: arithmetic is only defined on Int
+  = help: convert the operand first
+";
+    assert_eq!(diag.render(&sources), expected);
+}
@@ -0,0 +1,137 @@
+use axiom_compiler::{
+    diagnostic::codes, manifest::render_manifest, parser::parse_program, typecheck::typecheck,
+    CapabilityKind, Effect, EffectKind, StmtKind,
+};
+
+#[test]
+fn builtins_contribute_their_effects() {
+    let src = "let t = now();\nlet r = random();\nlet text = read_file(\"notes.txt\");\nprint t + r;\nprint text;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    assert_eq!(
+        typed.effect,
+        Effect::TIME.join(Effect::RANDOM).join(Effect::FS)
+    );
+    assert!(!typed.effect.contains(EffectKind::Net));
+    assert_eq!(
+        typed.manifest.required_kinds,
+        vec![
+            CapabilityKind::Time,
+            CapabilityKind::Random,
+            CapabilityKind::Fs
+        ]
+    );
+}
+
+#[test]
+fn functions_declare_sets_of_effects() {
+    let src = "fn sync(url: String) -> String !fs !net {\n  return read_file(\"cache\") + http_get(url);\n}\n";
+    let program = parse_program(src).expect("parse");
+    let StmtKind::FnDecl(decl) = &program.statements[0].kind else {
+        panic!("expected fn");
+    };
+    assert_eq!(decl.effect.effect, Effect::FS.join(Effect::NET));
+    assert_eq!(decl.effect.effect.to_string(), "fs, net");
+    typecheck(program).expect("typecheck");
+
+    let err = parse_program("fn f() !pure !fs { }\n").expect_err("pure with others");
+    assert!(err
+        .to_string()
+        .contains("`!pure` cannot be combined with other effects"));
+    let err = parse_program("fn f() !disk { }\n").expect_err("unknown effect");
+    assert_eq!(err.diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+}
+
+#[test]
+fn effects_beyond_the_declared_set_are_rejected() {
+    let src = "fn load() -> String !fs {\n  return http_get(\"http://example.com\");\n}\nfn stamp() -> Int !pure {\n  return now();\n}\n";
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("effect violation");
+    assert!(err
+        .diagnostics
+        .iter()
+        .all(|d| d.code == codes::EFFECT_NOT_ALLOWED));
+    let text = err.to_string();
+    assert!(text.contains(
+        "call to http_get has effect `net`, which is not allowed in `!fs` function load"
+    ));
+    assert!(text
+        .contains("call to now has effect `time`, which is not allowed in `!pure` function stamp"));
+}
+
+#[test]
+fn manifest_lists_effects_and_capability_kinds() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet page = http_get(\"http://example.com\");\ncall MockEcho { message: page } using toolCap timeout 10;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    assert_eq!(
+        render_manifest(&typed),
+        "capability_manifest_v1\neffects=net,tool\nrequires_kind=net\nrequires_kind=tool\nrequires=toolCap\n"
+    );
+}
+
+#[test]
+fn program_effect_attribute_bounds_top_level_code() {
+    let src = "#![effects(pure)]\ntool MockEcho input { message: String } output { echo: String } cap toolCap;\nfn fetch() !tool {\n  call MockEcho { message: \"x\" } using toolCap timeout 10;\n}\nprint 1;\ncall MockEcho { message: \"x\" } using toolCap timeout 10;\nfetch();\n";
+    let program = parse_program(src).expect("parse");
+    assert_eq!(
+        program.effects.as_ref().map(|e| e.effect),
+        Some(Effect::PURE)
+    );
+    let err = typecheck(program).expect_err("pure program calls a tool");
+    assert_eq!(err.diagnostics.len(), 2);
+    let text = err.to_string();
+    assert!(text.contains(
+        "call to tool MockEcho has effect `tool`, which is not allowed in a `#![effects(pure)]` program"
+    ));
+    assert!(text.contains(
+        "call to fetch has effect `tool`, which is not allowed in a `#![effects(pure)]` program"
+    ));
+
+    // Functions that are declared but never called add nothing.
+    let src = "#![effects(pure)]\nfn stamp() -> Int !time { return now(); }\nprint 1;\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+}
+
+#[test]
+fn program_effect_attribute_limits_kinds_and_caps() {
+    let src = "#![effects(fs, tool[toolCap])]\ntool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet text = read_file(\"notes.txt\");\ncall MockEcho { message: text } using toolCap timeout 10;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, Effect::FS.join(Effect::TOOL));
+
+    let src = "#![effects(tool[otherCap])]\ntool MockEcho input { message: String } output { echo: String } cap toolCap;\nprint http_get(\"http://example.com\");\ncall MockEcho { message: \"x\" } using toolCap timeout 10;\n";
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("exceeds bound");
+    let codes = err.diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![codes::EFFECT_NOT_ALLOWED, codes::CAP_NOT_DECLARED]
+    );
+    let text = err.to_string();
+    assert!(text.contains(
+        "call to http_get has effect `net`, which is not allowed in a `#![effects(tool[otherCap])]` program"
+    ));
+    assert!(text.contains(
+        "call to tool MockEcho uses capability toolCap, which the program does not declare"
+    ));
+}
+
+#[test]
+fn malformed_effect_attributes_are_syntax_errors() {
+    for (src, message) in [
+        (
+            "#![effects(pure, fs)]\n",
+            "`pure` cannot be combined with other effects",
+        ),
+        ("#![effects(disk)]\n", "unknown effect: disk"),
+        ("#![strict]\n", "unknown attribute: strict"),
+        (
+            "#![effects(pure)]\n#![effects(fs)]\n",
+            "duplicate `#![effects(...)]` attribute",
+        ),
+    ] {
+        let err = parse_program(src).expect_err(src);
+        assert!(err.to_string().contains(message), "{src}: {err}");
+    }
+    let err = parse_program("print 1;\n#![effects(pure)]\n").expect_err("late attribute");
+    assert_eq!(err.diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+    assert!(err
+        .to_string()
+        .contains("attributes must appear at the top of the file"));
+}
@@ -0,0 +1,203 @@
+use axiom_compiler::{
+    diagnostic::codes, parser::parse_program, Expr, ExprKind, StmtKind, TemplatePart,
+};
+
+fn sexpr(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Int(v) => v.to_string(),
+        ExprKind::Float(v) => format!("{v:?}"),
+        ExprKind::Bytes(b) => format!("(bytes {})", b.len()),
+        ExprKind::Unit => "()".to_string(),
+        ExprKind::Bool(v) => v.to_string(),
+        ExprKind::String(s) => format!("{s:?}"),
+        ExprKind::Template(parts) => {
+            let parts = parts
+                .iter()
+                .map(|p| match p {
+                    TemplatePart::Lit(s) => format!("{s:?}"),
+                    TemplatePart::Expr(e) => sexpr(e),
+                })
+                .collect::<Vec<_>>();
+            format!("(str {})", parts.join(" "))
+        }
+        ExprKind::Var(v) => v.clone(),
+        ExprKind::Array(items) => {
+            let items = items
+                .iter()
+                .map(|i| format!(" {}", sexpr(i)))
+                .collect::<String>();
+            format!("(array{items})")
+        }
+        ExprKind::Set(items) => {
+            let items = items
+                .iter()
+                .map(|i| format!(" {}", sexpr(i)))
+                .collect::<String>();
+            format!("(set{items})")
+        }
+        ExprKind::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(k, v)| format!(" ({} {})", sexpr(k), sexpr(v)))
+                .collect::<String>();
+            format!("(map{entries})")
+        }
+        ExprKind::Index { expr, index } => format!("(index {} {})", sexpr(expr), sexpr(index)),
+        ExprKind::Struct { name, fields } => {
+            let fields = fields
+                .iter()
+                .map(|(f, v)| format!(" ({f} {})", sexpr(v)))
+                .collect::<String>();
+            format!("(struct {name}{fields})")
+        }
+        ExprKind::Variant {
+            enum_name,
+            variant,
+            args,
+        } => {
+            let args = args
+                .iter()
+                .map(|a| format!(" {}", sexpr(a)))
+                .collect::<String>();
+            format!("({enum_name}::{variant}{args})")
+        }
+        ExprKind::Field { expr, field } => format!("(. {} {field})", sexpr(expr)),
+        ExprKind::Match { scrutinee, arms } => {
+            let arms = arms
+                .iter()
+                .map(|arm| format!(" ({:?} {})", arm.pattern.kind, sexpr(&arm.body)))
+                .collect::<String>();
+            format!("(match {}{arms})", sexpr(scrutinee))
+        }
+        ExprKind::ToolCall(call) => format!("(tool-call {} {})", call.tool, call.cap),
+        ExprKind::TryCall(call) => format!("(try-call {} {})", call.tool, call.cap),
+        ExprKind::Propagate(expr) => format!("(? {})", sexpr(expr)),
+        ExprKind::Call { callee, args } => {
+            let args = args
+                .iter()
+                .map(|a| format!(" {}", sexpr(a)))
+                .collect::<String>();
+            format!("(call {callee}{args})")
+        }
+        ExprKind::Unary { op, expr } => format!("({} {})", op.symbol(), sexpr(expr)),
+        ExprKind::Binary { lhs, op, rhs } => {
+            format!("({} {} {})", op.symbol(), sexpr(lhs), sexpr(rhs))
+        }
+    }
+}
+
+fn parse_expr(src: &str) -> Expr {
+    let program = parse_program(&format!("print {src};")).expect("parse");
+    match program.statements.into_iter().next().map(|s| s.kind) {
+        Some(StmtKind::Print { expr }) => expr,
+        other => panic!("expected print statement, got {other:?}"),
+    }
+}
+
+#[test]
+fn golden_expression_trees() {
+    let cases = [
+        ("10 - 2 - 3", "(- (- 10 2) 3)"),
+        ("8 / 4 / 2", "(/ (/ 8 4) 2)"),
+        ("1 + 2 * 3", "(+ 1 (* 2 3))"),
+        ("(1 + 2) * 3", "(* (+ 1 2) 3)"),
+        ("-5", "-5"),
+        ("1 - -5", "(- 1 -5)"),
+        ("-(5)", "(- 5)"),
+        ("-(a + 1) * -b", "(* (- (+ a 1)) (- b))"),
+        ("!!ok", "(! (! ok))"),
+        ("a + 1 < b * 2", "(< (+ a 1) (* b 2))"),
+        ("a == b && c != d", "(&& (== a b) (!= c d))"),
+        ("a || b && c", "(|| a (&& b c))"),
+        ("a && b || c && d", "(|| (&& a b) (&& c d))"),
+        ("x >= 1 || !(y <= 2)", "(|| (>= x 1) (! (<= y 2)))"),
+        ("((\"s\"))", "\"s\""),
+        (
+            "\"hi {name}!\" + \"x\"",
+            "(+ (str \"hi \" name \"!\") \"x\")",
+        ),
+        ("\"{a + 1}{b}\"", "(str (+ a 1) b)"),
+        ("\"\\{literal\\}\"", "\"{literal}\""),
+        ("f()", "(call f)"),
+        ("-1.5 * 2.0e3", "(* (- 1.5) 2000.0)"),
+        ("hex\"00ff\" + base64\"AQID\"", "(+ (bytes 2) (bytes 3))"),
+        ("(())", "()"),
+        ("[1, 2 + 3,]", "(array 1 (+ 2 3))"),
+        ("-xs[i + 1][0]", "(- (index (index xs (+ i 1)) 0))"),
+        ("Map { \"a\": [1], k: 2 }", "(map (\"a\" (array 1)) (k 2))"),
+        ("Set {} == Set { 1 }", "(== (set) (set 1))"),
+        ("len(xs)[0]", "(index (call len xs) 0)"),
+        ("-f(a, b + 1) * 2", "(* (- (call f a (+ b 1))) 2)"),
+        ("\"{g(x)}\"", "(str (call g x))"),
+        (
+            "Point { x: 1, y: -a.b }.x",
+            "(. (struct Point (x 1) (y (- (. a b)))) x)",
+        ),
+        (
+            "Shape::Circle(r * 2) == Shape::Empty",
+            "(== (Shape::Circle (* r 2)) (Shape::Empty))",
+        ),
+        ("xs[0].name", "(. (index xs 0) name)"),
+        ("-f(x)?.y?", "(- (? (. (? (call f x)) y)))"),
+        ("Some(1) == None", "(== (Option::Some 1) (Option::None))"),
+        ("Err(Ok(e))", "(Result::Err (Result::Ok e))"),
+        (
+            "try call T { a: 1 } using cap timeout 5",
+            "(try-call T cap)",
+        ),
+        (
+            "(call T {} using cap timeout 5).out",
+            "(. (tool-call T cap) out)",
+        ),
+        (
+            "match b { true => 1, _ => 0 }",
+            "(match b (Bool(true) 1) (Wildcard 0))",
+        ),
+    ];
+    for (src, expected) in cases {
+        assert_eq!(sexpr(&parse_expr(src)), expected, "source: {src}");
+    }
+}
+
+#[test]
+fn expression_spans_cover_operands_and_parentheses() {
+    let src = "print (1 + 2) * x;";
+    let program = parse_program(src).expect("parse");
+    let StmtKind::Print { expr } = &program.statements[0].kind else {
+        panic!("expected print");
+    };
+    assert_eq!(&src[expr.span.start..expr.span.end], "(1 + 2) * x");
+    let ExprKind::Binary { lhs, .. } = &expr.kind else {
+        panic!("expected binary");
+    };
+    assert_eq!(&src[lhs.span.start..lhs.span.end], "(1 + 2)");
+}
+
+#[test]
+fn rejects_chained_comparisons_and_unbalanced_parentheses() {
+    let err = parse_program("print a < b < c;").expect_err("chained comparison");
+    assert!(err
+        .to_string()
+        .contains("comparison operators cannot be chained"));
+    let err = parse_program("print (1 + 2;").expect_err("missing paren");
+    assert!(err.to_string().contains("expected `)`, found `;`"));
+}
+
+#[test]
+fn negative_literals_cover_the_full_int_range() {
+    assert_eq!(
+        sexpr(&parse_expr("-9223372036854775808")),
+        "-9223372036854775808"
+    );
+    let program =
+        parse_program("match 1 { -9223372036854775808 => { } _ => { } }").expect("pattern");
+    assert_eq!(program.statements.len(), 1);
+    for src in [
+        "print 9223372036854775808;",
+        "print -9223372036854775809;",
+        "print -(9223372036854775808);",
+    ] {
+        let err = parse_program(src).expect_err(src);
+        assert_eq!(err.diagnostics[0].code, codes::INVALID_LITERAL, "{src}");
+    }
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use axiom_compiler::{
+    diagnostic::codes, parser::parse_program, typecheck::typecheck, Effect, StmtKind, Type,
+};
+use common::error_codes;
+
+const DECL: &str = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\n";
+
+#[test]
+fn parses_signatures_with_effects() {
+    let src = "fn score(x: Int, y: Int) -> Int !pure { return x + y; }\nfn fetch(q: String) !tool[toolCap, other] { }\nfn log() { }\n";
+    let program = parse_program(src).expect("parse");
+    let decls = program
+        .statements
+        .iter()
+        .map(|s| match &s.kind {
+            StmtKind::FnDecl(decl) => decl,
+            other => panic!("expected fn, got {other:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(decls[0].params.len(), 2);
+    assert_eq!(decls[0].ret, Type::Int);
+    assert_eq!(decls[0].effect.effect, Effect::PURE);
+    assert_eq!(decls[1].ret, Type::Unit);
+    assert_eq!(decls[1].effect.effect, Effect::TOOL);
+    assert_eq!(decls[1].effect.caps, vec!["toolCap", "other"]);
+    assert_eq!(decls[2].effect.effect, Effect::PURE);
+}
+
+#[test]
+fn functions_are_checked_and_callable_before_declaration() {
+    let src = "print double(score(2));\nfn double(x: Int) -> Int { return x * 2; }\nfn score(x: Int) -> Int !pure {\n  if x > 1 { return x; } else { return 0; }\n}\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, Effect::PURE);
+}
+
+#[test]
+fn calling_a_tool_function_adds_its_effect() {
+    let src = format!(
+        "{DECL}fn fetch(q: String) !tool[toolCap] {{\n  call MockEcho {{ message: q }} using toolCap timeout 10;\n}}\nfetch(\"hi\");\n"
+    );
+    let typed = typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, Effect::TOOL);
+    assert_eq!(typed.manifest.required_caps, vec!["toolCap".to_string()]);
+}
+
+#[test]
+fn pure_functions_cannot_reach_tool_effects() {
+    let src = format!(
+        "{DECL}fn fetch() !tool {{\n  call MockEcho {{ message: \"x\" }} using toolCap timeout 10;\n}}\nfn score() -> Int !pure {{\n  fetch();\n  return 1;\n}}\nfn sneaky() {{\n  call MockEcho {{ message: \"x\" }} using toolCap timeout 10;\n}}\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("effect violation");
+    assert_eq!(err.diagnostics.len(), 2);
+    assert!(err
+        .diagnostics
+        .iter()
+        .all(|d| d.code == codes::EFFECT_NOT_ALLOWED));
+    let text = err.to_string();
+    assert!(text.contains(
+        "call to fetch has effect `tool`, which is not allowed in `!pure` function score"
+    ));
+    assert!(text.contains(
+        "call to tool MockEcho has effect `tool`, which is not allowed in `!pure` function sneaky"
+    ));
+}
+
+#[test]
+fn tool_functions_are_limited_to_declared_caps() {
+    let src = format!(
+        "{DECL}fn any() !tool {{\n  call MockEcho {{ message: \"x\" }} using toolCap timeout 10;\n}}\nfn narrow() !tool[otherCap] {{\n  call MockEcho {{ message: \"x\" }} using toolCap timeout 10;\n  any();\n}}\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("cap violation");
+    let text = err.to_string();
+    assert!(text.contains("uses capability toolCap, which function narrow does not declare"));
+    assert!(text.contains(
+        "call to any may use any capability, but function narrow only declares otherCap"
+    ));
+}
+
+#[test]
+fn rejects_bad_calls() {
+    let src = "fn f(x: Int) -> Int { return x; }\nprint f(1, 2);\nprint f(\"no\");\nprint h();\n";
+    assert_eq!(
+        error_codes(src),
+        vec![
+            codes::ARITY_MISMATCH,
+            codes::TYPE_MISMATCH,
+            codes::UNKNOWN_FUNCTION
+        ]
+    );
+}
+
+#[test]
+fn rejects_bad_returns() {
+    let src = "fn f(x: Int) -> Int {\n  if x > 0 { return x; }\n}\nfn g() { return 1; }\nfn h() -> Bool { return 1; }\nreturn;\n";
+    assert_eq!(
+        error_codes(src),
+        vec![
+            codes::MISSING_RETURN,
+            codes::INVALID_RETURN,
+            codes::INVALID_RETURN,
+            codes::INVALID_RETURN
+        ]
+    );
+}
+
+#[test]
+fn function_bodies_only_see_their_parameters() {
+    let src = "let secret = 1;\nfn f() -> Int { return secret; }\n";
+    assert_eq!(error_codes(src), vec![codes::UNKNOWN_VARIABLE]);
+}
+
+#[test]
+fn rejects_duplicate_and_nested_functions() {
+    let src = "fn f() { }\nfn f() { }\n";
+    assert_eq!(error_codes(src), vec![codes::DUPLICATE_DECLARATION]);
+
+    let err = parse_program("if true { fn g() { } }").expect_err("nested fn");
+    assert!(err
+        .to_string()
+        .contains("function declarations must appear at the top level"));
+    let err = parse_program("1 + 2;").expect_err("bare expression");
+    assert!(err
+        .to_string()
+        .contains("only function calls can be used as statements"));
+}
@@ -0,0 +1,103 @@
+use axiom_compiler::{
+    lexer::{lex, TokenKind},
+    parser::{parse_file, parse_program},
+    span::{FileId, SourceMap},
+    ExprKind, StmtKind, ToolCall,
+};
+
+#[test]
+fn tokens_carry_offsets_lines_and_columns() {
+    let src = "let x = 1;\n  print x;";
+    let tokens = lex(FileId::default(), src).expect("lex");
+    let print = tokens
+        .iter()
+        .find(|t| t.kind == TokenKind::Ident("print".into()))
+        .expect("print token");
+    assert_eq!((print.line, print.col), (2, 3));
+    assert_eq!(&src[print.span.start..print.span.end], "print");
+    assert_eq!(tokens.last().map(|t| &t.kind), Some(&TokenKind::Eof));
+}
+
+#[test]
+fn skips_block_comments_and_lexes_raw_strings() {
+    let src = "/* header\n spanning lines */ let s = r\"C:\\path\"; /// doc\n";
+    let kinds = lex(FileId::default(), src)
+        .expect("lex")
+        .into_iter()
+        .map(|t| t.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Ident("let".into()),
+            TokenKind::Ident("s".into()),
+            TokenKind::Eq,
+            TokenKind::Str("C:\\path".into()),
+            TokenKind::Semi,
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn parses_multi_line_tool_decl_and_call_with_punctuated_strings() {
+    let src = "tool MockEcho\n  input { message: String }\n  output { echo: String }\n  cap toolCap;\ncall MockEcho {\n  message: \"a, b } c\",\n} using toolCap timeout 1000;\n";
+    let program = parse_program(src).expect("parse");
+    assert_eq!(program.statements.len(), 2);
+    match &program.statements[1].kind {
+        StmtKind::ToolCall(ToolCall { input, .. }) => {
+            assert_eq!(input.len(), 1);
+            assert!(matches!(&input[0].1.kind, ExprKind::String(s) if s == "a, b } c"));
+        }
+        other => panic!("expected tool call, got {other:?}"),
+    }
+}
+
+#[test]
+fn parse_errors_report_file_line_and_column() {
+    let mut sources = SourceMap::default();
+    let file = sources.add("main.ax", "let x = 1;\nlet y 2;\n");
+    let err = parse_file(file, sources.source(file)).expect_err("expected parse error");
+    assert_eq!(
+        err.to_string(),
+        "error[AX0102]: expected `=`, found integer `2`"
+    );
+    let loc = sources.location(err.span().expect("span"));
+    assert_eq!(loc.to_string(), "main.ax:2:7");
+
+    let file = sources.add("open.ax", "let s = \"open;\n");
+    let err = lex(file, sources.source(file)).expect_err("expected lex error");
+    let loc = sources.location(err.span().expect("span"));
+    assert_eq!(loc.to_string(), "open.ax:1:9");
+    assert!(err.to_string().contains("unterminated string literal"));
+}
+
+#[test]
+fn lexes_float_and_bytes_literals() {
+    let src = "1.25 3.0e-2 7 hex\"0aFF\" base64\"aGk=\" hex";
+    let kinds = lex(FileId::default(), src)
+        .expect("lex")
+        .into_iter()
+        .map(|t| t.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Float(1.25),
+            TokenKind::Float(0.03),
+            TokenKind::Int(7),
+            TokenKind::Bytes(vec![0x0a, 0xff]),
+            TokenKind::Bytes(b"hi".to_vec()),
+            TokenKind::Ident("hex".into()),
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn rejects_malformed_bytes_literals() {
+    let err = lex(FileId::default(), "hex\"abc\" base64\"a*==\"").expect_err("bad bytes");
+    let text = err.to_string();
+    assert!(text.contains("hex literal must have an even number of digits"));
+    assert!(text.contains("invalid base64 character `*`"));
+}
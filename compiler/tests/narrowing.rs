@@ -0,0 +1,127 @@
+mod common;
+
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck, StmtKind};
+use common::error_codes;
+
+const DECLS: &str = "tool Search input { q: String } output { hits: Int } cap toolCap;\ntool Delete input { id: Int } output { ok: Bool } cap toolCap;\n";
+
+#[test]
+fn parses_narrow_statements() {
+    let program = parse_program(
+        "let readOnly = narrow toolCap to [Search, Fetch];\nlet narrow = 1;\nprint narrow;\n",
+    )
+    .expect("parse");
+    match &program.statements[0].kind {
+        StmtKind::Narrow {
+            name,
+            parent,
+            tools,
+        } => {
+            assert_eq!(name, "readOnly");
+            assert_eq!(parent, "toolCap");
+            assert_eq!(tools, &vec!["Search".to_string(), "Fetch".to_string()]);
+        }
+        other => panic!("expected narrow, got {other:?}"),
+    }
+    assert!(matches!(program.statements[1].kind, StmtKind::Let { .. }));
+
+    let err = parse_program("if true {\n  let r = narrow toolCap to [Search];\n}\n")
+        .expect_err("nested narrow");
+    assert!(err
+        .to_string()
+        .contains("capability narrowing must appear at the top level"));
+    let err = parse_program("let mut r = narrow toolCap to [Search];\n").expect_err("mut");
+    assert!(err.to_string().contains("cannot be `mut`"));
+}
+
+#[test]
+fn derived_capabilities_call_their_tools() {
+    let src = format!(
+        "{DECLS}let readOnly = narrow toolCap to [Search];\nlet r = call Search {{ q: \"x\" }} using readOnly timeout 10;\nprint r.hits;\ncall Delete {{ id: 1 }} using toolCap timeout 10;\n"
+    );
+    let typed = typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.manifest.required_caps, vec!["toolCap".to_string()]);
+}
+
+#[test]
+fn derived_capabilities_cannot_be_used_beyond_their_scope() {
+    let src = format!(
+        "{DECLS}let readOnly = narrow toolCap to [Search];\ncall Delete {{ id: 1 }} using readOnly timeout 10;\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("out of scope");
+    assert_eq!(err.diagnostics[0].code, codes::CAP_OUT_OF_SCOPE);
+    assert!(err
+        .to_string()
+        .contains("capability readOnly does not grant tool Delete"));
+
+    // Narrowing a derived capability cannot add tools back.
+    let src = format!(
+        "{DECLS}let readOnly = narrow toolCap to [Search];\nlet sneaky = narrow readOnly to [Search, Delete];\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("widening");
+    assert!(err
+        .to_string()
+        .contains("cannot narrow readOnly to Delete: readOnly only grants Search"));
+}
+
+#[test]
+fn narrowing_is_checked_against_declarations() {
+    let other = "tool Mail input { to: String } output { ok: Bool } cap mailCap;\n";
+    assert_eq!(
+        error_codes(&format!(
+            "{DECLS}{other}let r = narrow toolCap to [Mail, Missing];\n"
+        )),
+        vec![codes::TOOL_CAP_MISMATCH, codes::UNKNOWN_TOOL]
+    );
+    assert_eq!(
+        error_codes(&format!(
+            "{DECLS}call Search {{ q: \"x\" }} using r timeout 10;\nlet r = narrow toolCap to [Search];\n"
+        )),
+        vec![codes::USE_BEFORE_DEFINE]
+    );
+    assert_eq!(
+        error_codes(&format!(
+            "{DECLS}let r = narrow toolCap to [Search];\nlet r = narrow toolCap to [Delete];\n"
+        )),
+        vec![codes::DUPLICATE_DECLARATION]
+    );
+}
+
+#[test]
+fn functions_may_use_capabilities_derived_from_declared_ones() {
+    let src = format!(
+        "{DECLS}let readOnly = narrow toolCap to [Search];\nfn find(q: String) -> Int !tool[toolCap] {{\n  let r = call Search {{ q: q }} using readOnly timeout 10;\n  return r.hits;\n}}\nfn other() !tool[otherCap] {{\n  call Search {{ q: \"x\" }} using readOnly timeout 10;\n}}\nprint find(\"x\");\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("undeclared cap");
+    assert_eq!(err.errors().count(), 1);
+    assert!(err.to_string().contains(
+        "call to tool Search uses capability readOnly, which function other does not declare"
+    ));
+}
+
+#[test]
+fn functions_using_derived_capabilities_cannot_be_called_before_the_narrow() {
+    let body = "fn f() !tool[ro] {\n  call Search { q: \"x\" } using ro timeout 10;\n}\nfn g() !tool[toolCap] {\n  f();\n}\n";
+    let err = typecheck(
+        parse_program(&format!(
+            "{DECLS}{body}f();\ng();\nlet ro = narrow toolCap to [Search];\nf();\ng();\n"
+        ))
+        .expect("parse"),
+    )
+    .expect_err("called before narrow");
+    let messages = err.errors().map(|d| d.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "call to f uses ro before its definition",
+            "call to g uses ro before its definition",
+        ]
+    );
+    typecheck(
+        parse_program(&format!(
+            "{DECLS}{body}let ro = narrow toolCap to [Search];\ng();\n"
+        ))
+        .expect("parse"),
+    )
+    .expect("called after narrow");
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck, Type};
+use common::diagnostics;
+
+#[test]
+fn parses_option_and_result_types() {
+    let program =
+        parse_program("fn f(x: Option[Array[Int]]) -> Result[Int, ToolError] { return Ok(1); }")
+            .expect("parse");
+    let axiom_compiler::StmtKind::FnDecl(decl) = &program.statements[0].kind else {
+        panic!("expected fn");
+    };
+    assert_eq!(decl.params[0].1.to_string(), "Option[Array[Int]]");
+    assert_eq!(
+        decl.ret,
+        Type::Result(
+            Box::new(Type::Int),
+            Box::new(Type::Named("ToolError".into()))
+        )
+    );
+}
+
+#[test]
+fn typechecks_constructors_matches_and_propagation() {
+    let src = "fn half(n: Int) -> Option[Int] {\n  if n / 2 * 2 != n { return None; }\n  return Some(n / 2);\n}\nfn quarter(n: Int) -> Option[Int] {\n  let h = half(n)?;\n  return half(h);\n}\nfn parse(s: String) -> Result[Int, String] {\n  return match s { \"one\" => Ok(1), _ => Err(\"not a number: \" + s) };\n}\nfn twice(s: String) -> Result[Int, String] {\n  return Ok(parse(s)? * 2);\n}\nlet none: Option[Int] = None;\nprint quarter(8) == Some(2) && none == None;\nmatch twice(\"one\") {\n  Ok(n) => { print n; }\n  Err(e) => { print e; }\n}\nlet xs = [Some(1), None];\nlet rs: Array[Result[Int, String]] = [Ok(1), Err(\"e\")];\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+}
+
+#[test]
+fn try_call_returns_a_result_with_tool_error() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nfn ask(q: String) -> Result[String, ToolError] !tool[toolCap] {\n  let raw = try call MockEcho { message: q } using toolCap timeout 10;\n  return Ok(raw?.echo + \"!\");\n}\nmatch ask(\"hi\") {\n  Ok(out) => { print out; }\n  Err(e) => { print e.tool + \": \" + e.message; }\n}\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.manifest.required_caps, vec!["toolCap".to_string()]);
+}
+
+#[test]
+fn reports_uninferable_and_mismatched_variants() {
+    let src = "let a = None;\nlet b = Ok(1);\nlet c: Int = Err(\"x\");\nlet d: Option[Int] = Some(\"s\");\nlet e = Some(1, 2);\nlet f: Result[Int, String] = Ok(1);\nmatch f { Ok(_) => { } }\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::CANNOT_INFER,
+                "cannot infer the type of `None`".to_string()
+            ),
+            (
+                codes::CANNOT_INFER,
+                "cannot infer the type of `Ok`".to_string()
+            ),
+            (
+                codes::TYPE_MISMATCH,
+                "expected Int, found `Err`".to_string()
+            ),
+            (
+                codes::TYPE_MISMATCH,
+                "Some expected Int, got String".to_string()
+            ),
+            (
+                codes::ARITY_MISMATCH,
+                "Some takes 1 value(s), got 2".to_string()
+            ),
+            (
+                codes::NON_EXHAUSTIVE_MATCH,
+                "non-exhaustive match: `Err(_)` not covered".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn question_mark_requires_a_compatible_function() {
+    let src = "fn a() -> Option[Int] { return Some(1); }\nfn b() -> Result[Int, String] { return Ok(a()?); }\nfn c() -> Result[Int, Int] { let r: Result[Int, String] = Ok(1); return Ok(r?); }\nfn d() -> Int { return a()?; }\nprint a()?;\nfn e() -> Option[Int] { return Some(1?); }\nstruct Option { x: Int }\n";
+    let codes = diagnostics(src)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![
+            codes::DUPLICATE_DECLARATION,
+            codes::INVALID_RETURN,
+            codes::INVALID_RETURN,
+            codes::INVALID_RETURN,
+            codes::INVALID_RETURN,
+            codes::TYPE_MISMATCH,
+        ]
+    );
+    let messages = diagnostics(src)
+        .into_iter()
+        .map(|(_, m)| m)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages[2],
+        "`?` on Result[Int, String] needs function c to return Result[_, String], but it returns Result[Int, Int]"
+    );
+}
@@ -1,5 +1,9 @@
 use axiom_compiler::{
-    manifest::render_manifest, parser::parse_program, typecheck::typecheck, Effect,
+    manifest::render_manifest,
+    parser::{parse_file, parse_program},
+    span::SourceMap,
+    typecheck::typecheck,
+    Effect,
 };
 
 #[test]
@@ -7,7 +11,7 @@ fn parses_and_typechecks_pure_program() {
     let src = "let x = 1 + 2;\nprint x;\n";
     let p = parse_program(src).expect("parse");
     let typed = typecheck(p).expect("typecheck");
-    assert_eq!(typed.effect, Effect::Pure);
+    assert_eq!(typed.effect, Effect::PURE);
 }
 
 #[test]
@@ -15,7 +19,7 @@ fn detects_tool_effect_and_manifest() {
     let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\ncall MockEcho { message: \"hi\" } using toolCap timeout 1000;";
     let p = parse_program(src).expect("parse");
     let typed = typecheck(p).expect("typecheck");
-    assert_eq!(typed.effect, Effect::Tool);
+    assert_eq!(typed.effect, Effect::TOOL);
     let manifest = render_manifest(&typed);
     assert!(manifest.contains("requires=toolCap"));
 }
@@ -27,3 +31,40 @@ fn rejects_mismatched_tool_capability() {
     let err = typecheck(p).expect_err("expected cap mismatch");
     assert!(err.to_string().contains("requires cap"));
 }
+
+#[test]
+fn type_errors_point_at_offending_expression() {
+    let mut sources = SourceMap::default();
+    let file = sources.add("main.ax", "let x = 1;\nprint x + missing;\n");
+    let p = parse_file(file, sources.source(file)).expect("parse");
+    let err = typecheck(p).expect_err("expected unknown variable");
+    assert_eq!(err.to_string(), "error[AX0201]: unknown variable: missing");
+    let loc = sources.location(err.span().expect("span"));
+    assert_eq!(loc.to_string(), "main.ax:2:11");
+}
+
+#[test]
+fn types_comparisons_and_logic_as_bool() {
+    let src = "let ok = 1 < 2 && !(3 == 4);\nprint ok == true;\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let err = typecheck(parse_program("print 1 && true;").expect("parse"))
+        .expect_err("expected operand mismatch");
+    assert!(err
+        .to_string()
+        .contains("operator `&&` cannot be applied to Int and Bool"));
+    let err = typecheck(parse_program("print 1 == \"1\";").expect("parse"))
+        .expect_err("expected equality mismatch");
+    assert!(err.to_string().contains("`==` cannot be applied"));
+}
+
+#[test]
+fn float_bytes_and_unit_are_typed() {
+    let src = "fn noop() { }\nlet f = 1.5 * -2.0;\nlet ok = f < 0.5;\nlet b = hex\"00\" + base64\"AQ==\";\nlet u = noop();\nprint u == ();\n";
+    typecheck(parse_program(src).expect("parse")).expect("typecheck");
+
+    let err = typecheck(parse_program("print 1 + 2.0;\nprint -hex\"00\";\n").expect("parse"))
+        .expect_err("mixed operands");
+    let text = err.to_string();
+    assert!(text.contains("operator `+` cannot be applied to Int and Float"));
+    assert!(text.contains("operator `-` cannot be applied to Bytes"));
+}
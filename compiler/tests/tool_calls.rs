@@ -0,0 +1,74 @@
+mod common;
+
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck};
+use common::{diagnostics, warnings};
+
+#[test]
+fn unknown_and_duplicate_call_fields_are_errors() {
+    let src = "tool Search input { query: String, limit: Int } output { n: Int } cap toolCap;\ncall Search { querry: \"x\", query: \"y\", limit: 1, limit: nope } using toolCap timeout 10;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::UNKNOWN_FIELD,
+                "tool Search has no input field querry".to_string()
+            ),
+            (
+                codes::UNKNOWN_VARIABLE,
+                "unknown variable: nope".to_string()
+            ),
+            (
+                codes::DUPLICATE_DECLARATION,
+                "field limit is specified more than once".to_string()
+            ),
+        ]
+    );
+    let err = typecheck(parse_program(src).expect("parse")).expect_err("typo");
+    assert_eq!(
+        err.diagnostics[0].notes,
+        vec!["`Search` declares limit, query"]
+    );
+}
+
+#[test]
+fn tools_cannot_be_declared_twice() {
+    let src = "tool Search input { q: String } output { n: Int } cap toolCap;\ntool Search input { q: Int, q: Int } output { n: String } cap toolCap;\ntool Fetch input { url: String } output { body: String, body: Bytes } cap toolCap;\nlet r = call Search { q: \"x\" } using toolCap timeout 10;\nlet b: String = (call Fetch { url: \"u\" } using toolCap timeout 10).body;\n";
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            (
+                codes::DUPLICATE_DECLARATION,
+                "tool Search is declared more than once".to_string()
+            ),
+            (
+                codes::DUPLICATE_DECLARATION,
+                "output field body is declared more than once".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn uncalled_tools_are_warned_about() {
+    let src = "tool Search input { q: String } output { n: Int } cap toolCap;\ntool Unused input { q: String } output { n: Int } cap toolCap;\ntool InFn input { q: String } output { n: Int } cap toolCap;\nfn f() !tool {\n  call InFn { q: \"x\" } using toolCap timeout 10;\n}\ncall Search { q: \"x\" } using toolCap timeout 10;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let warnings = typed
+        .warnings
+        .iter()
+        .map(|d| (d.is_error(), d.code, d.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![(false, codes::UNUSED_TOOL, "tool Unused is never called")]
+    );
+}
+
+#[test]
+fn zero_timeouts_are_warned_about_wherever_the_call_is() {
+    let src = "tool Search input { q: String } output { n: Int } cap toolCap;\ncall Search { q: \"a\" } using toolCap timeout 0;\nfn f() -> Int !tool {\n  if true {\n    let r = call Search { q: \"b\" } using toolCap timeout 0;\n    return r.n;\n  }\n  return (call Search { q: \"c\" } using toolCap timeout 10).n;\n}\nwhile false {\n  let _r = try call Search { q: \"d\" } using toolCap timeout 0;\n}\nprint f();\n";
+    let codes = warnings(src)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>();
+    assert_eq!(codes, vec![codes::ZERO_TIMEOUT; 3]);
+}
@@ -0,0 +1,39 @@
+use axiom_compiler::{diagnostic::codes, parser::parse_program, typecheck::typecheck};
+
+const TOOLS: &str = "struct Hit { url: String, score: Float }\ntool Search input { q: String } output { hits: Array[Hit], total: Int, next: Option[String] } cap toolCap;\n";
+
+#[test]
+fn call_expressions_have_the_declared_output_record_type() {
+    let src = format!(
+        "{TOOLS}let r = call Search {{ q: \"axiom\" }} using toolCap timeout 100;\nlet best: Hit = r.hits[0];\nprint best.url + \"{{r.total + 1}}\";\nlet again: {{ total: Int, next: Option[String], hits: Array[Hit] }} = r;\nfn first(q: String) -> Option[String] !tool {{\n  return (call Search {{ q: q }} using toolCap timeout 100).next;\n}}\n"
+    );
+    let typed = typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    assert_eq!(typed.effect, axiom_compiler::Effect::TOOL);
+}
+
+#[test]
+fn record_fields_are_statically_checked() {
+    let src = format!(
+        "{TOOLS}let r = call Search {{ q: \"axiom\" }} using toolCap timeout 100;\nprint r.count;\nlet n: String = r.total;\nlet t = try call Search {{ q: \"x\" }} using toolCap timeout 100;\nprint t.hits;\n"
+    );
+    let err = typecheck(parse_program(&src).expect("parse")).expect_err("should not typecheck");
+    let diags = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.code, d.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diags,
+        vec![
+            (
+                codes::UNKNOWN_FIELD,
+                "type { hits: Array[Hit], next: Option[String], total: Int } has no field count"
+            ),
+            (codes::TYPE_MISMATCH, "let n expected String, got Int"),
+            (
+                codes::UNKNOWN_FIELD,
+                "type Result[{ hits: Array[Hit], next: Option[String], total: Int }, ToolError] has no field hits"
+            ),
+        ]
+    );
+}
@@ -1,5 +1,8 @@
-use std::collections::HashMap;
+pub use axiom_compiler::CapabilityKind;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Component, Path};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
 static NEXT_ID: AtomicU64 = AtomicU64::new(1);
 
@@ -7,42 +10,428 @@ static NEXT_ID: AtomicU64 = AtomicU64::new(1);
 pub struct Capability {
     id: u64,
     kind: CapabilityKind,
+    scope: Scope,
+    /// This capability's budget meter, if it has one, then its ancestors'.
+    /// Derived capabilities share their parent's meters, so narrowing never
+    /// resets what has been spent.
+    meters: Vec<Arc<Meter>>,
+    /// Ids of the capabilities this one was derived from, parent first.
+    ancestors: Vec<u64>,
+    revocations: Option<RevocationTable>,
+    /// Synthetic clock time at which this capability stops being valid.
+    expires_at_ms: Option<u64>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum CapabilityKind {
-    Tool,
-    Net,
-    Fs,
-    Memory,
+/// Revoked capability ids and why, shared between the operator and running
+/// programs. Revoking a capability also revokes everything derived from it.
+#[derive(Debug, Clone, Default)]
+pub struct RevocationTable {
+    revoked: Arc<Mutex<HashMap<u64, String>>>,
 }
 
-impl Capability {
-    pub fn can_use_tool(&self) -> bool {
-        self.kind == CapabilityKind::Tool
+impl RevocationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn revoke(&self, cap: &Capability, reason: &str) {
+        self.revoke_id(cap.id, reason);
+    }
+
+    pub fn revoke_id(&self, id: u64, reason: &str) {
+        self.revoked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(id)
+            .or_insert_with(|| reason.to_string());
+    }
+
+    /// Why the first revoked capability among `ids` was revoked.
+    fn reason(&self, ids: impl IntoIterator<Item = u64>) -> Option<String> {
+        let revoked = self.revoked.lock().unwrap_or_else(PoisonError::into_inner);
+        ids.into_iter().find_map(|id| revoked.get(&id).cloned())
+    }
+}
+
+/// Limits on what a capability may spend over its lifetime; `None` is
+/// unlimited. Also used for what is left of a budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub calls: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    /// Abstract cost units, as declared per tool in the `ToolRegistry`.
+    pub cost: Option<u64>,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn with_calls(mut self, calls: u64) -> Self {
+        self.calls = Some(calls);
+        self
     }
 
-    pub fn narrow_to_tool(&self) -> Option<Self> {
-        if self.can_use_tool() {
-            Some(Self {
-                id: self.id,
-                kind: CapabilityKind::Tool,
-            })
-        } else {
-            None
+    pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+
+    pub fn with_cost(mut self, cost: u64) -> Self {
+        self.cost = Some(cost);
+        self
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::unlimited()
+    }
+
+    /// The tighter of the two limits for each quantity.
+    fn min(self, other: Budget) -> Budget {
+        let min = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Budget {
+            calls: min(self.calls, other.calls),
+            timeout_ms: min(self.timeout_ms, other.timeout_ms),
+            cost: min(self.cost, other.cost),
         }
     }
 }
 
-fn mint(kind: CapabilityKind) -> Capability {
-    Capability {
-        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
-        kind,
+/// What one tool call spends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spend {
+    pub calls: u64,
+    pub timeout_ms: u64,
+    pub cost: u64,
+}
+
+#[derive(Debug)]
+struct Meter {
+    budget: Budget,
+    spent: Mutex<Spend>,
+}
+
+impl Meter {
+    fn spent(&self) -> std::sync::MutexGuard<'_, Spend> {
+        self.spent.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn remaining(&self, spent: &Spend) -> Budget {
+        let left = |limit: Option<u64>, used: u64| limit.map(|limit| limit.saturating_sub(used));
+        Budget {
+            calls: left(self.budget.calls, spent.calls),
+            timeout_ms: left(self.budget.timeout_ms, spent.timeout_ms),
+            cost: left(self.budget.cost, spent.cost),
+        }
+    }
+}
+
+/// The resources a capability reaches. Each field restricts the capabilities
+/// of one kind; `None` leaves it unrestricted and an empty set grants nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scope {
+    /// Tools a `tool` capability may call.
+    pub tools: Option<BTreeSet<String>>,
+    /// Hosts a `net` capability may reach: `api.example.com`, or
+    /// `*.example.com` for any subdomain.
+    pub hosts: Option<BTreeSet<String>>,
+    /// Path prefixes an `fs` capability may read, matched by component.
+    pub paths: Option<BTreeSet<String>>,
+    /// Memory namespaces a `memory` capability may access.
+    pub namespaces: Option<BTreeSet<String>>,
+}
+
+impl Scope {
+    /// A scope that restricts nothing.
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tools<S: Into<String>>(mut self, tools: impl IntoIterator<Item = S>) -> Self {
+        self.tools = Some(tools.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_hosts<S: Into<String>>(mut self, hosts: impl IntoIterator<Item = S>) -> Self {
+        self.hosts = Some(
+            hosts
+                .into_iter()
+                .map(|h| h.into().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_paths<S: Into<String>>(mut self, paths: impl IntoIterator<Item = S>) -> Self {
+        self.paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_namespaces<S: Into<String>>(
+        mut self,
+        namespaces: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.namespaces = Some(namespaces.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The resources both scopes reach. Never wider than either.
+    pub fn intersect(&self, other: &Scope) -> Scope {
+        Scope {
+            tools: intersect_with(&self.tools, &other.tools, |a, b| a == b),
+            hosts: intersect_with(&self.hosts, &other.hosts, host_covers),
+            paths: intersect_with(&self.paths, &other.paths, path_covers),
+            namespaces: intersect_with(&self.namespaces, &other.namespaces, |a, b| a == b),
+        }
+    }
+
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.tools.as_ref().is_none_or(|tools| tools.contains(tool))
+    }
+
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.hosts
+            .as_ref()
+            .is_none_or(|hosts| hosts.iter().any(|p| host_covers(p, &host)))
+    }
+
+    /// Paths are matched lexically, so any path containing `..` is refused.
+    pub fn allows_path(&self, path: &str) -> bool {
+        if Path::new(path)
+            .components()
+            .any(|c| c == Component::ParentDir)
+        {
+            return false;
+        }
+        self.paths
+            .as_ref()
+            .is_none_or(|paths| paths.iter().any(|p| path_covers(p, path)))
+    }
+
+    pub fn allows_namespace(&self, namespace: &str) -> bool {
+        self.namespaces
+            .as_ref()
+            .is_none_or(|namespaces| namespaces.contains(namespace))
+    }
+}
+
+/// Members of `a` covered by `b` and members of `b` covered by `a`, where
+/// `covers(x, y)` means everything `y` matches, `x` matches too.
+fn intersect_with(
+    a: &Option<BTreeSet<String>>,
+    b: &Option<BTreeSet<String>>,
+    covers: impl Fn(&str, &str) -> bool,
+) -> Option<BTreeSet<String>> {
+    match (a, b) {
+        (None, other) | (other, None) => other.clone(),
+        (Some(a), Some(b)) => Some(
+            a.iter()
+                .filter(|x| b.iter().any(|y| covers(y, x)))
+                .chain(b.iter().filter(|y| a.iter().any(|x| covers(x, y))))
+                .cloned()
+                .collect(),
+        ),
     }
 }
 
+/// Whether host pattern `pattern` matches every host `other` matches.
+fn host_covers(pattern: &str, other: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) if suffix.starts_with('.') => {
+            other.strip_prefix('*').unwrap_or(other).ends_with(suffix)
+        }
+        _ => pattern == other,
+    }
+}
+
+fn path_covers(prefix: &str, path: &str) -> bool {
+    Path::new(path).starts_with(prefix)
+}
+
+impl Capability {
+    /// Mints a fresh capability of `kind` limited to `scope`.
+    pub fn new(kind: CapabilityKind, scope: Scope) -> Self {
+        Capability {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            kind,
+            scope,
+            meters: Vec::new(),
+            ancestors: Vec::new(),
+            revocations: None,
+            expires_at_ms: None,
+        }
+    }
+
+    /// Makes this capability, and those derived from it, revocable through
+    /// `table`.
+    pub fn revocable(mut self, table: &RevocationTable) -> Self {
+        self.revocations = Some(table.clone());
+        self
+    }
+
+    /// Expires this capability at `at_ms` on the synthetic clock, unless it
+    /// already expires earlier.
+    pub fn expires_at(mut self, at_ms: u64) -> Self {
+        self.expires_at_ms = Some(self.expires_at_ms.map_or(at_ms, |t| t.min(at_ms)));
+        self
+    }
+
+    pub fn expires_at_ms(&self) -> Option<u64> {
+        self.expires_at_ms
+    }
+
+    /// Why this capability, or one it was derived from, was revoked.
+    pub fn revocation_reason(&self) -> Option<String> {
+        self.revocations
+            .as_ref()?
+            .reason(std::iter::once(self.id).chain(self.ancestors.iter().copied()))
+    }
+
+    /// Why this capability cannot be used at `now_ms`, if it cannot.
+    pub fn invalidity(&self, now_ms: u64) -> Option<String> {
+        if let Some(reason) = self.revocation_reason() {
+            return Some(format!("revoked: {reason}"));
+        }
+        self.expires_at_ms
+            .filter(|at| now_ms >= *at)
+            .map(|at| format!("expired at {at} ms"))
+    }
+
+    /// Limits this capability to `budget`, on top of any budget it already
+    /// has or inherited.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.meters.insert(
+            0,
+            Arc::new(Meter {
+                budget,
+                spent: Mutex::new(Spend::default()),
+            }),
+        );
+        self
+    }
+
+    /// What is left of the tightest budget this capability is under.
+    pub fn remaining(&self) -> Budget {
+        self.meters
+            .iter()
+            .map(|meter| meter.remaining(&meter.spent()))
+            .fold(Budget::unlimited(), Budget::min)
+    }
+
+    /// Charges `spend` against every budget this capability is under, or
+    /// charges nothing and describes the first limit it would exceed.
+    pub fn charge(&self, spend: Spend) -> Result<(), String> {
+        let mut spent = self.meters.iter().map(|m| m.spent()).collect::<Vec<_>>();
+        for (meter, spent) in self.meters.iter().zip(&spent) {
+            let left = meter.remaining(spent);
+            let limits = [
+                ("tool call", meter.budget.calls, left.calls, spend.calls),
+                (
+                    "timeout ms",
+                    meter.budget.timeout_ms,
+                    left.timeout_ms,
+                    spend.timeout_ms,
+                ),
+                ("cost", meter.budget.cost, left.cost, spend.cost),
+            ];
+            for (what, limit, left, asked) in limits {
+                if let (Some(limit), Some(left)) = (limit, left) {
+                    if asked > left {
+                        return Err(format!(
+                            "{what} limit of {limit} reached ({left} left, {asked} needed)"
+                        ));
+                    }
+                }
+            }
+        }
+        for spent in &mut spent {
+            spent.calls += spend.calls;
+            spent.timeout_ms = spent.timeout_ms.saturating_add(spend.timeout_ms);
+            spent.cost = spent.cost.saturating_add(spend.cost);
+        }
+        Ok(())
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn kind(&self) -> CapabilityKind {
+        self.kind
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    pub fn can_use_tool(&self, tool: &str) -> bool {
+        self.kind == CapabilityKind::Tool && self.scope.allows_tool(tool)
+    }
+
+    pub fn can_reach_host(&self, host: &str) -> bool {
+        self.kind == CapabilityKind::Net && self.scope.allows_host(host)
+    }
+
+    pub fn can_read_path(&self, path: &str) -> bool {
+        self.kind == CapabilityKind::Fs && self.scope.allows_path(path)
+    }
+
+    pub fn can_use_namespace(&self, namespace: &str) -> bool {
+        self.kind == CapabilityKind::Memory && self.scope.allows_namespace(namespace)
+    }
+
+    /// Derives a new capability reaching only what both this capability and
+    /// `scope` reach.
+    pub fn narrow(&self, scope: &Scope) -> Self {
+        Capability {
+            meters: self.meters.clone(),
+            ancestors: std::iter::once(self.id)
+                .chain(self.ancestors.iter().copied())
+                .collect(),
+            revocations: self.revocations.clone(),
+            expires_at_ms: self.expires_at_ms,
+            ..Self::new(self.kind, self.scope.intersect(scope))
+        }
+    }
+
+    /// Derives a capability for `tool` alone, if this one may call it.
+    pub fn narrow_to_tool(&self, tool: &str) -> Option<Self> {
+        self.can_use_tool(tool)
+            .then(|| self.narrow(&Scope::unrestricted().with_tools([tool])))
+    }
+}
+
+/// `toolCap`, plus `timeCap` and `randomCap`: time and randomness are
+/// synthetic and seeded, so granting them cannot make a run nondeterministic.
 pub fn default_capabilities() -> HashMap<String, Capability> {
     let mut caps = HashMap::new();
-    caps.insert("toolCap".to_string(), mint(CapabilityKind::Tool));
+    for kind in [
+        CapabilityKind::Tool,
+        CapabilityKind::Time,
+        CapabilityKind::Random,
+    ] {
+        caps.insert(
+            format!("{}Cap", kind.name()),
+            Capability::new(kind, Scope::unrestricted()),
+        );
+    }
+    caps
+}
+
+/// The default capabilities plus one unrestricted `{kind}Cap` for each of
+/// `kinds`.
+pub fn capabilities_with(kinds: &[CapabilityKind]) -> HashMap<String, Capability> {
+    let mut caps = default_capabilities();
+    for kind in kinds {
+        caps.insert(
+            format!("{}Cap", kind.name()),
+            Capability::new(*kind, Scope::unrestricted()),
+        );
+    }
     caps
 }
@@ -1,3 +1,4 @@
+use axiom_compiler::span::{SourceMap, Span};
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -9,6 +10,47 @@ pub enum RuntimeError {
     ToolValidation(String),
     ToolExecution(String),
     Eval(String),
+    /// Int arithmetic whose result does not fit in an `i64`, e.g. `i64::MAX + 1`.
+    Overflow(String),
+    DivisionByZero,
+    /// A tool call would exceed a capability's budget.
+    BudgetExceeded(String),
+    /// A capability was used after being revoked or after it expired.
+    Revoked(String),
+    /// A tool call's timeout would run past its capability's expiry.
+    TimeoutExceedsExpiry(String),
+    At {
+        span: Span,
+        error: Box<RuntimeError>,
+    },
+}
+
+impl RuntimeError {
+    /// Attaches a source location unless the error already carries a more precise one.
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            RuntimeError::At { .. } => self,
+            error => RuntimeError::At {
+                span,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RuntimeError::At { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Formats the error prefixed with its `file:line:col` location, when known.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        match self.span() {
+            Some(span) => format!("{}: {self}", sources.location(span)),
+            None => self.to_string(),
+        }
+    }
 }
 
 impl Display for RuntimeError {
@@ -21,6 +63,14 @@ impl Display for RuntimeError {
             RuntimeError::ToolValidation(s) => write!(f, "tool validation failed: {s}"),
             RuntimeError::ToolExecution(s) => write!(f, "tool execution failed: {s}"),
             RuntimeError::Eval(s) => write!(f, "evaluation error: {s}"),
+            RuntimeError::Overflow(s) => write!(f, "integer overflow: {s}"),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::BudgetExceeded(s) => write!(f, "budget exceeded: {s}"),
+            RuntimeError::Revoked(s) => write!(f, "capability no longer valid: {s}"),
+            RuntimeError::TimeoutExceedsExpiry(s) => {
+                write!(f, "timeout outlives capability: {s}")
+            }
+            RuntimeError::At { error, .. } => write!(f, "{error}"),
         }
     }
 }
@@ -1,5 +1,7 @@
 pub mod capability;
 pub mod error;
+pub mod json;
+pub mod policy;
 pub mod replay;
 pub mod runtime;
 pub mod tools;
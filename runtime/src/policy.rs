@@ -0,0 +1,265 @@
+//! Capability grants loaded from a policy file, a small subset of TOML:
+//!
+//! ```toml
+//! # One table per capability, named as programs refer to it.
+//! [toolCap]
+//! kind = "tool"
+//! tools = ["Search", "MockEcho"]
+//! max_calls = 20
+//!
+//! [fsCap]
+//! kind = "fs"
+//! paths = ["/srv/data"]
+//! ```
+//!
+//! Every table needs a `kind`. `tools`, `hosts`, `paths` and `namespaces`
+//! scope `tool`, `net`, `fs` and `memory` capabilities; `max_calls`,
+//! `max_timeout_ms` and `max_cost` budget tool capabilities; `expires_at_ms`
+//! expires any capability on the synthetic clock.
+
+use crate::capability::{Budget, Capability, CapabilityKind, Scope};
+use std::collections::HashMap;
+
+pub fn parse_grants(text: &str) -> Result<HashMap<String, Capability>, String> {
+    let mut grants: Vec<Grant> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        parse_line(line, idx + 1, &mut grants).map_err(|e| format!("line {}: {e}", idx + 1))?;
+    }
+    grants
+        .into_iter()
+        .map(|grant| {
+            let kind = grant
+                .kind
+                .ok_or_else(|| format!("line {}: [{}] has no kind", grant.line, grant.name))?;
+            let mut cap = Capability::new(kind, grant.scope);
+            if !grant.budget.is_unlimited() {
+                cap = cap.with_budget(grant.budget);
+            }
+            if let Some(at_ms) = grant.expires_at_ms {
+                cap = cap.expires_at(at_ms);
+            }
+            Ok((grant.name, cap))
+        })
+        .collect()
+}
+
+struct Grant {
+    name: String,
+    /// Line of the table header, for errors about the table as a whole.
+    line: usize,
+    kind: Option<CapabilityKind>,
+    scope: Scope,
+    budget: Budget,
+    expires_at_ms: Option<u64>,
+}
+
+enum Value {
+    Str(String),
+    Int(u64),
+    List(Vec<String>),
+}
+
+fn parse_line(line: &str, line_number: usize, grants: &mut Vec<Grant>) -> Result<(), String> {
+    let mut cursor = Cursor {
+        rest: line.trim_start(),
+    };
+    if cursor.at_end() {
+        return Ok(());
+    }
+    if cursor.eat('[') {
+        let name = cursor.ident()?;
+        cursor.expect(']')?;
+        cursor.end()?;
+        if grants.iter().any(|g| g.name == name) {
+            return Err(format!("[{name}] is declared more than once"));
+        }
+        grants.push(Grant {
+            name,
+            line: line_number,
+            kind: None,
+            scope: Scope::unrestricted(),
+            budget: Budget::unlimited(),
+            expires_at_ms: None,
+        });
+        return Ok(());
+    }
+    let key = cursor.ident()?;
+    cursor.expect('=')?;
+    let value = cursor.value()?;
+    cursor.end()?;
+    let grant = grants
+        .last_mut()
+        .ok_or_else(|| format!("{key} must be inside a [capability] table"))?;
+    grant.set(&key, value)
+}
+
+impl Grant {
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        let scoped = |kind: CapabilityKind| match self.kind {
+            Some(k) if k == kind => Ok(()),
+            Some(k) => Err(format!("{key} does not apply to {} capabilities", k.name())),
+            None => Err(format!("{key} must come after kind")),
+        };
+        match (key, value) {
+            ("kind", Value::Str(name)) => {
+                if self.kind.is_some() {
+                    return Err("kind is set more than once".to_string());
+                }
+                self.kind = Some(
+                    CapabilityKind::from_name(&name)
+                        .ok_or_else(|| format!("unknown capability kind: {name}"))?,
+                );
+            }
+            ("tools", Value::List(items)) => {
+                scoped(CapabilityKind::Tool)?;
+                self.scope = std::mem::take(&mut self.scope).with_tools(items);
+            }
+            ("hosts", Value::List(items)) => {
+                scoped(CapabilityKind::Net)?;
+                self.scope = std::mem::take(&mut self.scope).with_hosts(items);
+            }
+            ("paths", Value::List(items)) => {
+                scoped(CapabilityKind::Fs)?;
+                self.scope = std::mem::take(&mut self.scope).with_paths(items);
+            }
+            ("namespaces", Value::List(items)) => {
+                scoped(CapabilityKind::Memory)?;
+                self.scope = std::mem::take(&mut self.scope).with_namespaces(items);
+            }
+            ("max_calls", Value::Int(n)) => {
+                scoped(CapabilityKind::Tool)?;
+                self.budget = self.budget.with_calls(n);
+            }
+            ("max_timeout_ms", Value::Int(n)) => {
+                scoped(CapabilityKind::Tool)?;
+                self.budget = self.budget.with_timeout_ms(n);
+            }
+            ("max_cost", Value::Int(n)) => {
+                scoped(CapabilityKind::Tool)?;
+                self.budget = self.budget.with_cost(n);
+            }
+            ("expires_at_ms", Value::Int(n)) => self.expires_at_ms = Some(n),
+            ("kind", _) => return Err("kind must be a string".to_string()),
+            ("tools" | "hosts" | "paths" | "namespaces", _) => {
+                return Err(format!("{key} must be an array of strings"))
+            }
+            ("max_calls" | "max_timeout_ms" | "max_cost" | "expires_at_ms", _) => {
+                return Err(format!("{key} must be a non-negative integer"))
+            }
+            _ => return Err(format!("unknown key: {key}")),
+        }
+        Ok(())
+    }
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl Cursor<'_> {
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_space();
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(format!("unexpected `{}`", self.rest))
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected `{c}`"))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        self.skip_space();
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if len == 0 {
+            return Err("expected a name".to_string());
+        }
+        let (ident, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(ident.to_string())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_space();
+        if self.rest.starts_with('"') {
+            return self.string().map(Value::Str);
+        }
+        if self.eat('[') {
+            let mut items = Vec::new();
+            while !self.eat(']') {
+                if self.at_end() {
+                    return Err("unterminated array".to_string());
+                }
+                items.push(self.string()?);
+                if !self.eat(',') {
+                    self.expect(']')?;
+                    break;
+                }
+            }
+            return Ok(Value::List(items));
+        }
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+            .unwrap_or(self.rest.len());
+        let (digits, rest) = self.rest.split_at(len);
+        let value = digits
+            .replace('_', "")
+            .parse::<u64>()
+            .map_err(|_| "expected a string, integer or array".to_string())?;
+        self.rest = rest;
+        Ok(Value::Int(value))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.skip_space();
+        let mut chars = self
+            .rest
+            .strip_prefix('"')
+            .ok_or("expected a string")?
+            .char_indices();
+        let mut out = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 2..];
+                    return Ok(out);
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => out.push(c),
+                    _ => return Err("unsupported escape in string".to_string()),
+                },
+                c => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
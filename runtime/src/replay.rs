@@ -12,12 +12,46 @@ pub enum Event {
         output_hash: String,
         policy_tags: Vec<String>,
     },
+    /// A tool call that failed or returned invalid output.
+    ToolError {
+        tool: String,
+        input: String,
+        error: String,
+    },
     Random {
         value: u64,
     },
+    /// The synthetic clock, in milliseconds, as read by `now()` or after a
+    /// tool call advanced it.
     Time {
         millis: u64,
     },
+    /// What is left of a capability's budget after a tool call; `None` is
+    /// unlimited.
+    Budget {
+        cap: String,
+        calls: Option<u64>,
+        timeout_ms: Option<u64>,
+        cost: Option<u64>,
+    },
+    /// A capability was found revoked or expired when the program used it.
+    Revoked {
+        cap: String,
+        reason: String,
+    },
+    /// A tool call was refused because its timeout ends after the
+    /// capability it uses expires.
+    TimeoutExceedsExpiry {
+        cap: String,
+        deadline_ms: u64,
+        expires_at_ms: u64,
+    },
+    /// `name` was derived from `parent` by `narrow`, granting only `tools`.
+    CapDerived {
+        name: String,
+        parent: String,
+        tools: Vec<String>,
+    },
 }
 
 #[derive(Debug, Default, Clone)]
@@ -77,13 +111,49 @@ fn event_to_line(e: &Event) -> String {
             escape(output_hash),
             escape(&policy_tags.join(","))
         ),
+        Event::ToolError { tool, input, error } => format!(
+            "TOOLERR|{}|{}|{}",
+            escape(tool),
+            escape(input),
+            escape(error)
+        ),
         Event::Random { value } => format!("RANDOM|{value}"),
         Event::Time { millis } => format!("TIME|{millis}"),
+        Event::Budget {
+            cap,
+            calls,
+            timeout_ms,
+            cost,
+        } => format!(
+            "BUDGET|{}|{}|{}|{}",
+            escape(cap),
+            limit_to_text(*calls),
+            limit_to_text(*timeout_ms),
+            limit_to_text(*cost)
+        ),
+        Event::Revoked { cap, reason } => {
+            format!("REVOKED|{}|{}", escape(cap), escape(reason))
+        }
+        Event::TimeoutExceedsExpiry {
+            cap,
+            deadline_ms,
+            expires_at_ms,
+        } => format!("EXPIRYWINDOW|{}|{deadline_ms}|{expires_at_ms}", escape(cap)),
+        Event::CapDerived {
+            name,
+            parent,
+            tools,
+        } => format!(
+            "CAPDERIVE|{}|{}|{}",
+            escape(name),
+            escape(parent),
+            escape(&tools.join(","))
+        ),
     }
 }
 
 fn parse_line(line: &str) -> Result<Event, String> {
-    let parts: Vec<&str> = line.split('|').collect();
+    let parts = split_fields(line);
     match parts.first().copied().unwrap_or_default() {
         "PRINT" if parts.len() == 2 => Ok(Event::Print {
             value: unescape(parts[1]),
@@ -103,6 +173,11 @@ fn parse_line(line: &str) -> Result<Event, String> {
                 .map(ToString::to_string)
                 .collect(),
         }),
+        "TOOLERR" if parts.len() == 4 => Ok(Event::ToolError {
+            tool: unescape(parts[1]),
+            input: unescape(parts[2]),
+            error: unescape(parts[3]),
+        }),
         "RANDOM" if parts.len() == 2 => Ok(Event::Random {
             value: parts[1]
                 .parse::<u64>()
@@ -113,10 +188,73 @@ fn parse_line(line: &str) -> Result<Event, String> {
                 .parse::<u64>()
                 .map_err(|_| "invalid millis".to_string())?,
         }),
+        "BUDGET" if parts.len() == 5 => Ok(Event::Budget {
+            cap: unescape(parts[1]),
+            calls: parse_limit(parts[2])?,
+            timeout_ms: parse_limit(parts[3])?,
+            cost: parse_limit(parts[4])?,
+        }),
+        "REVOKED" if parts.len() == 3 => Ok(Event::Revoked {
+            cap: unescape(parts[1]),
+            reason: unescape(parts[2]),
+        }),
+        "EXPIRYWINDOW" if parts.len() == 4 => Ok(Event::TimeoutExceedsExpiry {
+            cap: unescape(parts[1]),
+            deadline_ms: parts[2]
+                .parse::<u64>()
+                .map_err(|_| "invalid deadline".to_string())?,
+            expires_at_ms: parts[3]
+                .parse::<u64>()
+                .map_err(|_| "invalid expiry".to_string())?,
+        }),
+        "CAPDERIVE" if parts.len() == 4 => Ok(Event::CapDerived {
+            name: unescape(parts[1]),
+            parent: unescape(parts[2]),
+            tools: unescape(parts[3])
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        }),
         _ => Err("invalid replay event".to_string()),
     }
 }
 
+/// Budget limits are written as numbers, or `-` when unlimited.
+fn limit_to_text(limit: Option<u64>) -> String {
+    limit.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+fn parse_limit(s: &str) -> Result<Option<u64>, String> {
+    match s {
+        "-" => Ok(None),
+        _ => s
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| "invalid budget".to_string()),
+    }
+}
+
+/// Splits a line on the `|` separators, leaving escaped pipes in place.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '|' => {
+                parts.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&line[start..]);
+    parts
+}
+
 fn escape(s: &str) -> String {
     s.replace('\\', "\\\\")
         .replace('|', "\\|")
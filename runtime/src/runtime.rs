@@ -1,26 +1,106 @@
 use crate::{
-    capability::{default_capabilities, Capability},
+    capability::{default_capabilities, Capability, CapabilityKind, Scope, Spend},
     error::RuntimeError,
+    json::{self, quote, Json},
     replay::{Event, ReplayLog},
     tools::ToolRegistry,
 };
-use axiom_compiler::{BinOp, Effect, Expr, Stmt, Type};
-use std::collections::HashMap;
+use axiom_compiler::{
+    encoding::{decode_base64, encode_base64, encode_hex},
+    BinOp, Block, Expr, ExprKind, FnDecl, MatchArm, Pattern, PatternKind, Stmt, StmtKind,
+    TemplatePart, ToolCall, ToolSignature, Type, UnaryOp, BUILTIN_ENUMS, TOOL_ERROR,
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Nesting limit for user-defined function calls.
+const MAX_CALL_DEPTH: usize = 64;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i64),
+    Float(f64),
     Bool(bool),
     String(String),
+    Bytes(Vec<u8>),
+    Unit,
+    Array(Vec<Value>),
+    /// Entries sorted by key so iteration, printing and hashing are deterministic.
+    Map(Vec<(Value, Value)>),
+    /// Members sorted and deduplicated.
+    Set(Vec<Value>),
+    /// Fields in declaration order.
+    Struct {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    Enum {
+        name: String,
+        variant: String,
+        payload: Vec<Value>,
+    },
+    /// A tool's output; fields sorted by name.
+    Record(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn variant(name: &str, variant: &str, payload: Vec<Value>) -> Value {
+        Value::Enum {
+            name: name.to_string(),
+            variant: variant.to_string(),
+            payload,
+        }
+    }
+
+    /// Builds a map from entries in source order; later duplicates win.
+    pub fn map_from(entries: Vec<(Value, Value)>) -> Value {
+        let mut map: Vec<(Value, Value)> = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            match map.binary_search_by(|(k, _)| key_cmp(k, &key)) {
+                Ok(idx) => map[idx].1 = value,
+                Err(idx) => map.insert(idx, (key, value)),
+            }
+        }
+        Value::Map(map)
+    }
+
+    pub fn set_from(mut members: Vec<Value>) -> Value {
+        members.sort_by(key_cmp);
+        members.dedup();
+        Value::Set(members)
+    }
+}
+
+/// Total order over key values (Int, Bool, String, Bytes); the typechecker
+/// keeps other values out of maps and sets.
+fn key_cmp(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Int(_) => 0,
+            Value::Bool(_) => 1,
+            Value::String(_) => 2,
+            Value::Bytes(_) => 3,
+            _ => 4,
+        }
+    }
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
 }
 
 pub struct ExecOptions {
     pub deterministic_seed: u64,
+    /// Reject tool outputs carrying fields their schema does not declare.
+    pub strict_tool_outputs: bool,
 }
 impl Default for ExecOptions {
     fn default() -> Self {
         Self {
             deterministic_seed: 42,
+            strict_tool_outputs: false,
         }
     }
 }
@@ -40,135 +120,1207 @@ pub fn execute(
     tools: &ToolRegistry,
     opts: ExecOptions,
 ) -> Result<ReplayLog, RuntimeError> {
-    let mut env = HashMap::<String, Value>::new();
-    let mut log = ReplayLog::default();
-    let mut rng = Lcg::new(opts.deterministic_seed);
+    let (log, result) = execute_logged(typed, capabilities, tools, opts);
+    result.map(|()| log)
+}
+
+/// Like `execute`, but returns the replay log even when the run fails, so the
+/// events leading up to the failure (such as a revocation) can be audited.
+pub fn execute_logged(
+    typed: axiom_compiler::typecheck::TypedProgram,
+    capabilities: &HashMap<String, Capability>,
+    tools: &ToolRegistry,
+    opts: ExecOptions,
+) -> (ReplayLog, Result<(), RuntimeError>) {
+    if let Err(error) = check_named_caps(&typed.manifest.required_caps, capabilities) {
+        return (ReplayLog::default(), Err(error));
+    }
+    for kind in &typed.manifest.required_kinds {
+        if !capabilities.values().any(|c| c.kind() == *kind) {
+            let error = RuntimeError::MissingCapability(format!(
+                "{0} effect requested but no {0} capability supplied",
+                kind.name()
+            ));
+            return (ReplayLog::default(), Err(error));
+        }
+    }
+
+    let fns = typed
+        .program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::FnDecl(decl) => Some((decl.name.as_str(), decl)),
+            _ => None,
+        })
+        .collect();
+    let structs = typed
+        .program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::StructDecl { name, fields } => Some((name.as_str(), fields.as_slice())),
+            _ => None,
+        })
+        .collect();
+    let enums = typed
+        .program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::EnumDecl { name, variants } => Some((name.as_str(), variants.as_slice())),
+            _ => None,
+        })
+        .collect();
+    let mut machine = Machine {
+        capabilities,
+        derived: HashMap::new(),
+        tools,
+        tool_sigs: &typed.tools,
+        fns,
+        structs,
+        enums,
+        depth: 0,
+        unwinding: None,
+        env: Env::default(),
+        log: ReplayLog::default(),
+        rng: Lcg::new(opts.deterministic_seed),
+        clock_ms: 0,
+        strict_tool_outputs: opts.strict_tool_outputs,
+    };
+    let result = machine.exec_stmts(&typed.program.statements).map(|_| ());
+    (machine.log, result)
+}
+
+/// Checks that every capability the program's tool calls name is granted
+/// and grants tool rights, so that a run never fails halfway for lack of one.
+fn check_named_caps(
+    required: &[String],
+    capabilities: &HashMap<String, Capability>,
+) -> Result<(), RuntimeError> {
+    let missing = required
+        .iter()
+        .filter(|cap| !capabilities.contains_key(*cap))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(RuntimeError::MissingCapability(format!(
+            "the program requires {}, which {} not granted",
+            missing.join(", "),
+            if missing.len() == 1 { "is" } else { "are" }
+        )));
+    }
+    match required
+        .iter()
+        .find(|cap| capabilities[*cap].kind() != CapabilityKind::Tool)
+    {
+        Some(cap) => Err(RuntimeError::InvalidCapability(format!(
+            "{cap} is used for tool calls but has kind {}",
+            capabilities[cap].kind().name()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Lexically scoped variable bindings; the innermost scope is last.
+#[derive(Debug)]
+struct Env {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+        }
+    }
+}
+
+impl Env {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn define(&mut self, name: String, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, value);
+        }
+    }
+
+    /// Updates the innermost binding of `name`; false if there is none.
+    fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// How control leaves a statement.
+enum Flow {
+    Normal,
+    Return(Value),
+}
 
-    if typed.effect == Effect::Tool && capabilities.is_empty() {
-        return Err(RuntimeError::MissingCapability(
-            "tool effect requested but no capabilities supplied".into(),
-        ));
+struct Machine<'a> {
+    capabilities: &'a HashMap<String, Capability>,
+    /// Capabilities derived by `narrow` during the run.
+    derived: HashMap<String, Capability>,
+    tools: &'a ToolRegistry,
+    tool_sigs: &'a HashMap<String, ToolSignature>,
+    fns: HashMap<&'a str, &'a FnDecl>,
+    structs: HashMap<&'a str, &'a [(String, Type)]>,
+    enums: HashMap<&'a str, &'a [(String, Vec<Type>)]>,
+    depth: usize,
+    /// The `None`/`Err` being returned by `?`; set while its error unwinds to
+    /// the enclosing statement list.
+    unwinding: Option<Value>,
+    env: Env,
+    log: ReplayLog,
+    rng: Lcg,
+    /// Synthetic clock read by `now()`; advanced by each tool call's timeout.
+    clock_ms: u64,
+    strict_tool_outputs: bool,
+}
+
+impl Machine<'_> {
+    fn exec_stmts(&mut self, stmts: &[Stmt]) -> Result<Flow, RuntimeError> {
+        for stmt in stmts {
+            let flow = match self.exec_stmt(stmt) {
+                Err(_) if self.unwinding.is_some() => Flow::Return(self.unwinding.take().unwrap()),
+                flow => flow.map_err(|e| e.at(stmt.span))?,
+            };
+            self.log.push(Event::Random {
+                value: self.rng.next(),
+            });
+            if let Flow::Return(_) = flow {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_block(&mut self, block: &Block) -> Result<Flow, RuntimeError> {
+        self.env.scopes.push(HashMap::new());
+        let result = self.exec_stmts(&block.stmts);
+        self.env.scopes.pop();
+        result
     }
 
-    let tool_sigs = typed.tools;
-    for stmt in typed.program.statements {
-        match stmt {
-            Stmt::ToolDecl { .. } => {}
-            Stmt::Let { name, expr } => {
-                env.insert(name, eval_expr(&expr, &env)?);
+    fn eval_condition(&mut self, cond: &Expr) -> Result<bool, RuntimeError> {
+        match self.eval_expr(cond)? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(RuntimeError::Eval("condition must be Bool".to_string()).at(cond.span)),
+        }
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<Flow, RuntimeError> {
+        match &stmt.kind {
+            StmtKind::ToolDecl { .. }
+            | StmtKind::FnDecl(_)
+            | StmtKind::StructDecl { .. }
+            | StmtKind::EnumDecl { .. } => {}
+            StmtKind::Match { scrutinee, arms } => {
+                let (arm, bindings) = self.select_arm(scrutinee, arms)?;
+                self.env.scopes.push(bindings);
+                let flow = self.exec_stmts(&arm.body.stmts);
+                self.env.scopes.pop();
+                return flow;
+            }
+            StmtKind::Return { expr } => {
+                let value = match expr {
+                    Some(e) => self.eval_expr(e)?,
+                    None => Value::Unit,
+                };
+                return Ok(Flow::Return(value));
             }
-            Stmt::Print { expr } => {
-                let v = eval_expr(&expr, &env)?;
+            StmtKind::Expr { expr } => {
+                self.eval_expr(expr)?;
+            }
+            StmtKind::Let { name, expr, .. } => {
+                let v = self.eval_expr(expr)?;
+                self.env.define(name.clone(), v);
+            }
+            StmtKind::Assign { name, expr } => {
+                let v = self.eval_expr(expr)?;
+                if !self.env.assign(name, v) {
+                    return Err(
+                        RuntimeError::Eval(format!("unknown variable: {name}")).at(stmt.span)
+                    );
+                }
+            }
+            StmtKind::Print { expr } => {
+                let v = self.eval_expr(expr)?;
                 let msg = format_value(&v);
                 println!("{msg}");
-                log.push(Event::Print { value: msg });
+                self.log.push(Event::Print { value: msg });
             }
-            Stmt::ToolCall {
-                tool,
-                input,
-                cap,
-                timeout_ms,
+            StmtKind::If {
+                cond,
+                then_block,
+                else_block,
             } => {
-                if timeout_ms == 0 {
-                    return Err(RuntimeError::InvalidTimeout(format!(
-                        "tool call {tool} has zero timeout"
-                    )));
+                if self.eval_condition(cond)? {
+                    return self.exec_block(then_block);
+                } else if let Some(else_block) = else_block {
+                    return self.exec_block(else_block);
                 }
-                let c = capabilities
-                    .get(&cap)
-                    .ok_or_else(|| RuntimeError::MissingCapability(cap.clone()))?;
-                if !c.can_use_tool() {
-                    return Err(RuntimeError::InvalidCapability(format!(
-                        "{cap} does not grant tool rights"
-                    )));
+            }
+            StmtKind::While { cond, body } => {
+                while self.eval_condition(cond)? {
+                    if let Flow::Return(value) = self.exec_block(body)? {
+                        return Ok(Flow::Return(value));
+                    }
                 }
-                let sig = tool_sigs
-                    .get(&tool)
-                    .ok_or_else(|| RuntimeError::MissingToolSignature(tool.clone()))?;
-                let mut fields = Vec::new();
-                for (k, e) in input {
-                    let v = eval_expr(&e, &env)?;
-                    fields.push(format!("\"{k}\":{}", to_json(v)));
+            }
+            StmtKind::For { var, iter, body } => {
+                let items = match self.eval_expr(iter)? {
+                    Value::Array(items) | Value::Set(items) => items,
+                    Value::Map(entries) => entries.into_iter().map(|(k, _)| k).collect(),
+                    _ => {
+                        return Err(RuntimeError::Eval("cannot iterate over value".to_string())
+                            .at(iter.span))
+                    }
+                };
+                for item in items {
+                    self.env.scopes.push(HashMap::from([(var.clone(), item)]));
+                    let flow = self.exec_stmts(&body.stmts);
+                    self.env.scopes.pop();
+                    if let Flow::Return(value) = flow? {
+                        return Ok(Flow::Return(value));
+                    }
                 }
-                let input_json = format!("{{{}}}", fields.join(","));
-                let out = tools
-                    .call(&tool, input_json.clone())
-                    .map_err(RuntimeError::ToolExecution)?;
-                validate_tool_output(&out, &sig.output)?;
-                log.push(Event::ToolCall {
-                    tool,
-                    input: input_json,
-                    output_hash: stable_hash_hex(&out),
-                    output: out,
-                    source: "tool-registry".to_string(),
-                    timestamp_ms: 0,
+            }
+            StmtKind::ToolCall(call) => {
+                self.call_tool(call)??;
+            }
+            StmtKind::Narrow {
+                name,
+                parent,
+                tools,
+            } => self.narrow(name, parent, tools)?,
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Performs a tool call, returning its output as a record. Capability,
+    /// signature and timeout errors are `Err(..)`; failures of the tool itself
+    /// are `Ok(Err(..))`, which `try call` hands to the program.
+    fn call_tool(&mut self, call: &ToolCall) -> Result<Result<Value, RuntimeError>, RuntimeError> {
+        let ToolCall {
+            tool,
+            input,
+            cap,
+            timeout_ms,
+        } = call;
+        let timeout_ms = *timeout_ms;
+        if timeout_ms == 0 {
+            return Err(RuntimeError::InvalidTimeout(format!(
+                "tool call {tool} has zero timeout"
+            )));
+        }
+        self.check_valid(cap)?;
+        self.check_valid_until(cap, self.clock_ms.saturating_add(timeout_ms))?;
+        let c = self.capability(cap)?;
+        if c.kind() != CapabilityKind::Tool {
+            return Err(RuntimeError::InvalidCapability(format!(
+                "{cap} does not grant tool rights"
+            )));
+        }
+        if !c.can_use_tool(tool) {
+            return Err(RuntimeError::InvalidCapability(format!(
+                "{cap} does not grant access to tool {tool}"
+            )));
+        }
+        let sig = self
+            .tool_sigs
+            .get(tool)
+            .ok_or_else(|| RuntimeError::MissingToolSignature(tool.clone()))?;
+        let mut fields = Vec::new();
+        for (k, e) in input {
+            let v = self.eval_expr(e)?;
+            fields.push((k.clone(), to_json(&v)));
+        }
+        let input_json = Json::Object(fields).canonical();
+        // Charged only once the call is certain to reach the tool.
+        let c = self.capability(cap)?;
+        c.charge(Spend {
+            calls: 1,
+            timeout_ms,
+            cost: self.tools.cost(tool),
+        })
+        .map_err(|e| RuntimeError::BudgetExceeded(format!("{cap}: {e}")))?;
+        let remaining = c.remaining();
+        let out = self
+            .tools
+            .call(tool, input_json.clone())
+            .map_err(RuntimeError::ToolExecution)
+            .and_then(|out| self.decode_output(&out, &sig.output));
+        match &out {
+            Ok((_, out)) => self.log.push(Event::ToolCall {
+                tool: tool.clone(),
+                input: input_json,
+                output_hash: stable_hash_hex(out),
+                output: out.clone(),
+                source: "tool-registry".to_string(),
+                timestamp_ms: self.clock_ms,
+                policy_tags: vec!["default".to_string()],
+            }),
+            Err(error) => self.log.push(Event::ToolError {
+                tool: tool.clone(),
+                input: input_json,
+                error: error.to_string(),
+            }),
+        }
+        if !remaining.is_unlimited() {
+            self.log.push(Event::Budget {
+                cap: cap.clone(),
+                calls: remaining.calls,
+                timeout_ms: remaining.timeout_ms,
+                cost: remaining.cost,
+            });
+        }
+        self.clock_ms = self.clock_ms.saturating_add(timeout_ms);
+        self.log.push(Event::Time {
+            millis: self.clock_ms,
+        });
+        Ok(out.map(|(record, _)| record))
+    }
+
+    fn capability(&self, name: &str) -> Result<&Capability, RuntimeError> {
+        self.derived
+            .get(name)
+            .or_else(|| self.capabilities.get(name))
+            .ok_or_else(|| RuntimeError::MissingCapability(name.to_string()))
+    }
+
+    /// Derives `name` from `parent`, limited to `tools`, and logs the lineage.
+    fn narrow(&mut self, name: &str, parent: &str, tools: &[String]) -> Result<(), RuntimeError> {
+        self.check_valid(parent)?;
+        let parent_cap = self.capability(parent)?;
+        if parent_cap.kind() != CapabilityKind::Tool {
+            return Err(RuntimeError::InvalidCapability(format!(
+                "{parent} does not grant tool rights"
+            )));
+        }
+        let child = parent_cap.narrow(&Scope::unrestricted().with_tools(tools.iter().cloned()));
+        self.log.push(Event::CapDerived {
+            name: name.to_string(),
+            parent: parent.to_string(),
+            tools: child.scope().tools.iter().flatten().cloned().collect(),
+        });
+        self.derived.insert(name.to_string(), child);
+        Ok(())
+    }
+
+    /// Validates raw tool output against `schema`, returning the decoded
+    /// record and the output's canonical JSON for the replay log.
+    fn decode_output(
+        &self,
+        raw: &str,
+        schema: &HashMap<String, Type>,
+    ) -> Result<(Value, String), RuntimeError> {
+        let json = json::parse(raw)
+            .map_err(|e| RuntimeError::ToolValidation(format!("invalid JSON output: {e}")))?;
+        let mut fields = schema
+            .iter()
+            .map(|(field, ty)| (field.clone(), ty.clone()))
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        let record = self
+            .decode(&json, &Type::Record(fields), "$")
+            .map_err(RuntimeError::ToolValidation)?;
+        Ok((record, json.canonical()))
+    }
+
+    /// Converts JSON to a value of type `ty`; the inverse of `to_json`.
+    /// Errors name the offending JSON path, starting from `path`.
+    fn decode(&self, json: &Json, ty: &Type, path: &str) -> Result<Value, String> {
+        let mismatch = || format!("{path}: expected {ty}, got {}", json.kind());
+        Ok(match (ty, json) {
+            (Type::Int, Json::Int(v)) => Value::Int(*v),
+            (Type::Float, Json::Int(v)) => Value::Float(*v as f64),
+            (Type::Float, Json::Float(v)) => Value::Float(*v),
+            (Type::Bool, Json::Bool(v)) => Value::Bool(*v),
+            (Type::String, Json::String(v)) => Value::String(v.clone()),
+            (Type::Bytes, Json::String(v)) => {
+                Value::Bytes(decode_base64(v).map_err(|e| format!("{path}: {e}"))?)
+            }
+            (Type::Unit, Json::Null) => Value::Unit,
+            (Type::Array(elem), Json::Array(items)) => {
+                Value::Array(self.decode_items(items, std::iter::repeat(&**elem), path)?)
+            }
+            (Type::Set(elem), Json::Array(items)) => {
+                Value::set_from(self.decode_items(items, std::iter::repeat(&**elem), path)?)
+            }
+            (Type::Map(key, value), Json::Object(members)) => {
+                check_unique_keys(members, path)?;
+                Value::map_from(
+                    members
+                        .iter()
+                        .map(|(k, v)| {
+                            let path = format!("{path}[{}]", quote(k));
+                            let key = decode_key(k, key).map_err(|e| format!("{path}: {e}"))?;
+                            Ok((key, self.decode(v, value, &path)?))
+                        })
+                        .collect::<Result<_, String>>()?,
+                )
+            }
+            (Type::Option(_), Json::Null) => Value::variant("Option", "None", Vec::new()),
+            (Type::Option(inner), json) => {
+                Value::variant("Option", "Some", vec![self.decode(json, inner, path)?])
+            }
+            (Type::Result(ok, err), Json::Object(members)) if members.len() == 1 => {
+                let (variant, value) = &members[0];
+                let ty = match variant.as_str() {
+                    "Ok" => ok,
+                    "Err" => err,
+                    _ => return Err(mismatch()),
+                };
+                let path = format!("{path}.{variant}");
+                Value::variant("Result", variant, vec![self.decode(value, ty, &path)?])
+            }
+            (Type::Record(fields), Json::Object(members)) => {
+                Value::Record(self.decode_fields(fields, members, path)?)
+            }
+            (Type::Named(name), Json::Object(members)) if self.structs.contains_key(&**name) => {
+                Value::Struct {
+                    name: name.clone(),
+                    fields: self.decode_fields(self.structs[&**name], members, path)?,
+                }
+            }
+            (Type::Named(name), json) if self.enums.contains_key(&**name) => {
+                let (variant, payload) = match json {
+                    Json::String(variant) => (variant, &[][..]),
+                    Json::Object(members) if members.len() == 1 => match &members[0] {
+                        (variant, Json::Array(payload)) => (variant, payload.as_slice()),
+                        _ => return Err(mismatch()),
+                    },
+                    _ => return Err(mismatch()),
+                };
+                let types = self.enums[&**name]
+                    .iter()
+                    .find(|(v, _)| v == variant)
+                    .map(|(_, types)| types)
+                    .ok_or_else(|| format!("{path}: enum {name} has no variant {variant}"))?;
+                if types.len() != payload.len() {
+                    return Err(format!(
+                        "{path}: variant {name}::{variant} takes {} value(s), got {}",
+                        types.len(),
+                        payload.len()
+                    ));
+                }
+                let path = format!("{path}.{variant}");
+                Value::variant(
+                    name,
+                    variant,
+                    self.decode_items(payload, types.iter(), &path)?,
+                )
+            }
+            _ => return Err(mismatch()),
+        })
+    }
+
+    /// Decodes array elements or enum payloads, pairing items with `types`.
+    fn decode_items<'t>(
+        &self,
+        items: &[Json],
+        types: impl Iterator<Item = &'t Type>,
+        path: &str,
+    ) -> Result<Vec<Value>, String> {
+        items
+            .iter()
+            .zip(types)
+            .enumerate()
+            .map(|(i, (item, ty))| self.decode(item, ty, &format!("{path}[{i}]")))
+            .collect()
+    }
+
+    /// Decodes an object's declared fields. Missing fields are errors, and so
+    /// are undeclared ones in strict mode.
+    fn decode_fields(
+        &self,
+        fields: &[(String, Type)],
+        members: &[(String, Json)],
+        path: &str,
+    ) -> Result<Vec<(String, Value)>, String> {
+        check_unique_keys(members, path)?;
+        if self.strict_tool_outputs {
+            if let Some((extra, _)) = members
+                .iter()
+                .find(|(k, _)| !fields.iter().any(|(field, _)| field == k))
+            {
+                return Err(format!("{path}: unknown field {}", quote(extra)));
+            }
+        }
+        fields
+            .iter()
+            .map(|(field, ty)| {
+                let (_, json) = members
+                    .iter()
+                    .find(|(k, _)| k == field)
+                    .ok_or_else(|| format!("{path}: missing field {field}"))?;
+                Ok((
+                    field.clone(),
+                    self.decode(json, ty, &format!("{path}.{field}"))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Runs a user-defined function in a fresh environment holding only its
+    /// arguments.
+    fn call_fn(&mut self, callee: &str, args: &[Expr], expr: &Expr) -> Result<Value, RuntimeError> {
+        let Some(decl) = self.fns.get(callee).copied() else {
+            return self.call_builtin(callee, args).map_err(|e| e.at(expr.span));
+        };
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::Eval(format!(
+                "call depth exceeded {MAX_CALL_DEPTH} in {callee}"
+            ))
+            .at(expr.span));
+        }
+        let mut frame = HashMap::new();
+        for ((name, _), arg) in decl.params.iter().zip(args) {
+            frame.insert(name.clone(), self.eval_expr(arg)?);
+        }
+        let caller = std::mem::replace(
+            &mut self.env,
+            Env {
+                scopes: vec![frame],
+            },
+        );
+        self.depth += 1;
+        let result = self.exec_stmts(&decl.body.stmts);
+        self.depth -= 1;
+        self.env = caller;
+        match result? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(Value::Unit),
+        }
+    }
+
+    fn call_builtin(&mut self, callee: &str, args: &[Expr]) -> Result<Value, RuntimeError> {
+        match (callee, args) {
+            ("len", [arg]) => self.eval_len(arg),
+            ("now", []) => {
+                self.require(CapabilityKind::Time)?;
+                self.log.push(Event::Time {
+                    millis: self.clock_ms,
+                });
+                Ok(Value::Int(i64::try_from(self.clock_ms).unwrap_or(i64::MAX)))
+            }
+            ("random", []) => {
+                self.require(CapabilityKind::Random)?;
+                let value = self.rng.next();
+                self.log.push(Event::Random { value });
+                Ok(Value::Int((value >> 1) as i64))
+            }
+            ("read_file", [path]) => {
+                let Value::String(path) = self.eval_expr(path)? else {
+                    return Err(RuntimeError::Eval("read_file expects a path".to_string()));
+                };
+                self.require_scoped(CapabilityKind::Fs, &format!("path {path}"), |c| {
+                    c.can_read_path(&path)
+                })?;
+                self.call_io_builtin("read_file", "path", path)
+            }
+            ("http_get", [url]) => {
+                let Value::String(url) = self.eval_expr(url)? else {
+                    return Err(RuntimeError::Eval("http_get expects a URL".to_string()));
+                };
+                let host = url_host(&url).to_string();
+                self.require_scoped(CapabilityKind::Net, &format!("host {host}"), |c| {
+                    c.can_reach_host(&host)
+                })?;
+                self.call_io_builtin("http_get", "url", url)
+            }
+            _ => Err(RuntimeError::Eval(format!("unknown function: {callee}"))),
+        }
+    }
+
+    /// Runs an I/O built-in through the tool registry, which supplies its
+    /// implementation, and logs the result like a tool call so that replays
+    /// never touch the outside world.
+    fn call_io_builtin(
+        &mut self,
+        name: &str,
+        field: &str,
+        arg: String,
+    ) -> Result<Value, RuntimeError> {
+        let input = Json::Object(vec![(field.to_string(), Json::String(arg))]).canonical();
+        match self.tools.call(name, input.clone()) {
+            Ok(text) => {
+                let output = Json::String(text.clone()).canonical();
+                self.log.push(Event::ToolCall {
+                    tool: name.to_string(),
+                    input,
+                    output_hash: stable_hash_hex(&output),
+                    output,
+                    source: "builtin".to_string(),
+                    timestamp_ms: self.clock_ms,
                     policy_tags: vec!["default".to_string()],
                 });
-                log.push(Event::Time { millis: timeout_ms });
+                Ok(Value::String(text))
+            }
+            Err(e) => {
+                let error = RuntimeError::ToolExecution(e);
+                self.log.push(Event::ToolError {
+                    tool: name.to_string(),
+                    input,
+                    error: error.to_string(),
+                });
+                Err(error)
             }
         }
-        log.push(Event::Random { value: rng.next() });
     }
-    Ok(log)
-}
 
-fn validate_tool_output(raw: &str, schema: &HashMap<String, Type>) -> Result<(), RuntimeError> {
-    for k in schema.keys() {
-        let needle = format!("\"{k}\":");
-        if !raw.contains(&needle) {
-            return Err(RuntimeError::ToolValidation(format!(
-                "tool output missing required field: {k}"
+    /// Fails unless a valid capability of `kind` was granted.
+    fn require(&mut self, kind: CapabilityKind) -> Result<(), RuntimeError> {
+        self.require_scoped(kind, "", |_| true)
+    }
+
+    /// Fails unless some valid granted capability of `kind` reaches
+    /// `resource`.
+    fn require_scoped(
+        &mut self,
+        kind: CapabilityKind,
+        resource: &str,
+        allows: impl Fn(&Capability) -> bool,
+    ) -> Result<(), RuntimeError> {
+        let mut granted = self
+            .capabilities
+            .iter()
+            .filter(|(_, c)| c.kind() == kind)
+            .collect::<Vec<_>>();
+        if granted.is_empty() {
+            return Err(RuntimeError::MissingCapability(format!(
+                "{} capability required",
+                kind.name()
             )));
         }
+        granted.retain(|(_, c)| allows(c));
+        // Sorted so that the capability reported as revoked is deterministic.
+        granted.sort_by_key(|(name, _)| name.as_str());
+        let Some((first, _)) = granted.first() else {
+            return Err(RuntimeError::InvalidCapability(format!(
+                "no {} capability grants access to {resource}",
+                kind.name()
+            )));
+        };
+        if granted
+            .iter()
+            .any(|(_, c)| c.invalidity(self.clock_ms).is_none())
+        {
+            return Ok(());
+        }
+        let first = first.to_string();
+        self.check_valid(&first)
+    }
+
+    /// Fails, logging why, if capability `name` was revoked or has expired.
+    fn check_valid(&mut self, name: &str) -> Result<(), RuntimeError> {
+        match self.capability(name)?.invalidity(self.clock_ms) {
+            Some(reason) => Err(self.no_longer_valid(name, reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if `name` expires before `deadline_ms`, when a call using it
+    /// could still be running.
+    fn check_valid_until(&mut self, name: &str, deadline_ms: u64) -> Result<(), RuntimeError> {
+        match self.capability(name)?.expires_at_ms() {
+            Some(at) if deadline_ms > at => {
+                self.log.push(Event::TimeoutExceedsExpiry {
+                    cap: name.to_string(),
+                    deadline_ms,
+                    expires_at_ms: at,
+                });
+                Err(RuntimeError::TimeoutExceedsExpiry(format!(
+                    "{name} expires at {at} ms, before the call's timeout at {deadline_ms} ms"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn no_longer_valid(&mut self, name: &str, reason: String) -> RuntimeError {
+        self.log.push(Event::Revoked {
+            cap: name.to_string(),
+            reason: reason.clone(),
+        });
+        RuntimeError::Revoked(format!("{name} {reason}"))
+    }
+
+    fn eval_len(&mut self, arg: &Expr) -> Result<Value, RuntimeError> {
+        let len = match self.eval_expr(arg)? {
+            Value::Array(items) | Value::Set(items) => items.len(),
+            Value::Map(entries) => entries.len(),
+            Value::String(s) => s.chars().count(),
+            Value::Bytes(b) => b.len(),
+            _ => return Err(RuntimeError::Eval("len of invalid value".to_string()).at(arg.span)),
+        };
+        Ok(Value::Int(len as i64))
+    }
+
+    fn eval_index(&mut self, target: &Expr, index: &Expr) -> Result<Value, RuntimeError> {
+        let target_value = self.eval_expr(target)?;
+        let key = self.eval_expr(index)?;
+        match (target_value, key) {
+            (Value::Array(items), Value::Int(i)) => {
+                let len = items.len();
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| items.into_iter().nth(i))
+                    .ok_or_else(|| {
+                        RuntimeError::Eval(format!(
+                            "index {i} out of bounds for array of length {len}"
+                        ))
+                        .at(index.span)
+                    })
+            }
+            (Value::Map(entries), key) => {
+                match entries.binary_search_by(|(k, _)| key_cmp(k, &key)) {
+                    Ok(idx) => Ok(entries[idx].1.clone()),
+                    Err(_) => Err(RuntimeError::Eval(format!(
+                        "key {} not found in map",
+                        format_nested(&key)
+                    ))
+                    .at(index.span)),
+                }
+            }
+            _ => Err(RuntimeError::Eval("invalid index operation".to_string()).at(index.span)),
+        }
+    }
+
+    /// Picks the first arm whose pattern matches, with the values it binds.
+    fn select_arm<'b, B>(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &'b [MatchArm<B>],
+    ) -> Result<(&'b MatchArm<B>, HashMap<String, Value>), RuntimeError> {
+        let value = self.eval_expr(scrutinee)?;
+        for arm in arms {
+            let mut bindings = HashMap::new();
+            if match_pattern(&arm.pattern, &value, &mut bindings) {
+                return Ok((arm, bindings));
+            }
+        }
+        Err(
+            RuntimeError::Eval(format!("no match arm matched {}", format_nested(&value)))
+                .at(scrutinee.span),
+        )
+    }
+
+    fn eval_struct(
+        &mut self,
+        name: &str,
+        fields: &[(String, Expr)],
+    ) -> Result<Value, RuntimeError> {
+        let mut values = HashMap::with_capacity(fields.len());
+        for (field, expr) in fields {
+            values.insert(field.as_str(), self.eval_expr(expr)?);
+        }
+        let decl = self.structs.get(name).copied().unwrap_or_default();
+        let fields = decl
+            .iter()
+            .map(|(field, _)| {
+                let value = values.remove(field.as_str()).ok_or_else(|| {
+                    RuntimeError::Eval(format!("missing field {field} in struct {name}"))
+                })?;
+                Ok((field.clone(), value))
+            })
+            .collect::<Result<_, RuntimeError>>()?;
+        Ok(Value::Struct {
+            name: name.to_string(),
+            fields,
+        })
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Int(v) => Ok(Value::Int(*v)),
+            ExprKind::Float(v) => Ok(Value::Float(*v)),
+            ExprKind::Bytes(v) => Ok(Value::Bytes(v.clone())),
+            ExprKind::Unit => Ok(Value::Unit),
+            ExprKind::Bool(v) => Ok(Value::Bool(*v)),
+            ExprKind::String(v) => Ok(Value::String(v.clone())),
+            ExprKind::Template(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    match part {
+                        TemplatePart::Lit(s) => out.push_str(s),
+                        TemplatePart::Expr(e) => out.push_str(&format_value(&self.eval_expr(e)?)),
+                    }
+                }
+                Ok(Value::String(out))
+            }
+            ExprKind::Var(v) => {
+                self.env.get(v).cloned().ok_or_else(|| {
+                    RuntimeError::Eval(format!("unknown variable: {v}")).at(expr.span)
+                })
+            }
+            ExprKind::Array(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.eval_expr(item))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ExprKind::Set(items) => Ok(Value::set_from(
+                items
+                    .iter()
+                    .map(|item| self.eval_expr(item))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ExprKind::Map(entries) => {
+                let mut values = Vec::with_capacity(entries.len());
+                for (k, v) in entries {
+                    values.push((self.eval_expr(k)?, self.eval_expr(v)?));
+                }
+                Ok(Value::map_from(values))
+            }
+            ExprKind::Index {
+                expr: target,
+                index,
+            } => self.eval_index(target, index),
+            ExprKind::Struct { name, fields } => {
+                self.eval_struct(name, fields).map_err(|e| e.at(expr.span))
+            }
+            ExprKind::Variant {
+                enum_name,
+                variant,
+                args,
+            } => Ok(Value::Enum {
+                name: enum_name.clone(),
+                variant: variant.clone(),
+                payload: args
+                    .iter()
+                    .map(|arg| self.eval_expr(arg))
+                    .collect::<Result<_, _>>()?,
+            }),
+            ExprKind::Field {
+                expr: target,
+                field,
+            } => match self.eval_expr(target)? {
+                Value::Struct { fields, .. } | Value::Record(fields) => fields
+                    .into_iter()
+                    .find(|(f, _)| f == field)
+                    .map(|(_, v)| v)
+                    .ok_or_else(|| RuntimeError::Eval(format!("no field {field}")).at(expr.span)),
+                _ => Err(RuntimeError::Eval(format!("no field {field}")).at(expr.span)),
+            },
+            ExprKind::Match { scrutinee, arms } => {
+                let (arm, bindings) = self.select_arm(scrutinee, arms)?;
+                self.env.scopes.push(bindings);
+                let value = self.eval_expr(&arm.body);
+                self.env.scopes.pop();
+                value
+            }
+            ExprKind::ToolCall(call) => self.call_tool(call)?,
+            ExprKind::TryCall(call) => Ok(match self.call_tool(call)? {
+                Ok(record) => Value::variant("Result", "Ok", vec![record]),
+                Err(error) => {
+                    let error = Value::Struct {
+                        name: TOOL_ERROR.to_string(),
+                        fields: vec![
+                            ("tool".to_string(), Value::String(call.tool.clone())),
+                            ("message".to_string(), Value::String(error.to_string())),
+                        ],
+                    };
+                    Value::variant("Result", "Err", vec![error])
+                }
+            }),
+            ExprKind::Propagate(inner) => match self.eval_expr(inner)? {
+                Value::Enum {
+                    variant, payload, ..
+                } if variant == "Some" || variant == "Ok" => {
+                    Ok(payload.into_iter().next().unwrap_or(Value::Unit))
+                }
+                value => {
+                    self.unwinding = Some(value);
+                    Err(RuntimeError::Eval("`?` outside of a function".to_string()).at(expr.span))
+                }
+            },
+            ExprKind::Call { callee, args } => self.call_fn(callee, args, expr),
+            ExprKind::Unary { op, expr: inner } => match (op, self.eval_expr(inner)?) {
+                (UnaryOp::Neg, Value::Int(v)) => v
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| RuntimeError::Overflow(format!("-({v})")).at(expr.span)),
+                (UnaryOp::Neg, Value::Float(v)) => Ok(Value::Float(-v)),
+                (UnaryOp::Not, Value::Bool(v)) => Ok(Value::Bool(!v)),
+                _ => Err(operand_error(op.symbol(), expr)),
+            },
+            ExprKind::Binary {
+                lhs,
+                op: op @ (BinOp::And | BinOp::Or),
+                rhs,
+            } => {
+                let Value::Bool(l) = self.eval_expr(lhs)? else {
+                    return Err(operand_error(op.symbol(), expr));
+                };
+                // Short-circuit: the right operand is only evaluated when it decides the result.
+                if l == (*op == BinOp::Or) {
+                    return Ok(Value::Bool(l));
+                }
+                match self.eval_expr(rhs)? {
+                    Value::Bool(r) => Ok(Value::Bool(r)),
+                    _ => Err(operand_error(op.symbol(), expr)),
+                }
+            }
+            ExprKind::Binary { lhs, op, rhs } => {
+                let l = self.eval_expr(lhs)?;
+                let r = self.eval_expr(rhs)?;
+                match (op, l, r) {
+                    (BinOp::Eq, l, r) => Ok(Value::Bool(l == r)),
+                    (BinOp::Ne, l, r) => Ok(Value::Bool(l != r)),
+                    (BinOp::Add, Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
+                    (BinOp::Add, Value::Bytes(mut l), Value::Bytes(r)) => {
+                        l.extend(r);
+                        Ok(Value::Bytes(l))
+                    }
+                    (op, Value::Float(l), Value::Float(r)) => Ok(match op {
+                        BinOp::Add => Value::Float(l + r),
+                        BinOp::Sub => Value::Float(l - r),
+                        BinOp::Mul => Value::Float(l * r),
+                        BinOp::Div => Value::Float(l / r),
+                        BinOp::Lt => Value::Bool(l < r),
+                        BinOp::Le => Value::Bool(l <= r),
+                        BinOp::Gt => Value::Bool(l > r),
+                        BinOp::Ge => Value::Bool(l >= r),
+                        _ => return Err(operand_error(op.symbol(), expr)),
+                    }),
+                    (BinOp::Div, Value::Int(_), Value::Int(0)) => {
+                        Err(RuntimeError::DivisionByZero.at(expr.span))
+                    }
+                    (op, Value::Int(l), Value::Int(r)) => Ok(match op {
+                        op if op.is_arithmetic() => {
+                            Value::Int(op.checked_int(l, r).ok_or_else(|| {
+                                RuntimeError::Overflow(format!("{l} {} {r}", op.symbol()))
+                                    .at(expr.span)
+                            })?)
+                        }
+                        BinOp::Lt => Value::Bool(l < r),
+                        BinOp::Le => Value::Bool(l <= r),
+                        BinOp::Gt => Value::Bool(l > r),
+                        BinOp::Ge => Value::Bool(l >= r),
+                        _ => return Err(operand_error(op.symbol(), expr)),
+                    }),
+                    _ => Err(operand_error(op.symbol(), expr)),
+                }
+            }
+        }
+    }
+}
+
+/// The host of `url`, which network scopes are checked against: the
+/// authority without a scheme, user info or port.
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+/// Map keys arrive as JSON object keys, so non-string keys are parsed back.
+fn decode_key(key: &str, ty: &Type) -> Result<Value, String> {
+    let parsed = match ty {
+        Type::String => Some(Value::String(key.to_string())),
+        Type::Int => key.parse().ok().map(Value::Int),
+        Type::Bool => key.parse().ok().map(Value::Bool),
+        Type::Bytes => decode_base64(key).ok().map(Value::Bytes),
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("invalid {ty} map key {}", quote(key)))
+}
+
+/// Duplicate keys are rejected rather than resolved, since JSON readers
+/// disagree on which one wins.
+fn check_unique_keys(members: &[(String, Json)], path: &str) -> Result<(), String> {
+    for (i, (key, _)) in members.iter().enumerate() {
+        if members[..i].iter().any(|(k, _)| k == key) {
+            return Err(format!("{path}: duplicate key {}", quote(key)));
+        }
     }
     Ok(())
 }
 
-fn eval_expr(expr: &Expr, env: &HashMap<String, Value>) -> Result<Value, RuntimeError> {
-    match expr {
-        Expr::Int(v) => Ok(Value::Int(*v)),
-        Expr::Bool(v) => Ok(Value::Bool(*v)),
-        Expr::String(v) => Ok(Value::String(v.clone())),
-        Expr::Var(v) => env
-            .get(v)
-            .cloned()
-            .ok_or_else(|| RuntimeError::Eval(format!("unknown variable: {v}"))),
-        Expr::Binary { lhs, op, rhs } => {
-            let l = eval_expr(lhs, env)?;
-            let r = eval_expr(rhs, env)?;
-            let (l, r) = match (l, r) {
-                (Value::Int(l), Value::Int(r)) => (l, r),
-                _ => {
-                    return Err(RuntimeError::Eval(
-                        "binary ops require Int values".to_string(),
-                    ));
-                }
-            };
-            Ok(Value::Int(match op {
-                BinOp::Add => l + r,
-                BinOp::Sub => l - r,
-                BinOp::Mul => l * r,
-                BinOp::Div => l / r,
-            }))
+fn match_pattern(pattern: &Pattern, value: &Value, bindings: &mut HashMap<String, Value>) -> bool {
+    match (&pattern.kind, value) {
+        (PatternKind::Wildcard, _) => true,
+        (PatternKind::Binding(name), v) => {
+            bindings.insert(name.clone(), v.clone());
+            true
+        }
+        (PatternKind::Int(p), Value::Int(v)) => p == v,
+        (PatternKind::Bool(p), Value::Bool(v)) => p == v,
+        (PatternKind::String(p), Value::String(v)) => p == v,
+        (
+            PatternKind::Variant {
+                enum_name,
+                variant,
+                args,
+            },
+            Value::Enum {
+                name,
+                variant: actual,
+                payload,
+            },
+        ) => {
+            enum_name == name
+                && variant == actual
+                && args.len() == payload.len()
+                && args
+                    .iter()
+                    .zip(payload)
+                    .all(|(p, v)| match_pattern(p, v, bindings))
         }
+        _ => false,
     }
 }
 
-fn to_json(v: Value) -> String {
+fn operand_error(symbol: &str, expr: &Expr) -> RuntimeError {
+    RuntimeError::Eval(format!("invalid operands for `{symbol}`")).at(expr.span)
+}
+
+/// Bytes travel as base64 strings, map keys included; Unit and non-finite floats become `null`.
+/// Maps become objects with stringified keys, in key order. Structs become
+/// objects in field order; enum variants become `"Variant"` or, with a
+/// payload, `{"Variant":[...]}`. `Some(x)` is `x` and `None` is `null`;
+/// results are `{"Ok":x}` or `{"Err":e}`.
+fn to_json(v: &Value) -> Json {
     match v {
-        Value::Int(v) => v.to_string(),
-        Value::Bool(v) => v.to_string(),
-        Value::String(v) => format!("\"{v}\""),
+        Value::Int(v) => Json::Int(*v),
+        Value::Float(v) => Json::Float(*v),
+        Value::Unit => Json::Null,
+        Value::Bool(v) => Json::Bool(*v),
+        Value::String(v) => Json::String(v.clone()),
+        Value::Bytes(v) => Json::String(encode_base64(v)),
+        Value::Array(items) | Value::Set(items) => Json::Array(items.iter().map(to_json).collect()),
+        Value::Map(entries) => Json::Object(
+            entries
+                .iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Value::Bytes(b) => encode_base64(b),
+                        k => format_value(k),
+                    };
+                    (key, to_json(v))
+                })
+                .collect(),
+        ),
+        Value::Struct { fields, .. } | Value::Record(fields) => Json::Object(
+            fields
+                .iter()
+                .map(|(f, v)| (f.clone(), to_json(v)))
+                .collect(),
+        ),
+        Value::Enum { name, payload, .. } if name == "Option" => {
+            payload.first().map_or(Json::Null, to_json)
+        }
+        Value::Enum {
+            name,
+            variant,
+            payload,
+        } if name == "Result" => Json::Object(vec![(
+            variant.clone(),
+            payload.first().map_or(Json::Null, to_json),
+        )]),
+        Value::Enum {
+            variant, payload, ..
+        } if payload.is_empty() => Json::String(variant.clone()),
+        Value::Enum {
+            variant, payload, ..
+        } => Json::Object(vec![(
+            variant.clone(),
+            Json::Array(payload.iter().map(to_json).collect()),
+        )]),
     }
 }
+
 fn format_value(v: &Value) -> String {
     match v {
         Value::Int(v) => v.to_string(),
+        Value::Float(v) => format_float(*v),
         Value::Bool(v) => v.to_string(),
         Value::String(v) => v.clone(),
+        Value::Bytes(v) => format!("hex\"{}\"", encode_hex(v)),
+        Value::Unit => "()".to_string(),
+        Value::Array(items) => {
+            let items = items.iter().map(format_nested).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Set(items) => {
+            let items = items.iter().map(format_nested).collect::<Vec<_>>();
+            format!("{{{}}}", items.join(", "))
+        }
+        Value::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(k, v)| format!("{}: {}", format_nested(k), format_nested(v)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Struct { name, fields } => {
+            let fields = fields
+                .iter()
+                .map(|(f, v)| format!("{f}: {}", format_nested(v)))
+                .collect::<Vec<_>>();
+            format!("{name} {{ {} }}", fields.join(", "))
+        }
+        Value::Record(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(f, v)| format!("{f}: {}", format_nested(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        Value::Enum {
+            name,
+            variant,
+            payload,
+        } => {
+            let mut out = if BUILTIN_ENUMS.contains(&name.as_str()) {
+                variant.clone()
+            } else {
+                format!("{name}::{variant}")
+            };
+            if !payload.is_empty() {
+                let payload = payload.iter().map(format_nested).collect::<Vec<_>>();
+                out.push_str(&format!("({})", payload.join(", ")));
+            }
+            out
+        }
     }
 }
 
+/// Like `format_value`, but quotes strings so collection contents stay unambiguous.
+fn format_nested(v: &Value) -> String {
+    match v {
+        Value::String(s) => quote(s),
+        v => format_value(v),
+    }
+}
+
+/// Shortest representation that round-trips, always with a fractional part
+/// or exponent so floats never print like integers.
+fn format_float(v: f64) -> String {
+    format!("{v:?}")
+}
+
 fn stable_hash_hex(s: &str) -> String {
     let mut hash: u64 = 0xcbf29ce484222325;
     for b in s.bytes() {
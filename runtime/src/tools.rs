@@ -1,3 +1,4 @@
+use crate::json::quote;
 use std::collections::HashMap;
 
 pub type ToolFn = Box<dyn Fn(String) -> Result<String, String> + Send + Sync>;
@@ -5,20 +6,30 @@ pub type ToolFn = Box<dyn Fn(String) -> Result<String, String> + Send + Sync>;
 #[derive(Default)]
 pub struct ToolRegistry {
     tools: HashMap<String, ToolFn>,
+    /// Cost units charged per call against capability budgets; tools without
+    /// a declared cost cost one unit.
+    costs: HashMap<String, u64>,
 }
 
 impl ToolRegistry {
+    /// `MockEcho` returns its JSON input as the string field `echo`.
     pub fn with_mock_tools() -> Self {
         let mut reg = Self::default();
         reg.register(
             "MockEcho",
-            Box::new(|input| Ok(format!("{{\"echo\":{input}}}"))),
+            Box::new(|input| Ok(format!("{{\"echo\":{}}}", quote(&input)))),
         );
         reg
     }
     pub fn register(&mut self, name: &str, f: ToolFn) {
         self.tools.insert(name.to_string(), f);
     }
+    pub fn set_cost(&mut self, name: &str, cost: u64) {
+        self.costs.insert(name.to_string(), cost);
+    }
+    pub fn cost(&self, name: &str) -> u64 {
+        self.costs.get(name).copied().unwrap_or(1)
+    }
     pub fn call(&self, name: &str, input: String) -> Result<String, String> {
         self.tools
             .get(name)
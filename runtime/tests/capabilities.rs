@@ -0,0 +1,482 @@
+use axiom_compiler::{parser::parse_program, typecheck::typecheck};
+use axiom_runtime::{
+    capability::{
+        default_capabilities, Budget, Capability, CapabilityKind, RevocationTable, Scope, Spend,
+    },
+    error::RuntimeError,
+    replay::{Event, ReplayLog},
+    runtime::{execute, execute_logged, ExecOptions},
+    tools::ToolRegistry,
+};
+use std::collections::{BTreeSet, HashMap};
+
+fn set(items: &[&str]) -> Option<BTreeSet<String>> {
+    Some(items.iter().map(|s| s.to_string()).collect())
+}
+
+fn run(src: &str, caps: &HashMap<String, Capability>) -> Result<Vec<Event>, RuntimeError> {
+    run_with(src, caps, &ToolRegistry::with_mock_tools())
+}
+
+fn run_with(
+    src: &str,
+    caps: &HashMap<String, Capability>,
+    tools: &ToolRegistry,
+) -> Result<Vec<Event>, RuntimeError> {
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    execute(typed, caps, tools, ExecOptions::default()).map(|log| log.events)
+}
+
+fn budgeted(budget: Budget) -> HashMap<String, Capability> {
+    let mut caps = default_capabilities();
+    caps.insert(
+        "toolCap".to_string(),
+        Capability::new(CapabilityKind::Tool, Scope::unrestricted()).with_budget(budget),
+    );
+    caps
+}
+
+const ECHO_LOOP: &str = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet mut i = 0;\nwhile i < 10 {\n  call MockEcho { message: \"hi\" } using toolCap timeout 100;\n  i = i + 1;\n}\n";
+
+#[test]
+fn scopes_intersect_and_never_widen() {
+    let outer = Scope::unrestricted()
+        .with_tools(["Search", "Fetch"])
+        .with_hosts(["*.example.com"])
+        .with_paths(["/data"]);
+    let inner = Scope::unrestricted()
+        .with_tools(["Fetch", "Delete"])
+        .with_hosts(["api.example.com", "evil.test", "*.cdn.example.com"])
+        .with_paths(["/data/reports", "/etc"])
+        .with_namespaces(["notes"]);
+    let both = outer.intersect(&inner);
+    assert_eq!(both.tools, set(&["Fetch"]));
+    assert_eq!(both.hosts, set(&["*.cdn.example.com", "api.example.com"]));
+    assert_eq!(both.paths, set(&["/data/reports"]));
+    assert_eq!(both.namespaces, set(&["notes"]));
+    assert_eq!(inner.intersect(&outer), both);
+
+    // Narrowing to something wider keeps the narrower scope.
+    let wide = Scope::unrestricted().with_hosts(["*.com"]);
+    assert_eq!(
+        Scope::unrestricted()
+            .with_hosts(["api.example.com"])
+            .intersect(&wide)
+            .hosts,
+        set(&["api.example.com"])
+    );
+}
+
+#[test]
+fn scopes_match_hosts_and_paths() {
+    let scope = Scope::unrestricted()
+        .with_hosts(["*.example.com", "docs.test"])
+        .with_paths(["/data"]);
+    assert!(scope.allows_host("API.example.com"));
+    assert!(scope.allows_host("docs.test"));
+    assert!(!scope.allows_host("example.com"));
+    assert!(!scope.allows_host("example.com.evil.test"));
+    assert!(scope.allows_path("/data/report.txt"));
+    assert!(!scope.allows_path("/database"));
+    assert!(!scope.allows_path("/data/../etc/passwd"));
+    assert!(scope.allows_tool("Anything"));
+    assert!(!Scope::unrestricted()
+        .with_namespaces(Vec::<String>::new())
+        .allows_namespace("notes"));
+}
+
+#[test]
+fn narrowed_capabilities_keep_their_kind() {
+    let tool = Capability::new(
+        CapabilityKind::Tool,
+        Scope::unrestricted().with_tools(["MockEcho", "Search"]),
+    );
+    let echo = tool.narrow_to_tool("MockEcho").expect("in scope");
+    assert!(echo.can_use_tool("MockEcho"));
+    assert!(!echo.can_use_tool("Search"));
+    assert_ne!(echo.id(), tool.id());
+    assert!(tool.narrow_to_tool("Delete").is_none());
+    assert!(echo.narrow_to_tool("Search").is_none());
+
+    let fs = Capability::new(CapabilityKind::Fs, Scope::unrestricted());
+    assert!(!fs.can_use_tool("MockEcho"));
+    assert!(fs.can_read_path("/anywhere"));
+}
+
+#[test]
+fn tool_calls_are_checked_against_the_tool_allowlist() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\ncall MockEcho { message: \"hi\" } using toolCap timeout 10;\n";
+    let mut caps = default_capabilities();
+    caps.insert(
+        "toolCap".to_string(),
+        Capability::new(
+            CapabilityKind::Tool,
+            Scope::unrestricted().with_tools(["MockEcho"]),
+        ),
+    );
+    let events = run(src, &caps).expect("allowed tool");
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::ToolCall { tool, .. } if tool == "MockEcho")));
+
+    caps.insert(
+        "toolCap".to_string(),
+        Capability::new(
+            CapabilityKind::Tool,
+            Scope::unrestricted().with_tools(["Search"]),
+        ),
+    );
+    let err = run(src, &caps).expect_err("tool outside scope");
+    assert_eq!(
+        err.to_string(),
+        "invalid capability: toolCap does not grant access to tool MockEcho"
+    );
+}
+
+#[test]
+fn file_reads_are_checked_against_path_prefixes() {
+    let src = "print read_file(\"/srv/data/notes.txt\");\n";
+    let mut tools = ToolRegistry::default();
+    tools.register("read_file", Box::new(|_| Ok("scoped".to_string())));
+
+    let mut caps = default_capabilities();
+    caps.insert(
+        "fsCap".to_string(),
+        Capability::new(
+            CapabilityKind::Fs,
+            Scope::unrestricted().with_paths(["/srv/data"]),
+        ),
+    );
+    let events = run_with(src, &caps, &tools).expect("path in scope");
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::Print { value } if value == "scoped")));
+
+    caps.insert(
+        "fsCap".to_string(),
+        Capability::new(
+            CapabilityKind::Fs,
+            Scope::unrestricted().with_paths(["/srv/data/other"]),
+        ),
+    );
+    let err = run_with(src, &caps, &tools).expect_err("path outside scope");
+    assert_eq!(
+        err.to_string(),
+        "invalid capability: no fs capability grants access to path /srv/data/notes.txt"
+    );
+}
+
+#[test]
+fn http_requests_are_checked_against_host_patterns() {
+    let mut caps = default_capabilities();
+    caps.insert(
+        "netCap".to_string(),
+        Capability::new(
+            CapabilityKind::Net,
+            Scope::unrestricted().with_hosts(["*.example.com"]),
+        ),
+    );
+    let mut tools = ToolRegistry::default();
+    tools.register("http_get", Box::new(|_| Ok("page".to_string())));
+    run_with(
+        "print http_get(\"http://user@api.example.com:8080/x?q=1\");\n",
+        &caps,
+        &tools,
+    )
+    .expect("host in scope");
+    // Refused before the registry is consulted.
+    let err = run("print http_get(\"http://evil.test/x\");\n", &caps).expect_err("host");
+    assert_eq!(
+        err.to_string(),
+        "invalid capability: no net capability grants access to host evil.test"
+    );
+}
+
+#[test]
+fn narrow_derives_capabilities_and_logs_their_lineage() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\ntool Search input { q: String } output { hits: Int } cap toolCap;\nlet echoOnly = narrow toolCap to [MockEcho, Search];\nlet strict = narrow echoOnly to [MockEcho];\ncall MockEcho { message: \"hi\" } using strict timeout 10;\n";
+    let mut caps = default_capabilities();
+    caps.insert(
+        "toolCap".to_string(),
+        Capability::new(
+            CapabilityKind::Tool,
+            Scope::unrestricted().with_tools(["MockEcho"]),
+        ),
+    );
+    let events = run(src, &caps).expect("execute");
+    let derived = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::CapDerived {
+                name,
+                parent,
+                tools,
+            } => Some((name.as_str(), parent.as_str(), tools.join(","))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // The runtime scope of toolCap already excludes Search.
+    assert_eq!(
+        derived,
+        vec![
+            ("echoOnly", "toolCap", "MockEcho".to_string()),
+            ("strict", "echoOnly", "MockEcho".to_string()),
+        ]
+    );
+    let log = ReplayLog { events };
+    let text = log.to_text();
+    assert!(text.contains("CAPDERIVE|strict|echoOnly|MockEcho\n"));
+    let parsed = ReplayLog::from_text(&text).expect("parse replay");
+    assert_eq!(parsed.digest_hex(), log.digest_hex());
+}
+
+#[test]
+fn derived_capabilities_are_enforced_at_runtime() {
+    // Narrowed to a tool the runtime grant does not include.
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet echo = narrow toolCap to [MockEcho];\ncall MockEcho { message: \"hi\" } using echo timeout 10;\n";
+    let mut caps = default_capabilities();
+    caps.insert(
+        "toolCap".to_string(),
+        Capability::new(
+            CapabilityKind::Tool,
+            Scope::unrestricted().with_tools(["Search"]),
+        ),
+    );
+    let err = run(src, &caps).expect_err("narrowing cannot widen");
+    assert_eq!(
+        err.to_string(),
+        "invalid capability: echo does not grant access to tool MockEcho"
+    );
+}
+
+#[test]
+fn budgets_bound_tool_calls_timeouts_and_cost() {
+    let err = run(ECHO_LOOP, &budgeted(Budget::unlimited().with_calls(3))).expect_err("calls");
+    assert_eq!(
+        err.to_string(),
+        "budget exceeded: toolCap: tool call limit of 3 reached (0 left, 1 needed)"
+    );
+    assert!(err.span().is_some(), "located at the call");
+
+    let err = run(
+        ECHO_LOOP,
+        &budgeted(Budget::unlimited().with_timeout_ms(250)),
+    )
+    .expect_err("timeout");
+    assert_eq!(
+        err.to_string(),
+        "budget exceeded: toolCap: timeout ms limit of 250 reached (50 left, 100 needed)"
+    );
+
+    let mut tools = ToolRegistry::with_mock_tools();
+    tools.set_cost("MockEcho", 5);
+    let err = run_with(
+        ECHO_LOOP,
+        &budgeted(Budget::unlimited().with_cost(12)),
+        &tools,
+    )
+    .expect_err("cost");
+    assert_eq!(
+        err.to_string(),
+        "budget exceeded: toolCap: cost limit of 12 reached (2 left, 5 needed)"
+    );
+}
+
+#[test]
+fn remaining_budget_is_logged_after_each_call() {
+    let caps = budgeted(Budget::unlimited().with_calls(20).with_cost(15));
+    let events = run(ECHO_LOOP, &caps).expect("within budget");
+    let remaining = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Budget {
+                cap,
+                calls,
+                timeout_ms,
+                cost,
+            } => Some((cap.as_str(), *calls, *timeout_ms, *cost)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(remaining.len(), 10);
+    assert_eq!(remaining[0], ("toolCap", Some(19), None, Some(14)));
+    assert_eq!(remaining[9], ("toolCap", Some(10), None, Some(5)));
+
+    let log = ReplayLog { events };
+    let text = log.to_text();
+    assert!(text.contains("BUDGET|toolCap|10|-|5\n"));
+    let parsed = ReplayLog::from_text(&text).expect("parse replay");
+    assert_eq!(parsed.digest_hex(), log.digest_hex());
+
+    // Without budgets, nothing extra is logged.
+    let events = run(ECHO_LOOP, &default_capabilities()).expect("unbudgeted");
+    assert!(!events.iter().any(|e| matches!(e, Event::Budget { .. })));
+}
+
+#[test]
+fn calls_that_fail_before_reaching_the_tool_spend_no_budget() {
+    let caps = budgeted(Budget::unlimited().with_calls(1));
+    let src = "tool MockEcho input { value: Int } output { echo: String } cap toolCap;\nlet zero = 0;\ncall MockEcho { value: 1 / zero } using toolCap timeout 10;\n";
+    let err = run(src, &caps).expect_err("input fails to evaluate");
+    assert_eq!(err.to_string(), "division by zero");
+    assert_eq!(
+        caps["toolCap"].remaining(),
+        Budget::unlimited().with_calls(1)
+    );
+}
+
+#[test]
+fn derived_capabilities_spend_from_their_parents_budget() {
+    let parent = Capability::new(CapabilityKind::Tool, Scope::unrestricted())
+        .with_budget(Budget::unlimited().with_calls(2));
+    let child = parent
+        .narrow_to_tool("MockEcho")
+        .expect("in scope")
+        .with_budget(Budget::unlimited().with_calls(5).with_cost(3));
+    let call = Spend {
+        calls: 1,
+        timeout_ms: 10,
+        cost: 1,
+    };
+    assert_eq!(
+        child.remaining(),
+        Budget::unlimited().with_calls(2).with_cost(3)
+    );
+    child.charge(call).expect("first");
+    parent.charge(call).expect("second");
+    assert_eq!(parent.remaining(), Budget::unlimited().with_calls(0));
+    // A failed charge spends nothing from any budget.
+    assert!(child.charge(call).is_err());
+    assert_eq!(
+        child.remaining(),
+        Budget::unlimited().with_calls(0).with_cost(2)
+    );
+
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet echo = narrow toolCap to [MockEcho];\ncall MockEcho { message: \"a\" } using echo timeout 10;\ncall MockEcho { message: \"b\" } using toolCap timeout 10;\ncall MockEcho { message: \"c\" } using echo timeout 10;\n";
+    let err = run(src, &budgeted(Budget::unlimited().with_calls(2))).expect_err("shared budget");
+    assert_eq!(
+        err.to_string(),
+        "budget exceeded: echo: tool call limit of 2 reached (0 left, 1 needed)"
+    );
+}
+
+#[test]
+fn revoked_capabilities_stop_working_mid_run() {
+    let table = RevocationTable::new();
+    let tool_cap = Capability::new(CapabilityKind::Tool, Scope::unrestricted()).revocable(&table);
+    let mut tools = ToolRegistry::with_mock_tools();
+    let (operator, id) = (table.clone(), tool_cap.id());
+    let calls = std::sync::atomic::AtomicU64::new(0);
+    // Stands in for an operator cutting the agent off after its second call.
+    tools.register(
+        "Watchdog",
+        Box::new(move |_| {
+            if calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 1 {
+                operator.revoke_id(id, "operator cut off");
+            }
+            Ok("{}".to_string())
+        }),
+    );
+    let mut caps = default_capabilities();
+    caps.insert("toolCap".to_string(), tool_cap);
+    let src = "tool Watchdog input { n: Int } output { } cap toolCap;\nlet sub = narrow toolCap to [Watchdog];\nlet mut i = 0;\nwhile i < 5 {\n  call Watchdog { n: i } using sub timeout 10;\n  i = i + 1;\n}\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let err = execute(typed, &caps, &tools, ExecOptions::default()).expect_err("revoked");
+    assert_eq!(
+        err.to_string(),
+        "capability no longer valid: sub revoked: operator cut off"
+    );
+    assert!(caps["toolCap"].revocation_reason().is_some());
+}
+
+#[test]
+fn revocations_and_expiry_are_logged() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet mut i = 0;\nwhile i < 5 {\n  call MockEcho { message: \"hi\" } using toolCap timeout 100;\n  i = i + 1;\n}\n";
+    let run_until = |expires_at: u64| {
+        let mut caps = default_capabilities();
+        caps.insert(
+            "toolCap".to_string(),
+            Capability::new(CapabilityKind::Tool, Scope::unrestricted()).expires_at(expires_at),
+        );
+        let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+        let (log, result) = execute_logged(
+            typed,
+            &caps,
+            &ToolRegistry::with_mock_tools(),
+            ExecOptions::default(),
+        );
+        let calls = log
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::ToolCall { timestamp_ms, .. } => Some(*timestamp_ms),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let last = log.events.last().cloned().expect("events");
+        (calls, last, result.expect_err("expired").to_string())
+    };
+    // The third call would still be running when the capability expires.
+    let (calls, last, error) = run_until(250);
+    assert_eq!(calls, vec![0, 100]);
+    assert!(matches!(
+        last,
+        Event::TimeoutExceedsExpiry { cap, deadline_ms: 300, expires_at_ms: 250 } if cap == "toolCap"
+    ));
+    assert_eq!(
+        error,
+        "timeout outlives capability: toolCap expires at 250 ms, before the call's timeout at 300 ms"
+    );
+    // Calls may run right up to the expiry; the next one starts too late.
+    let (calls, last, error) = run_until(200);
+    assert_eq!(calls, vec![0, 100]);
+    assert!(matches!(
+        last,
+        Event::Revoked { cap, reason } if cap == "toolCap" && reason == "expired at 200 ms"
+    ));
+    assert_eq!(
+        error,
+        "capability no longer valid: toolCap expired at 200 ms"
+    );
+
+    let line = "REVOKED|toolCap|expired at 250 ms";
+    let parsed = ReplayLog::from_text(line).expect("parse replay");
+    assert!(matches!(
+        &parsed.events[0],
+        Event::Revoked { cap, reason } if cap == "toolCap" && reason == "expired at 250 ms"
+    ));
+    assert_eq!(parsed.to_text(), format!("{line}\n"));
+    let line = "EXPIRYWINDOW|toolCap|300|250";
+    let parsed = ReplayLog::from_text(line).expect("parse replay");
+    assert_eq!(parsed.to_text(), format!("{line}\n"));
+}
+
+#[test]
+fn builtins_skip_revoked_and_expired_capabilities() {
+    let table = RevocationTable::new();
+    let old = Capability::new(CapabilityKind::Time, Scope::unrestricted()).revocable(&table);
+    table.revoke(&old, "rotated");
+    let mut caps = default_capabilities();
+    caps.insert("timeCap".to_string(), old);
+    let err = run("print now();\n", &caps).expect_err("only time cap revoked");
+    assert_eq!(
+        err.to_string(),
+        "capability no longer valid: timeCap revoked: rotated"
+    );
+
+    caps.insert(
+        "freshTime".to_string(),
+        Capability::new(CapabilityKind::Time, Scope::unrestricted()),
+    );
+    run("print now();\n", &caps).expect("another time cap is valid");
+
+    // Derived capabilities never outlive their parent.
+    let parent = Capability::new(CapabilityKind::Tool, Scope::unrestricted()).expires_at(100);
+    let child = parent
+        .narrow_to_tool("MockEcho")
+        .expect("in scope")
+        .expires_at(500);
+    assert_eq!(child.expires_at_ms(), Some(100));
+    assert_eq!(child.invalidity(99), None);
+    assert_eq!(child.invalidity(100), Some("expired at 100 ms".to_string()));
+}
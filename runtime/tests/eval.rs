@@ -0,0 +1,213 @@
+use axiom_compiler::{parser::parse_program, typecheck::typecheck};
+use axiom_runtime::{
+    replay::Event,
+    runtime::{execute_with_defaults, ExecOptions},
+    tools::ToolRegistry,
+};
+
+fn printed(src: &str) -> Vec<String> {
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let log = execute_with_defaults(
+        typed,
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect("execute");
+    log.events
+        .into_iter()
+        .filter_map(|e| match e {
+            Event::Print { value } => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn evaluates_comparison_equality_and_boolean_operators() {
+    let src = "let a = 3;\nprint a < 4;\nprint a >= 4;\nprint a == 3 && a != 4;\nprint \"x\" == \"y\";\nprint !(a <= 2) || false;\n";
+    assert_eq!(printed(src), vec!["true", "false", "true", "false", "true"]);
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    // The right operands would divide by zero if they were evaluated.
+    let src = "let zero = 0;\nprint false && 1 / zero == 1;\nprint true || 1 / zero == 1;\n";
+    assert_eq!(printed(src), vec!["false", "true"]);
+}
+
+#[test]
+fn concatenates_and_interpolates_strings() {
+    let src = "let name = \"ada\";\nlet n = 2 + 1;\nprint \"hello \" + name;\nprint \"{name} has {n} tasks, done={n == 3}\";\nprint \"\\{not {\"interpolated\"}\\}\";\n";
+    assert_eq!(
+        printed(src),
+        vec![
+            "hello ada",
+            "ada has 3 tasks, done=true",
+            "{not interpolated}"
+        ]
+    );
+}
+
+#[test]
+fn executes_selected_branch_in_its_own_scope() {
+    let src = "let n = 7;\nlet label = \"outer\";\nif n > 10 {\n  print \"big\";\n} else if n > 5 {\n  let label = \"inner\";\n  print label;\n} else {\n  print \"small\";\n}\nprint label;\nwhile n < 0 {\n  print \"never\";\n}\n";
+    assert_eq!(printed(src), vec!["inner", "outer"]);
+}
+
+#[test]
+fn calls_user_defined_functions() {
+    let src = "fn fact(n: Int) -> Int !pure {\n  if n <= 1 { return 1; }\n  return n * fact(n - 1);\n}\nfn greet(name: String) {\n  print \"hi {name}\";\n  return;\n  print \"unreachable\";\n}\nlet n = 5;\ngreet(\"ada\");\nprint \"{n}! = {fact(n)}\";\nprint n;\n";
+    assert_eq!(printed(src), vec!["hi ada", "5! = 120", "5"]);
+}
+
+#[test]
+fn unbounded_recursion_is_a_runtime_error() {
+    let src = "fn spin(n: Int) -> Int { return spin(n + 1); }\nprint spin(0);\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let err = execute_with_defaults(
+        typed,
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect_err("recursion limit");
+    assert!(err.to_string().contains("call depth exceeded 64 in spin"));
+}
+
+#[test]
+fn formats_floats_bytes_and_unit_deterministically() {
+    let src = "fn noop() { }\nprint 0.1 + 0.2;\nprint 3.0 * 2.0;\nprint -1.0 / 0.0;\nprint 1.0e21;\nprint 2.5 >= 2.5;\nprint hex\"00ff\" + base64\"AQ==\";\nprint noop();\nprint \"v={1.5}\";\n";
+    assert_eq!(
+        printed(src),
+        vec![
+            "0.30000000000000004",
+            "6.0",
+            "-inf",
+            "1e21",
+            "true",
+            "hex\"00ff01\"",
+            "()",
+            "v=1.5"
+        ]
+    );
+}
+
+#[test]
+fn evaluates_collections_in_deterministic_order() {
+    let src = "let xs = [3, 1, 2];\nlet m = Map { \"b\": 2, \"a\": 1, \"b\": 20 };\nlet s = Set { \"z\", \"x\", \"z\" };\nprint xs;\nprint m;\nprint s;\nprint xs[2] + m[\"b\"];\nprint len(xs) + len(m) + len(s) + len(\"héllo\");\nfor k in m { print k; }\nfor x in xs { print x * 10; }\nprint Set { 2, 1 } == Set { 1, 2, 1 };\n";
+    assert_eq!(
+        printed(src),
+        vec![
+            "[3, 1, 2]",
+            "{\"a\": 1, \"b\": 20}",
+            "{\"x\", \"z\"}",
+            "22",
+            "12",
+            "a",
+            "b",
+            "30",
+            "10",
+            "20",
+            "true"
+        ]
+    );
+}
+
+#[test]
+fn out_of_bounds_indexing_is_a_runtime_error() {
+    for (src, message) in [
+        (
+            "let xs = [1];\nprint xs[1];\n",
+            "index 1 out of bounds for array of length 1",
+        ),
+        (
+            "let m = Map { \"a\": 1 };\nprint m[\"b\"];\n",
+            "key \"b\" not found in map",
+        ),
+    ] {
+        let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+        let err = execute_with_defaults(
+            typed,
+            &ToolRegistry::with_mock_tools(),
+            ExecOptions::default(),
+        )
+        .expect_err("index error");
+        assert!(err.to_string().contains(message), "{err}");
+    }
+}
+
+#[test]
+fn constructs_and_matches_structs_and_enums() {
+    let src = "struct Point { x: Int, y: Int }\nenum Shape { Empty, Dot(Point), Rect(Point, Point) }\nfn area(s: Shape) -> Int {\n  return match s {\n    Shape::Rect(a, b) => (b.x - a.x) * (b.y - a.y),\n    _ => 0,\n  };\n}\nlet origin = Point { y: 0, x: 0 };\nlet shapes = [Shape::Empty, Shape::Dot(origin), Shape::Rect(origin, Point { x: 2, y: 3 })];\nfor s in shapes {\n  match s {\n    Shape::Empty => { print \"empty\"; }\n    Shape::Dot(p) => { print p; }\n    other => { print area(other); }\n  }\n}\nprint shapes[2];\nprint match \"b\" { \"a\" => 1, \"b\" => 2, _ => 3 };\nprint Shape::Dot(origin) == Shape::Dot(Point { x: 0, y: 0 });\n";
+    assert_eq!(
+        printed(src),
+        vec![
+            "empty",
+            "Point { x: 0, y: 0 }",
+            "6",
+            "Shape::Rect(Point { x: 0, y: 0 }, Point { x: 2, y: 3 })",
+            "2",
+            "true",
+        ]
+    );
+}
+
+#[test]
+fn question_mark_returns_none_and_err_early() {
+    let src = "fn half(n: Int) -> Option[Int] {\n  if n / 2 * 2 != n { return None; }\n  return Some(n / 2);\n}\nfn eighth(n: Int) -> Option[Int] {\n  let q = half(half(n)?)?;\n  print \"quarter of {n} is {q}\";\n  return half(q);\n}\nfn check(n: Int) -> Result[Int, String] {\n  if n < 0 { return Err(\"negative\"); }\n  return Ok(n);\n}\nfn sum(a: Int, b: Int) -> Result[Int, String] {\n  return Ok(check(a)? + check(b)?);\n}\nprint eighth(16);\nprint eighth(6);\nprint sum(1, 2);\nprint sum(1, -2);\nprint [Some(\"a\"), None];\n";
+    assert_eq!(
+        printed(src),
+        vec![
+            "quarter of 16 is 4",
+            "Some(2)",
+            "None",
+            "Ok(3)",
+            "Err(\"negative\")",
+            "[Some(\"a\"), None]",
+        ]
+    );
+}
+
+#[test]
+fn assignments_update_the_nearest_mutable_binding() {
+    let src = "let mut total = 0;\nlet mut i = 0;\nwhile i < 4 {\n  let step = i * 10;\n  total = total + step;\n  i = i + 1;\n}\nprint total;\nfn count(xs: Array[Int]) -> Int {\n  let mut n = 0;\n  for x in xs {\n    if x > 1 {\n      n = n + 1;\n    }\n  }\n  return n;\n}\nprint count([1, 2, 3]);\nprint i;\n";
+    assert_eq!(printed(src), vec!["60", "2", "4"]);
+}
+
+fn run_error(src: &str) -> String {
+    let mut sources = axiom_compiler::span::SourceMap::default();
+    let file = sources.add("main.ax", src);
+    let typed =
+        typecheck(axiom_compiler::parser::parse_file(file, sources.source(file)).expect("parse"))
+            .expect("typecheck");
+    execute_with_defaults(
+        typed,
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect_err("should fail")
+    .render(&sources)
+}
+
+#[test]
+fn integer_overflow_and_division_by_zero_are_located_errors() {
+    assert_eq!(
+        run_error("let big = 9223372036854775807;\nprint big - 1;\nprint big + 1;\n"),
+        "main.ax:3:7: integer overflow: 9223372036854775807 + 1"
+    );
+    assert_eq!(
+        run_error("let zero = 0;\nlet n = 7;\nprint n / zero;\n"),
+        "main.ax:3:7: division by zero"
+    );
+    assert_eq!(
+        run_error("let min = -9223372036854775807 - 1;\nprint min / -1;\n"),
+        "main.ax:2:7: integer overflow: -9223372036854775808 / -1"
+    );
+    assert_eq!(
+        run_error("fn neg(x: Int) -> Int {\n  return -x;\n}\nlet min = -9223372036854775807 - 1;\nprint neg(min);\n"),
+        "main.ax:2:10: integer overflow: -(-9223372036854775808)"
+    );
+    assert_eq!(
+        run_error("let big = 3037000500;\nlet xs = [big * big];\n"),
+        "main.ax:2:11: integer overflow: 3037000500 * 3037000500"
+    );
+}
@@ -0,0 +1,78 @@
+use axiom_runtime::json::{parse, quote, Json};
+
+#[test]
+fn parses_nested_documents() {
+    let doc =
+        parse(" {\"a\": [1, -2.5e1, true, null], \"b\": {\"c\": \"x\\ny\\u00e9\\ud83d\\ude00\"}} ")
+            .expect("valid JSON");
+    assert_eq!(
+        doc,
+        Json::Object(vec![
+            (
+                "a".to_string(),
+                Json::Array(vec![
+                    Json::Int(1),
+                    Json::Float(-25.0),
+                    Json::Bool(true),
+                    Json::Null
+                ])
+            ),
+            (
+                "b".to_string(),
+                Json::Object(vec![("c".to_string(), Json::String("x\nyé😀".to_string()))])
+            ),
+        ])
+    );
+    assert_eq!(
+        parse("9223372036854775808").expect("big"),
+        Json::Float(9.223372036854776e18)
+    );
+}
+
+#[test]
+fn rejects_malformed_documents() {
+    for (text, error) in [
+        ("", "unexpected end of input at byte 0"),
+        ("{\"a\" 1}", "expected `:` at byte 5"),
+        ("[1, 2", "expected `,` at byte 5"),
+        ("[1,]", "unexpected character at byte 3"),
+        ("{a: 1}", "expected object key at byte 1"),
+        ("01", "leading zeros are not allowed at byte 2"),
+        ("\"tab\there\"", "control character in string at byte 4"),
+        ("\"\\ud800\"", "unpaired surrogate at byte 7"),
+        ("{} {}", "trailing characters after JSON value at byte 3"),
+        ("nul", "invalid literal at byte 0"),
+    ] {
+        assert_eq!(parse(text).expect_err(text), error, "input: {text}");
+    }
+    let deep = "[".repeat(100) + &"]".repeat(100);
+    assert!(parse(&deep)
+        .expect_err("too deep")
+        .contains("nested too deeply"));
+}
+
+#[test]
+fn quoted_strings_round_trip() {
+    let s = "quote\" backslash\\ newline\n bell\u{7} é";
+    assert_eq!(
+        quote(s),
+        "\"quote\\\" backslash\\\\ newline\\n bell\\u0007 é\""
+    );
+    assert_eq!(
+        parse(&quote(s)).expect("valid"),
+        Json::String(s.to_string())
+    );
+}
+
+#[test]
+fn canonical_form_sorts_keys_and_drops_whitespace() {
+    let a = parse(r#"{ "b": [1, 2.5, {"y": null, "x": false}], "a": "\u00e9\t" }"#).expect("a");
+    let b = parse(r#"{"a":"é\t","b":[1,2.5,{"x":false,"y":null}]}"#).expect("b");
+    assert_eq!(
+        a.canonical(),
+        r#"{"a":"é\t","b":[1,2.5,{"x":false,"y":null}]}"#
+    );
+    assert_eq!(a.canonical(), b.canonical());
+    assert_eq!(Json::Float(f64::NAN).canonical(), "null");
+    assert_eq!(Json::Float(6.0).canonical(), "6.0");
+}
@@ -0,0 +1,136 @@
+use axiom_compiler::{parser::parse_program, typecheck::typecheck};
+use axiom_runtime::{
+    capability::{Budget, CapabilityKind},
+    policy::parse_grants,
+    runtime::{execute_logged, ExecOptions},
+    tools::ToolRegistry,
+};
+use std::collections::BTreeSet;
+
+const GRANTS: &str = r#"
+# Grants for the search agent.
+[toolCap]
+kind = "tool"
+tools = ["MockEcho", "Search"]   # no deletes
+max_calls = 10
+max_timeout_ms = 5_000
+
+[docs]
+kind = "fs"
+paths = ["/srv/docs", "/srv/shared dir"]
+expires_at_ms = 60000
+
+[web]
+kind = "net"
+hosts = ["*.example.com"]
+
+[notes]
+kind = "memory"
+namespaces = []
+"#;
+
+#[test]
+fn parses_named_capabilities_with_kinds_scopes_and_budgets() {
+    let caps = parse_grants(GRANTS).expect("parse grants");
+    let mut names = caps.keys().map(String::as_str).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["docs", "notes", "toolCap", "web"]);
+
+    let tool = &caps["toolCap"];
+    assert_eq!(tool.kind(), CapabilityKind::Tool);
+    assert!(tool.can_use_tool("Search"));
+    assert!(!tool.can_use_tool("Delete"));
+    assert_eq!(
+        tool.remaining(),
+        Budget::unlimited().with_calls(10).with_timeout_ms(5000)
+    );
+
+    let docs = &caps["docs"];
+    assert!(docs.can_read_path("/srv/shared dir/a.txt"));
+    assert!(!docs.can_read_path("/etc/passwd"));
+    assert_eq!(docs.expires_at_ms(), Some(60000));
+    assert!(caps["web"].can_reach_host("api.example.com"));
+    assert_eq!(caps["notes"].scope().namespaces, Some(BTreeSet::new()));
+}
+
+#[test]
+fn grant_errors_name_the_line() {
+    for (text, message) in [
+        (
+            "kind = \"tool\"\n",
+            "line 1: kind must be inside a [capability] table",
+        ),
+        (
+            "[a]\nkind = \"disk\"\n",
+            "line 2: unknown capability kind: disk",
+        ),
+        (
+            "[a]\ntools = [\"X\"]\n",
+            "line 2: tools must come after kind",
+        ),
+        (
+            "[a]\nkind = \"fs\"\nmax_calls = 3\n",
+            "line 3: max_calls does not apply to fs capabilities",
+        ),
+        (
+            "[a]\nkind = \"tool\"\ncolor = \"red\"\n",
+            "line 3: unknown key: color",
+        ),
+        (
+            "[a]\nkind = \"tool\"\nmax_calls = -1\n",
+            "line 3: expected a string, integer or array",
+        ),
+        (
+            "[a]\nkind = \"tool\"\ntools = \"X\"\n",
+            "line 3: tools must be an array of strings",
+        ),
+        (
+            "[a]\nkind = \"tool\"\n[a]\n",
+            "line 3: [a] is declared more than once",
+        ),
+        (
+            "[a]\nkind = \"tool\"\ntools = [\"X\"\n",
+            "line 3: expected `]`",
+        ),
+        ("\n[a]\n", "line 2: [a] has no kind"),
+    ] {
+        match parse_grants(text) {
+            Ok(_) => panic!("{text:?} should not parse"),
+            Err(e) => assert_eq!(e, message, "{text:?}"),
+        }
+    }
+}
+
+#[test]
+fn runs_fail_up_front_when_the_manifest_is_not_granted() {
+    let src = "print \"started\";\ntool MockEcho input { message: String } output { echo: String } cap toolCap;\ncall MockEcho { message: \"hi\" } using toolCap timeout 10;\n";
+    let run = |grants: &str| {
+        let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+        let caps = parse_grants(grants).expect("parse grants");
+        execute_logged(
+            typed,
+            &caps,
+            &ToolRegistry::with_mock_tools(),
+            ExecOptions::default(),
+        )
+    };
+
+    let (log, result) = run("[timeCap]\nkind = \"time\"\n");
+    assert_eq!(
+        result.expect_err("toolCap missing").to_string(),
+        "missing capability: the program requires toolCap, which is not granted"
+    );
+    // Nothing ran, not even the leading print.
+    assert!(log.events.is_empty());
+
+    let (log, result) = run("[toolCap]\nkind = \"net\"\n");
+    assert_eq!(
+        result.expect_err("wrong kind").to_string(),
+        "invalid capability: toolCap is used for tool calls but has kind net"
+    );
+    assert!(log.events.is_empty());
+
+    let (log, result) = run("[toolCap]\nkind = \"tool\"\n");
+    result.expect("granted");
+    assert!(!log.events.is_empty());
+}
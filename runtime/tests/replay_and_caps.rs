@@ -1,10 +1,40 @@
-use axiom_compiler::{parser::parse_program, typecheck::typecheck};
+use axiom_compiler::{
+    parser::{parse_file, parse_program},
+    span::SourceMap,
+    typecheck::typecheck,
+};
 use axiom_runtime::{
-    replay::ReplayLog,
-    runtime::{execute_with_defaults, ExecOptions},
+    capability::{capabilities_with, default_capabilities, CapabilityKind},
+    replay::{Event, ReplayLog},
+    runtime::{execute, execute_with_defaults, ExecOptions},
     tools::ToolRegistry,
 };
 
+fn run_with_mock_tools(src: &str) -> ReplayLog {
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    execute_with_defaults(
+        typed,
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect("execute")
+}
+
+/// Input and logged output of the first tool call in `log`.
+fn tool_call_io(log: &ReplayLog) -> (String, String) {
+    log.events
+        .iter()
+        .find_map(|e| match e {
+            Event::ToolCall { input, output, .. } => Some((input.clone(), output.clone())),
+            _ => None,
+        })
+        .expect("tool call event")
+}
+
+fn first_tool_call(src: &str) -> (String, String) {
+    tool_call_io(&run_with_mock_tools(src))
+}
+
 #[test]
 fn replay_hash_is_stable_for_same_program() {
     let src = "tool MockEcho input { value: Int } output { echo: String } cap toolCap;\nlet x = 1 + 2;\nprint x;\ncall MockEcho { value: x } using toolCap timeout 1000;\n";
@@ -55,3 +85,429 @@ fn timeout_must_be_non_zero() {
     .expect_err("expected timeout failure");
     assert!(err.to_string().contains("zero timeout"));
 }
+
+#[test]
+fn runtime_errors_carry_statement_location() {
+    let mut sources = SourceMap::default();
+    let file = sources.add(
+        "main.ax",
+        "tool MockEcho input { value: Int } output { echo: String } cap toolCap;\nlet x = 1;\n  call MockEcho { value: x } using toolCap timeout 0;\n",
+    );
+    let typed =
+        typecheck(parse_file(file, sources.source(file)).expect("parse")).expect("typecheck");
+    let err = execute_with_defaults(
+        typed,
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect_err("expected timeout failure");
+    assert_eq!(
+        err.render(&sources),
+        "main.ax:3:3: invalid timeout: tool call MockEcho has zero timeout"
+    );
+}
+
+#[test]
+fn tool_input_strings_are_json_escaped() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nlet who = \"\\\"quoted\\\" \\\\ {1 + 1}\";\ncall MockEcho { message: \"say {who}\\n\" } using toolCap timeout 1000;\n";
+    let (input, _) = first_tool_call(src);
+    assert_eq!(input, r#"{"message":"say \"quoted\" \\ 2\n"}"#);
+}
+
+#[test]
+fn tool_calls_inside_functions_are_logged() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\nfn fetch(q: String) !tool[toolCap] {\n  call MockEcho { message: q } using toolCap timeout 10;\n}\nfetch(\"a\");\nfetch(\"b\");\n";
+    let log = run_with_mock_tools(src);
+    let inputs = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::ToolCall { input, .. } => Some(input.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(inputs, vec![r#"{"message":"a"}"#, r#"{"message":"b"}"#]);
+}
+
+#[test]
+fn tool_inputs_encode_floats_bytes_and_unit() {
+    let src = "tool MockEcho input { score: Float, blob: Bytes, nothing: Unit } output { echo: String } cap toolCap;\ncall MockEcho { score: 0.5, blob: hex\"fffe\", nothing: () } using toolCap timeout 10;\n";
+    let log = run_with_mock_tools(src);
+    let (input, _) = tool_call_io(&log);
+    assert_eq!(input, r#"{"blob":"//4=","nothing":null,"score":0.5}"#);
+    let replayed = ReplayLog::from_text(&log.to_text()).expect("parse replay");
+    assert_eq!(replayed.digest_hex(), log.digest_hex());
+}
+
+#[test]
+fn tool_inputs_encode_collections_in_key_order() {
+    let src = "tool MockEcho input { tags: Set[String], scores: Map[String, Int], ids: Array[Int] } output { echo: String } cap toolCap;\ncall MockEcho { tags: Set { \"b\", \"a\" }, scores: Map { \"z\": 1, \"m\": 2 }, ids: [2, 1] } using toolCap timeout 10;\n";
+    let (input, _) = first_tool_call(src);
+    assert_eq!(
+        input,
+        r#"{"ids":[2,1],"scores":{"m":2,"z":1},"tags":["a","b"]}"#
+    );
+}
+
+#[test]
+fn tool_inputs_encode_structs_and_enums() {
+    let src = "struct Query { text: String, limit: Int }\nenum Mode { Fast, Deep(Int) }\ntool MockEcho input { q: Query, a: Mode, b: Mode } output { echo: String } cap toolCap;\ncall MockEcho { q: Query { limit: 5, text: \"x\" }, a: Mode::Fast, b: Mode::Deep(2) } using toolCap timeout 10;\n";
+    let (input, _) = first_tool_call(src);
+    assert_eq!(
+        input,
+        r#"{"a":"Fast","b":{"Deep":[2]},"q":{"limit":5,"text":"x"}}"#
+    );
+}
+
+#[test]
+fn try_call_hands_tool_failures_to_the_program() {
+    let src = "tool MockEcho input { message: String } output { echo: String } cap toolCap;\ntool Flaky input { message: String } output { echo: String } cap toolCap;\nfn ask(name: String) -> Result[String, ToolError] !tool[toolCap] {\n  if name == \"echo\" {\n    return Ok((try call MockEcho { message: \"hi\" } using toolCap timeout 10)?.echo);\n  }\n  return Ok((try call Flaky { message: \"hi\" } using toolCap timeout 10)?.echo);\n}\nfor name in [\"echo\", \"flaky\"] {\n  match ask(name) {\n    Ok(out) => { print out; }\n    Err(e) => { print \"{e.tool} failed: {e.message}\"; }\n  }\n}\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::with_mock_tools();
+    tools.register(
+        "Flaky",
+        Box::new(|_| Err("service unavailable".to_string())),
+    );
+    let log = execute_with_defaults(typed, &tools, ExecOptions::default()).expect("execute");
+    let printed = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Print { value } => Some(value.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        printed,
+        vec![
+            r#"{"message":"hi"}"#,
+            "Flaky failed: tool execution failed: service unavailable",
+        ]
+    );
+    assert!(log.events.iter().any(|e| matches!(
+        e,
+        Event::ToolError { tool, error, .. }
+            if tool == "Flaky" && error == "tool execution failed: service unavailable"
+    )));
+    let replayed = ReplayLog::from_text(&log.to_text()).expect("parse replay");
+    assert_eq!(replayed.digest_hex(), log.digest_hex());
+}
+
+#[test]
+fn call_results_are_decoded_into_typed_records() {
+    let src = "struct Hit { url: String, score: Float }\nenum Source { Web, Cache(Int) }\ntool Search input { q: String } output { hits: Array[Hit], total: Int, next: Option[String], source: Source } cap toolCap;\nlet r = call Search { q: \"axiom\" } using toolCap timeout 100;\nprint r.hits[1].url;\nprint r.total * 2;\nprint r.next;\nprint r.source;\nprint r;\nlet e = call MockEcho { message: \"a\\\"b\" } using toolCap timeout 10;\nprint e.echo;\n";
+    let src = format!(
+        "tool MockEcho input {{ message: String }} output {{ echo: String }} cap toolCap;\n{src}"
+    );
+    let typed = typecheck(parse_program(&src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::with_mock_tools();
+    tools.register(
+        "Search",
+        Box::new(|_| {
+            Ok(r#"{"total": 2, "hits": [{"url": "a", "score": 1}, {"url": "b\u00e9", "score": 0.5}], "next": null, "source": {"Cache": [7]}}"#.to_string())
+        }),
+    );
+    let log = execute_with_defaults(typed, &tools, ExecOptions::default()).expect("execute");
+    let printed = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Print { value } => Some(value.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        printed,
+        vec![
+            "bé",
+            "4",
+            "None",
+            "Source::Cache(7)",
+            "{ hits: [Hit { url: \"a\", score: 1.0 }, Hit { url: \"bé\", score: 0.5 }], next: None, source: Source::Cache(7), total: 2 }",
+            r#"{"message":"a\"b"}"#,
+        ]
+    );
+}
+
+#[test]
+fn mistyped_tool_output_fails_the_call() {
+    let src = "tool Search input { q: String } output { total: Int } cap toolCap;\nlet r = try call Search { q: \"x\" } using toolCap timeout 10;\nmatch r {\n  Ok(out) => { print out.total; }\n  Err(e) => { print e.message; }\n}\nlet total = (call Search { q: \"x\" } using toolCap timeout 10).total;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::default();
+    tools.register(
+        "Search",
+        Box::new(|_| Ok(r#"{"total": "many"}"#.to_string())),
+    );
+    let err =
+        execute_with_defaults(typed, &tools, ExecOptions::default()).expect_err("untyped output");
+    assert_eq!(
+        err.to_string(),
+        "tool validation failed: $.total: expected Int, got string"
+    );
+}
+
+fn output_error(output: &str, strict: bool) -> String {
+    let src = "struct Hit { url: String, score: Float }\nenum Source { Web, Cache(Int) }\ntool Search input { q: String } output { hits: Array[Hit], meta: { source: Source, tags: Map[String, Option[Int]] } } cap toolCap;\ncall Search { q: \"x\" } using toolCap timeout 10;\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::default();
+    let output = output.to_string();
+    tools.register("Search", Box::new(move |_| Ok(output.clone())));
+    let opts = ExecOptions {
+        strict_tool_outputs: strict,
+        ..ExecOptions::default()
+    };
+    match execute_with_defaults(typed, &tools, opts) {
+        Ok(_) => "ok".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn tool_output_errors_name_the_json_path() {
+    let meta = r#""meta": {"source": "Web", "tags": {}}"#;
+    for (output, error) in [
+        (
+            format!(r#"{{"hits": [{{"url": "a", "score": 1}}, {{"url": 2, "score": 1}}], {meta}}}"#),
+            "$.hits[1].url: expected String, got number",
+        ),
+        (
+            format!(r#"{{"hits": [{{"url": "a"}}], {meta}}}"#),
+            "$.hits[0]: missing field score",
+        ),
+        (
+            r#"{"hits": [], "meta": {"source": {"Cache": ["7"]}, "tags": {}}}"#.to_string(),
+            "$.meta.source.Cache[0]: expected Int, got string",
+        ),
+        (
+            r#"{"hits": [], "meta": {"source": "Disk", "tags": {}}}"#.to_string(),
+            "$.meta.source: enum Source has no variant Disk",
+        ),
+        (
+            r#"{"hits": [], "meta": {"source": "Web", "tags": {"a": 1, "b\"": true}}}"#.to_string(),
+            r#"$.meta.tags["b\""]: expected Int, got boolean"#,
+        ),
+        (
+            format!(r#"{{"hits": [], {meta}, "hits": []}}"#),
+            r#"$: duplicate key "hits""#,
+        ),
+        (
+            r#"["hits", "meta"]"#.to_string(),
+            "$: expected { hits: Array[Hit], meta: { source: Source, tags: Map[String, Option[Int]] } }, got array",
+        ),
+        (
+            r#"{"hits": [], "meta": {"source": "Web", "tags": {}}"#.to_string(),
+            "invalid JSON output: expected `,` at byte 50",
+        ),
+        (
+            r#"{"hits": [], "note": "\"meta\":", "x": {"meta": 1}}"#.to_string(),
+            "$: missing field meta",
+        ),
+    ] {
+        assert_eq!(
+            output_error(&output, false),
+            format!("tool validation failed: {error}"),
+            "output: {output}"
+        );
+    }
+}
+
+#[test]
+fn strict_mode_rejects_undeclared_output_fields() {
+    let extra =
+        r#"{"hits": [{"url": "a", "score": 1, "rank": 1}], "meta": {"source": "Web", "tags": {}}}"#;
+    assert_eq!(output_error(extra, false), "ok");
+    assert_eq!(
+        output_error(extra, true),
+        r#"tool validation failed: $.hits[0]: unknown field "rank""#
+    );
+    let top = r#"{"hits": [], "meta": {"source": "Web", "tags": {}}, "debug": true}"#;
+    assert_eq!(
+        output_error(top, true),
+        r#"tool validation failed: $: unknown field "debug""#
+    );
+}
+
+fn tool_events(src: &str, output: &'static str) -> ReplayLog {
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::default();
+    tools.register("Search", Box::new(move |_| Ok(output.to_string())));
+    execute_with_defaults(typed, &tools, ExecOptions::default()).expect("execute")
+}
+
+#[test]
+fn same_logical_tool_io_hashes_the_same() {
+    let decl = "struct Q { text: String, limit: Int }\ntool Search input { q: Q, tag: String } output { n: Int, s: String } cap toolCap;\n";
+    let a = tool_events(
+        &format!("{decl}call Search {{ q: Q {{ text: \"a|b\", limit: 1 }}, tag: \"t\" }} using toolCap timeout 10;\n"),
+        r#"{"n": 1, "s": "\u0041\/"}"#,
+    );
+    let b = tool_events(
+        &format!("{decl}call Search {{ tag: \"t\", q: Q {{ limit: 1, text: \"a|b\" }} }} using toolCap timeout 10;\n"),
+        r#"{ "s":"A/","n":1 }"#,
+    );
+    let Some(Event::ToolCall {
+        input,
+        output,
+        output_hash,
+        ..
+    }) = a
+        .events
+        .iter()
+        .find(|e| matches!(e, Event::ToolCall { .. }))
+    else {
+        panic!("expected a tool call event");
+    };
+    assert_eq!(input, r#"{"q":{"limit":1,"text":"a|b"},"tag":"t"}"#);
+    assert_eq!(output, r#"{"n":1,"s":"A/"}"#);
+    assert_eq!(a.to_text(), b.to_text());
+    assert_eq!(a.digest_hex(), b.digest_hex());
+    let Some(Event::ToolCall {
+        output_hash: other, ..
+    }) = b
+        .events
+        .iter()
+        .find(|e| matches!(e, Event::ToolCall { .. }))
+    else {
+        panic!("expected a tool call event");
+    };
+    assert_eq!(output_hash, other);
+    let replayed = ReplayLog::from_text(&a.to_text()).expect("parse replay");
+    assert_eq!(replayed.to_text(), a.to_text());
+}
+
+#[test]
+fn tool_input_strings_cannot_inject_fields() {
+    let log = tool_events(
+        "tool Search input { q: String, admin: Bool } output { n: Int } cap toolCap;\ncall Search { q: \"x\\\",\\\"admin\\\":true,\\\"\\0\", admin: false } using toolCap timeout 10;\n",
+        r#"{"n": 0}"#,
+    );
+    let (input, _) = tool_call_io(&log);
+    assert_eq!(
+        input,
+        r#"{"admin":false,"q":"x\",\"admin\":true,\"\u0000"}"#
+    );
+    let parsed = axiom_runtime::json::parse(&input).expect("valid JSON");
+    assert_eq!(parsed.canonical(), input);
+}
+
+#[test]
+fn time_and_random_builtins_are_synthetic_and_logged() {
+    let src = "tool MockEcho input { value: Int } output { echo: String } cap toolCap;\nprint now();\ncall MockEcho { value: 1 } using toolCap timeout 250;\ncall MockEcho { value: 2 } using toolCap timeout 100;\nprint now();\nprint random() == random();\n";
+    let log = run_with_mock_tools(src);
+    let printed = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Print { value } => Some(value.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(printed, vec!["0", "350", "false"]);
+    // Every TIME event is a reading of the clock, whether by `now()` or
+    // after a call advanced it.
+    let times = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Time { millis } => Some(*millis),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(times, vec![0, 250, 350, 350]);
+    assert_eq!(log.digest_hex(), run_with_mock_tools(src).digest_hex());
+}
+
+#[test]
+fn effects_need_a_capability_of_their_kind() {
+    let src = "print read_file(\"notes.txt\");\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let err = execute(
+        typed,
+        &default_capabilities(),
+        &ToolRegistry::with_mock_tools(),
+        ExecOptions::default(),
+    )
+    .expect_err("fs is not granted by default");
+    assert_eq!(
+        err.to_string(),
+        "missing capability: fs effect requested but no fs capability supplied"
+    );
+}
+
+#[test]
+fn io_builtins_run_through_the_tool_registry_and_are_logged() {
+    let src = "print read_file(\"notes.txt\");\nprint http_get(\"http://example.com/\");\n";
+    let run = |tools: &ToolRegistry| {
+        let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+        execute(
+            typed,
+            &capabilities_with(&[CapabilityKind::Fs, CapabilityKind::Net]),
+            tools,
+            ExecOptions::default(),
+        )
+    };
+    let mut tools = ToolRegistry::default();
+    tools.register("read_file", Box::new(|_| Ok("hello from disk".to_string())));
+    tools.register("http_get", Box::new(|_| Ok("<html>".to_string())));
+    let log = run(&tools).expect("execute");
+    let calls = log
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            Event::ToolCall {
+                tool,
+                input,
+                output,
+                source,
+                ..
+            } => Some((
+                tool.as_str(),
+                input.as_str(),
+                output.as_str(),
+                source.as_str(),
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        vec![
+            (
+                "read_file",
+                r#"{"path":"notes.txt"}"#,
+                r#""hello from disk""#,
+                "builtin"
+            ),
+            (
+                "http_get",
+                r#"{"url":"http://example.com/"}"#,
+                r#""<html>""#,
+                "builtin"
+            ),
+        ]
+    );
+    let replayed = ReplayLog::from_text(&log.to_text()).expect("parse replay");
+    assert_eq!(replayed.digest_hex(), log.digest_hex());
+
+    let err = run(&ToolRegistry::with_mock_tools()).expect_err("no read_file tool");
+    assert_eq!(
+        err.to_string(),
+        "tool execution failed: unknown tool: read_file"
+    );
+}
+
+#[test]
+fn bytes_map_keys_round_trip_through_tools() {
+    let src = "tool Echo input { m: Map[Bytes, Int] } output { m: Map[Bytes, Int] } cap toolCap;\nlet r = call Echo { m: Map { hex\"ff00\": 1, hex\"\": 2 } } using toolCap timeout 10;\nprint r.m[hex\"ff00\"];\n";
+    let typed = typecheck(parse_program(src).expect("parse")).expect("typecheck");
+    let mut tools = ToolRegistry::default();
+    tools.register("Echo", Box::new(Ok));
+    let log = execute_with_defaults(typed, &tools, ExecOptions::default()).expect("execute");
+    let (input, output) = tool_call_io(&log);
+    assert_eq!(input, r#"{"m":{"":2,"/wA=":1}}"#);
+    assert_eq!(output, input);
+    assert!(log
+        .events
+        .iter()
+        .any(|e| matches!(e, Event::Print { value } if value == "1")));
+}
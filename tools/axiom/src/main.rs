@@ -1,7 +1,16 @@
-use axiom_compiler::{manifest::render_manifest, parser::parse_program, typecheck::typecheck};
+use axiom_compiler::{
+    diagnostic::codes,
+    manifest::render_manifest,
+    parser::parse_file,
+    span::SourceMap,
+    typecheck::{typecheck, TypedProgram},
+    EffectKind,
+};
 use axiom_runtime::{
+    capability::default_capabilities,
+    policy::parse_grants,
     replay::ReplayLog,
-    runtime::{execute_with_defaults, ExecOptions},
+    runtime::{execute_logged, ExecOptions},
     tools::ToolRegistry,
 };
 use std::{env, fs, path::PathBuf};
@@ -18,26 +27,37 @@ fn main() -> Result<(), String> {
                 .windows(2)
                 .find(|w| w[0] == "--replay-out")
                 .map(|w| PathBuf::from(&w[1]));
-            let src = fs::read_to_string(file).map_err(|e| e.to_string())?;
-            let typed = typecheck(parse_program(&src).map_err(|e| e.to_string())?)
-                .map_err(|e| e.to_string())?;
-            let log = execute_with_defaults(
-                typed,
-                &ToolRegistry::with_mock_tools(),
-                ExecOptions::default(),
-            )
-            .map_err(|e| e.to_string())?;
-            let digest = log.digest_hex();
-            println!("replay_hash={digest}");
+            let opts = ExecOptions {
+                strict_tool_outputs: args.iter().any(|a| a == "--strict-tool-outputs"),
+                ..ExecOptions::default()
+            };
+            let (typed, sources) = compile(file)?;
+            if args.iter().any(|a| a == "--require-pure") && !typed.effect.is_pure() {
+                return Err(format!(
+                    "{file} is required to be pure, but has effect `{}`",
+                    typed.effect
+                ));
+            }
+            let caps = match args.windows(2).find(|w| w[0] == "--caps") {
+                Some(w) => {
+                    let text = fs::read_to_string(&w[1]).map_err(|e| format!("{}: {e}", w[1]))?;
+                    parse_grants(&text).map_err(|e| format!("{}: {e}", w[1]))?
+                }
+                None => default_capabilities(),
+            };
+            let (log, result) =
+                execute_logged(typed, &caps, &ToolRegistry::with_mock_tools(), opts);
+            // Written even for failed runs, which are the ones worth auditing.
             if let Some(path) = replay_out {
                 fs::write(path, log.to_text()).map_err(|e| e.to_string())?;
             }
+            result.map_err(|e| e.render(&sources))?;
+            let digest = log.digest_hex();
+            println!("replay_hash={digest}");
         }
         "manifest" => {
             let file = args.get(2).ok_or_else(help)?;
-            let src = fs::read_to_string(file).map_err(|e| e.to_string())?;
-            let typed = typecheck(parse_program(&src).map_err(|e| e.to_string())?)
-                .map_err(|e| e.to_string())?;
+            let (typed, _) = compile(file)?;
             print!("{}", render_manifest(&typed));
         }
         "replay-check" => {
@@ -54,15 +74,11 @@ fn main() -> Result<(), String> {
         }
         "lint" => {
             let file = args.get(2).ok_or_else(help)?;
-            let src = fs::read_to_string(file).map_err(|e| e.to_string())?;
-            let typed = typecheck(parse_program(&src).map_err(|e| e.to_string())?)
-                .map_err(|e| e.to_string())?;
-            if src.contains(" timeout 0;") {
+            let (typed, _) = compile(file)?;
+            if typed.warnings.iter().any(|w| w.code == codes::ZERO_TIMEOUT) {
                 return Err("tool calls must use timeout > 0".to_string());
             }
-            if typed.effect == axiom_compiler::Effect::Tool
-                && typed.manifest.required_caps.is_empty()
-            {
+            if typed.effect.contains(EffectKind::Tool) && typed.manifest.required_caps.is_empty() {
                 return Err("tool effects require manifest capabilities".to_string());
             }
             println!("lint ok");
@@ -83,6 +99,26 @@ fn main() -> Result<(), String> {
     Ok(())
 }
 
+fn compile(path: &str) -> Result<(TypedProgram, SourceMap), String> {
+    let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut sources = SourceMap::default();
+    let file = sources.add(path, src);
+    let typed = parse_file(file, sources.source(file))
+        .and_then(typecheck)
+        .map_err(|e| {
+            eprint!("{}", e.render(&sources));
+            let count = e.errors().count();
+            format!(
+                "could not compile {path} due to {count} previous error{}",
+                if count == 1 { "" } else { "s" }
+            )
+        })?;
+    for warning in &typed.warnings {
+        eprint!("{}", warning.render(&sources));
+    }
+    Ok((typed, sources))
+}
+
 fn help() -> String {
     "usage: axiom <run|manifest|replay-check|fmt|lint|test|bench|pkg> ...".to_string()
 }